@@ -24,42 +24,62 @@ use odin_actor::prelude::*;
 use odin_actor::tokio_kanal::{ActorSystem,ActorSystemHandle,Actor,ActorHandle,AbortHandle,JoinHandle,spawn, MpscSender,MpscReceiver,create_mpsc_sender_receiver};
 use reqwest::{Client};
 use crate::*;
-use crate::ws::{WsStream,WsCmd, init_websocket, run_websocket, send_ws_text_msg, read_next_ws_msg};
+use crate::storage::SentinelStorage;
+use crate::position::{PositionConfig,detect_relocation};
+use crate::confidence::CloudWeighting;
+use crate::privacy::{PrivacyFilter,PrivacyPolicy};
+use crate::ws::{WsStream,WsCmd, init_websocket, run_websocket, send_ws_text_msg, send_join, send_leave, read_next_ws_msg};
 
-const PING_TIMER: i64 = 1;
+const CHECKPOINT_TIMER: i64 = 1;
 
-macro_rules! define_update {
-    ($f:ident <- $r:ty) => { paste!{
-        async fn [<update_ $f>] (&mut self, rec: $r)->Result<()> {
-            let sentinel = self.sentinels.sentinel_of(&rec.device_id)?;
+// timer ids are <base> + source index
+const PING_TIMER_BASE: i64 = 100;
+const REFRESH_TIMER_BASE: i64 = 200;
 
-            // only convert if there are clients for it
-            let json = if !self.json_update_callbacks.is_empty() { Some(Arc::new(serde_json::to_string(&rec)?)) } else { None };
-            let update_rec = if !self.update_callbacks.is_empty() { Some( Arc::<SentinelUpdate>::new(rec.clone().into())) } else { None };
-
-            sort_in_record(&mut sentinel.$f, rec); // this consumes the rec
+/// the connection state for each Delphire backend we get data from
+struct SourceConnection {
+    source_id: SourceId,
+    config: Arc<SentinelConfig>,
+    init_completed: bool, // set once the init task for this source has finished (successful or not)
 
-            if let Some(json) = json { self.json_update_callbacks.trigger(json).await; } // we don't propagate errors here
-            if let Some(update_rec) = update_rec { self.update_callbacks.trigger(update_rec).await; }
+    ping_timer: Option<AbortHandle>,
+    refresh_timer: Option<AbortHandle>,
+    websocket_task: Option<JoinHandle<Result<()>>>,
+    ws_write: Option<SplitSink<WsStream,Message>>,  // the sender end of the channel to send commands to the acquisition task
+}
 
-            Ok(())
+impl SourceConnection {
+    fn new (source_id: SourceId, config: SentinelConfig)->Self {
+        SourceConnection {
+            source_id,
+            config: Arc::new(config),
+            init_completed: false,
+            ping_timer: None,
+            refresh_timer: None,
+            websocket_task: None,
+            ws_write: None,
         }
-    }}
+    }
 }
 
 /// the actor state
 pub struct SentinelConnector {
-    config: Arc<SentinelConfig>,
-    sentinels: SentinelStore,
+    sources: Vec<SourceConnection>,
+    sentinels: SentinelStore, // merged from all sources
+    initialized: bool, // set once we triggered the init callbacks, either from a checkpoint or after all sources are initialized
+
+    checkpoint: Option<CheckpointConfig>,
+    checkpoint_timer: Option<AbortHandle>,
 
     last_recv_epoch: Arc<AtomicU64>, // in millis
 
-    ping_timer: Option<AbortHandle>,
-    websocket_task: Option<JoinHandle<Result<()>>>,
-    ws_write: Option<SplitSink<WsStream,Message>>,  // the sender end of the channel to send commands to the acquisition task
+    position_config: PositionConfig, // used to detect device relocations
+    cloud_weighting: CloudWeighting, // used to annotate fire/smoke records with cloud cover adjusted probabilities
+    privacy: PrivacyFilter, // applied to records before they get into our SentinelStore (but after they are stored)
 
     //-- callbacks 
-    init_callbacks: CallbackList<()>,  // triggered when sentinels are initialized
+    init_callbacks: CallbackList<()>,  // triggered when sentinels of all sources are initialized
+    device_callbacks: CallbackList<DeviceEvent>, // triggered when devices are added, retired or relocated after initialization
 
     //-- callbacks triggered upon receiving a new record
     update_callbacks: CallbackList<Arc<SentinelUpdate>>,  // triggered by new SensorRecords
@@ -67,32 +87,247 @@ pub struct SentinelConnector {
 }
 
 impl SentinelConnector {
+    /// create a connector for a single Delphire backend. Device ids are not namespaced
     pub fn new (config: SentinelConfig)->Self {
+        let checkpoint = config.checkpoint.clone();
+        Self::new_with_sources( vec![ SourceConnection::new( String::new(), config) ], checkpoint)
+    }
+
+    /// create a connector that merges several Delphire backends into one SentinelStore. Device ids
+    /// are namespaced with the respective source id
+    pub fn new_multi (config: MultiSentinelConfig)->Self {
+        let sources = config.sources.into_iter().map( |src| SourceConnection::new( src.id, src.config)).collect();
+        Self::new_with_sources( sources, config.checkpoint)
+    }
+
+    /// persist all records we receive in the given storage
+    pub fn with_storage (mut self, storage: Box<dyn SentinelStorage>)->Self {
+        self.sentinels.set_storage( storage);
+        self
+    }
+
+    /// filter person records and their images before they are published. Our storage still gets all records
+    pub fn with_privacy_policy (mut self, policy: PrivacyPolicy)->Self {
+        self.privacy = PrivacyFilter::new( policy);
+        self
+    }
+
+    /// use a non-default cloud cover weighting curve for fire/smoke probabilities
+    pub fn with_cloud_weighting (mut self, cloud_weighting: CloudWeighting)->Self {
+        self.cloud_weighting = cloud_weighting;
+        self
+    }
+
+    /// use non-default parameters to detect device relocations
+    pub fn with_position_config (mut self, position_config: PositionConfig)->Self {
+        self.position_config = position_config;
+        self
+    }
+
+    fn new_with_sources (sources: Vec<SourceConnection>, checkpoint: Option<CheckpointConfig>)->Self {
         SentinelConnector {
-            config: Arc::new(config),
+            sources,
             sentinels: SentinelStore::new(),
+            initialized: false,
+
+            checkpoint,
+            checkpoint_timer: None,
 
             last_recv_epoch: Arc::new(AtomicU64::new(0)),
 
-            ping_timer: None,
-            websocket_task: None,
-            ws_write: None,
+            position_config: PositionConfig::default(),
+            cloud_weighting: CloudWeighting::default(),
+            privacy: PrivacyFilter::default(),
 
             init_callbacks: CallbackList::new(),
+            device_callbacks: CallbackList::new(),
             update_callbacks: CallbackList::new(),
             json_update_callbacks: CallbackList::new(),
         }
     }
 
-    async fn run_init_task (hself: ActorHandle<SentinelConnectorMsg>, config: Arc<SentinelConfig>)->Result<()> {
+    fn source_index (&self, source_id: &str)->Option<usize> {
+        self.sources.iter().position( |src| src.source_id == source_id)
+    }
+
+    /// get the initial data of a source. If we loaded a checkpoint we only retrieve records that are newer than the ones we have
+    async fn run_init_task (hself: ActorHandle<SentinelConnectorMsg>, source_id: SourceId, config: Arc<SentinelConfig>, resume: ResumePoints)->Result<()> {
+        let http_client = Client::new();
+
+        // this might take a while so we shouldn't await it in receive()
+        let result = resume_sentinel_store_from_config( &http_client, &config, &resume).await.map( |store| store.into_namespaced( &source_id));
+        Ok(hself.send_msg( SourceInit{ source_id, result }).await?)
+    }
+
+    /// check the current device list of a source against the devices we know and initialize new ones. Devices that fail
+    /// to initialize are skipped (and picked up again by the next refresh) so that they don't block the other changes
+    async fn run_refresh_task (hself: ActorHandle<SentinelConnectorMsg>, source_id: SourceId, config: Arc<SentinelConfig>, known_ids: Vec<String>)->Result<()> {
         let http_client = Client::new();
+        let device_list = get_device_list_from_config( &http_client, &config).await?;
+
+        let mut added = Vec::new();
+        for device in &device_list.data {
+            if !known_ids.contains( &device.id) {
+                match init_sentinel_from_config( &http_client, &config, device).await {
+                    Ok(mut sentinel) => {
+                        sentinel.set_source( &source_id);
+                        added.push( sentinel);
+                    }
+                    Err(e) => eprintln!("@@ failed to initialize new device '{}' of source '{}': {:?}", device.id, source_id, e)
+                }
+            }
+        }
+
+        let removed: Vec<DeviceId> = known_ids.iter()
+            .filter( |id| !device_list.data.iter().any( |d| &d.id == *id))
+            .map( |id| namespaced_device_id( &source_id, id))
+            .collect();
+
+        if !added.is_empty() || !removed.is_empty() {
+            hself.send_msg( SourceDevicesChanged{ source_id, added, removed }).await?;
+        }
+        Ok(())
+    }
+
+    fn refresh_devices (&self, hself: ActorHandle<SentinelConnectorMsg>, idx: usize) {
+        let source = &self.sources[idx];
+        let source_id = source.source_id.clone();
+        let config = source.config.clone();
+        let known_ids = self.sentinels.get_source_device_ids( &source_id);
 
-        let sentinel_store = init_sentinel_store_from_config( &http_client, &config).await?; // this might take a while so we shouldn't await it in receive()
-        Ok(hself.send_msg( sentinel_store).await?)
+        // this needs to query the server so we have to spawn
+        spawn( async move {
+            if let Err(e) = SentinelConnector::run_refresh_task( hself, source_id.clone(), config, known_ids).await {
+                eprintln!("@@ failed to refresh devices of source '{}': {:?}", source_id, e);
+            }
+        });
+    }
+
+    async fn update_devices (&mut self, hself: ActorHandle<SentinelConnectorMsg>, msg: SourceDevicesChanged) {
+        if let Some(idx) = self.source_index( &msg.source_id) {
+            let mut added_ids = Vec::new();
+            for sentinel in msg.added {
+                added_ids.push( split_device_id( &sentinel.device_id).1.to_string());
+                self.add_device( sentinel).await;
+            }
+
+            let mut removed_ids = Vec::new();
+            for device_id in msg.removed {
+                if self.retire_device( &device_id).await {
+                    removed_ids.push( split_device_id( &device_id).1.to_string());
+                }
+            }
+
+            if let Some(tx) = self.sources[idx].ws_write.as_mut() {
+                if !added_ids.is_empty() {
+                    if let Err(e) = send_join( tx, added_ids, get_next_msg_id()).await {
+                        eprintln!("@@ failed to join new devices of source '{}': {:?}", msg.source_id, e);
+                    }
+                }
+                if !removed_ids.is_empty() {
+                    if let Err(e) = send_leave( tx, removed_ids, get_next_msg_id()).await {
+                        eprintln!("@@ failed to leave retired devices of source '{}': {:?}", msg.source_id, e);
+                    }
+                }
+            } else if !added_ids.is_empty() {
+                self.open_websocket( hself, idx).await
+            }
+        }
+    }
+
+    /// add the sentinel of a device that was reported after we got the initial data
+    async fn add_device (&mut self, mut sentinel: Sentinel) {
+        sentinel.annotate_confidences( &self.cloud_weighting);
+        if let Err(e) = self.sentinels.write_through_sentinel( &sentinel) { eprintln!("@@ failed to store records of {}: {:?}", sentinel.device_id, e); }
+        self.privacy.filter_sentinel( &mut sentinel);
+        let event = self.sentinels.add_device( sentinel);
+        self.device_callbacks.trigger( event).await;
+    }
+
+    /// returns true if the device was active before
+    async fn retire_device (&mut self, device_id: &DeviceId)->bool {
+        if let Some(event) = self.sentinels.retire_device( device_id, Utc::now()) {
+            self.device_callbacks.trigger( event).await;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// warm start - make the last checkpoint available before we query the servers
+    async fn load_checkpoint (&mut self, hself: ActorHandle<SentinelConnectorMsg>) {
+        if let Some(checkpoint) = &self.checkpoint {
+            if checkpoint.path.is_file() {
+                match SentinelStore::load( &checkpoint.path) {
+                    Ok(sentinels) => {
+                        self.sentinels.merge( sentinels); // don't replace - we might already have a storage
+                        self.initialized = true;
+                        self.init_callbacks.trigger(()).await;
+                    }
+                    Err(e) => eprintln!("@@ failed to load checkpoint {:?}: {:?}", checkpoint.path, e)
+                }
+            }
+
+            if let Some(interval) = checkpoint.interval {
+                self.checkpoint_timer = Some( hself.start_repeat_timer( CHECKPOINT_TIMER, interval));
+            }
+        }
+    }
+
+    fn save_checkpoint (&self) {
+        if let Some(checkpoint) = &self.checkpoint {
+            if let Err(e) = self.sentinels.save( &checkpoint.path) {
+                eprintln!("@@ failed to save checkpoint {:?}: {:?}", checkpoint.path, e);
+            }
+        }
     }
 
-    async fn send_ws_cmd (&mut self, cmd: WsCmd)->Result<()> {
-        if let Some(mut tx) = self.ws_write.as_mut() { 
+    /// merge the sentinels we got from the init task of a source into our store. If we did a warm start we only process
+    /// records we don't have yet so that clients that got the checkpoint data can update accordingly
+    async fn merge_sentinels (&mut self, source_id: &str, mut sentinels: SentinelStore) {
+        sentinels.annotate_confidences( &self.cloud_weighting);
+
+        if !self.initialized { // cold start - nobody has seen our data yet
+            if let Err(e) = self.sentinels.write_through_all( &sentinels) { eprintln!("@@ failed to store records of source '{}': {:?}", source_id, e); }
+            self.privacy.filter_store( &mut sentinels); // storage gets the unfiltered records
+            self.sentinels.merge( sentinels);
+            return
+        }
+
+        let mut missing: Vec<DeviceId> = self.sentinels.get_source_device_ids( source_id).iter()
+            .map( |id| namespaced_device_id( source_id, id))
+            .collect();
+
+        for sentinel in sentinels.into_values() {
+            missing.retain( |id| *id != sentinel.device_id);
+
+            if let Some(existing) = self.sentinels.get_mut( &sentinel.device_id) {
+                existing.device_name = sentinel.device_name.clone();
+                existing.retired = None;
+                self.merge_sentinel( sentinel).await;
+            } else {
+                self.add_device( sentinel).await;
+            }
+        }
+
+        for device_id in missing { // checkpointed devices that are no longer reported by the server
+            self.retire_device( &device_id).await;
+        }
+    }
+
+    /// process records of a server provided sentinel that are not yet in our store as if we got them through the websocket
+    async fn merge_sentinel (&mut self, sentinel: Sentinel) {
+        let updates = match self.sentinels.get( &sentinel.device_id) {
+            Some(existing) => existing.new_updates( sentinel),
+            None => Vec::new()
+        };
+        for update in updates {
+            self.update( update).await.ok();
+        }
+    }
+
+    async fn send_ws_cmd (&mut self, idx: usize, cmd: WsCmd)->Result<()> {
+        if let Some(mut tx) = self.sources[idx].ws_write.as_mut() { 
             let json = serde_json::to_string(&cmd)?;
             send_ws_text_msg( &mut tx, json).await
 
@@ -101,114 +336,246 @@ impl SentinelConnector {
         }
     }
 
-    async fn set_sentinels (&mut self, sentinels: SentinelStore) {
-        self.sentinels = sentinels;
-        self.init_callbacks.trigger(()).await; // let other actors know we have data
+    /// send command to the backends that own the respective devices, using their native device ids
+    async fn route_ws_cmd (&mut self, cmd: WsCmd)->Result<()> {
+        if let Some(device_ids) = cmd.device_ids() {
+            let mut source_device_ids: HashMap<String,Vec<String>> = HashMap::new();
+            for device_id in device_ids {
+                let (source_id,native_id) = split_device_id( device_id);
+                source_device_ids.entry( source_id.to_string()).or_default().push( native_id.to_string());
+            }
+
+            for (source_id,native_ids) in source_device_ids {
+                let idx = self.source_index( &source_id).ok_or( OdinSentinelError::NoSuchDeviceError( source_id))?;
+                self.send_ws_cmd( idx, cmd.with_device_ids( native_ids)).await?;
+            }
+            Ok(())
+
+        } else { // not device specific - send to all sources
+            for idx in 0..self.sources.len() {
+                self.send_ws_cmd( idx, cmd.clone()).await?;
+            }
+            Ok(())
+        }
+    }
+
+    async fn init_source (&mut self, hself: ActorHandle<SentinelConnectorMsg>, msg: SourceInit) {
+        if let Some(idx) = self.source_index( &msg.source_id) {
+            match msg.result {
+                Ok(sentinels) => {
+                    self.merge_sentinels( &msg.source_id, sentinels).await;
+                    self.open_websocket( hself.clone(), idx).await;
+
+                    let source = &mut self.sources[idx];
+                    if let Some(interval) = source.config.device_refresh_interval {
+                        source.refresh_timer = Some( hself.start_repeat_timer( REFRESH_TIMER_BASE + idx as i64, interval))
+                    }
+                }
+                Err(e) => eprintln!("@@ failed to initialize source '{}': {:?}", msg.source_id, e)
+            }
+            self.sources[idx].init_completed = true;
+
+            if !self.initialized && self.sources.iter().all( |src| src.init_completed) {
+                self.initialized = true;
+                self.init_callbacks.trigger(()).await; // let other actors know we have data
+            }
+        }
     }
 
-    async fn open_websocket (&mut self, hself: ActorHandle<SentinelConnectorMsg>) {
-        let device_ids = self.sentinels.get_device_ids();
+    async fn open_websocket (&mut self, hself: ActorHandle<SentinelConnectorMsg>, idx: usize) {
+        let source = &mut self.sources[idx];
+        let device_ids = self.sentinels.get_source_device_ids( &source.source_id);
 
         if !device_ids.is_empty() {
-            let hself = hself.clone();
-            let config = self.config.clone();
+            let config = source.config.clone();
 
-            if let Ok(ws_stream) = init_websocket(self.config.clone(), device_ids).await {
+            if let Ok(ws_stream) = init_websocket( config.clone(), device_ids).await {
                 let (ws_write, ws_read) = ws_stream.split();
-                self.ws_write = Some(ws_write);
+                source.ws_write = Some(ws_write);
 
-                self.websocket_task = Some( spawn( run_websocket( hself.clone(), config, ws_read)) );
-                if let Some(interval) = self.config.ping_interval {
-                    self.ping_timer = Some( hself.start_repeat_timer( PING_TIMER, interval) )
+                source.websocket_task = Some( spawn( run_websocket( hself.clone(), source.source_id.clone(), config, ws_read)) );
+                if let Some(interval) = source.config.ping_interval {
+                    source.ping_timer = Some( hself.start_repeat_timer( PING_TIMER_BASE + idx as i64, interval) )
                 }
             }
         }
     }
 
-    fn cleanup_websocket (&mut self) {
-        self.ws_write = None;
+    fn cleanup_websocket (&mut self, idx: usize) {
+        let source = &mut self.sources[idx];
+        source.ws_write = None;
 
-        if let Some(abort_handle) = &self.ping_timer {
+        if let Some(abort_handle) = &source.ping_timer {
             abort_handle.abort()
         }
 
-        if let Some(join_handle) = &self.websocket_task {
+        if let Some(join_handle) = &source.websocket_task {
             if !join_handle.is_finished() {
                 join_handle.abort();
             }
-            self.websocket_task = None;
+            source.websocket_task = None;
         }
+    }
 
+    fn cleanup (&mut self) {
+        if let Some(abort_handle) = &self.checkpoint_timer {
+            abort_handle.abort()
+        }
+        self.cleanup_websockets();
+        self.save_checkpoint();
     }
 
-    define_update! { accel       <- SensorRecord<AccelerometerData> }
-    define_update! { anemo       <- SensorRecord<AnemometerData> }
-    define_update! { cloudcover  <- SensorRecord<CloudcoverData> }
-    define_update! { fire        <- SensorRecord<FireData> }
-    define_update! { gas         <- SensorRecord<GasData> }
-    define_update! { gps         <- SensorRecord<GpsData> }
-    define_update! { gyro        <- SensorRecord<GyroscopeData> }
-    define_update! { image       <- SensorRecord<ImageData> }
-    define_update! { mag         <- SensorRecord<MagnetometerData> }
-    define_update! { orientation <- SensorRecord<OrientationData> }
-    define_update! { person      <- SensorRecord<PersonData> }
-    define_update! { power       <- SensorRecord<PowerData> }
-    define_update! { smoke       <- SensorRecord<SmokeData> }
-    define_update! { thermo      <- SensorRecord<ThermometerData> }
-    define_update! { valve       <- SensorRecord<ValveData> }
-    define_update! { voc         <- SensorRecord<VocData> }
+    fn cleanup_websockets (&mut self) {
+        for idx in 0..self.sources.len() {
+            self.cleanup_websocket( idx);
+
+            if let Some(abort_handle) = &self.sources[idx].refresh_timer {
+                abort_handle.abort()
+            }
+        }
+    }
+
+    async fn update (&mut self, mut update: SentinelUpdate)->Result<()> {
+        let device_id = update.device_id().to_string();
+        if self.sentinels.get( &device_id).map_or( false, |s| s.retired.is_some()) {
+            return Ok(()) // the server might still send records before it processed our leave
+        }
+
+        update.annotate_confidence( self.sentinels.sentinel_of( &device_id)?, &self.cloud_weighting); // stored records include it
+
+        if let Err(e) = self.sentinels.write_through( &update) { eprintln!("@@ failed to store record {}: {:?}", update.record_id(), e); }
+        let updates = self.privacy.filter_update( update, self.sentinels.sentinel_of( &device_id)?);
+        for update in updates {
+            self.publish( &device_id, update).await?;
+        }
+        Ok(())
+    }
+
+    /// sort a (filtered) update into our store and send it to our clients
+    async fn publish (&mut self, device_id: &DeviceId, update: SentinelUpdate)->Result<()> {
+        // only convert if there are clients for it
+        let json = if !self.json_update_callbacks.is_empty() { Some(Arc::new(serde_json::to_string(&update)?)) } else { None };
+        let update_rec = if !self.update_callbacks.is_empty() { Some( Arc::new(update.clone())) } else { None };
+
+        let sentinel = self.sentinels.sentinel_of( device_id)?;
+        let is_gps = matches!( update, SentinelUpdate::Gps(_));
+        update.sort_into( sentinel); // this consumes the update
+        let relocation = if is_gps { detect_relocation( sentinel, &self.position_config) } else { None };
+
+        if let Some(json) = json { self.json_update_callbacks.trigger(json).await; } // we don't propagate errors here
+        if let Some(update_rec) = update_rec { self.update_callbacks.trigger(update_rec).await; }
+        if let Some(relocation) = relocation { self.device_callbacks.trigger( DeviceEvent::Relocated(relocation)).await; }
+
+        Ok(())
+    }
 
 }
 
 
 #[derive(Debug)] pub struct AddInitCallback { pub id: String, pub action: Callback<()> }
 
+#[derive(Debug)] pub struct AddDeviceCallback { pub id: String, pub action: Callback<DeviceEvent> }
+
 #[derive(Debug)] pub struct AddUpdateCallback { pub id: String, pub action: Callback<Arc<SentinelUpdate>> }
 
 #[derive(Debug)] pub struct AddJsonUpdateCallback { pub id: String, pub action: Callback<Arc<String>> }
 
+/// message to send a command to the devices it is addressed to. Device ids are the (possibly namespaced) ids of our
+/// SentinelStore, i.e. the connector routes the command to the backend that owns the respective devices
+#[derive(Debug)] pub struct SendWsCmd(pub WsCmd);
+
 /// message to request a single callback execution with the current Sentinel snapshot in JSON format
 /// (since this is a single execution there is no point transmitting this as an Arc<String>) 
 #[derive(Debug)] pub struct TriggerJsonSnapshot(pub Callback<String>);
 
+//-- messages for client actors that mirror the connector state by means of a JSON snapshot and subsequent updates (see cot and cap modules)
+
+/// sent by init callbacks of clients
+#[derive(Debug)] pub struct SentinelsInitialized;
+
+/// sent by snapshot callbacks of clients
+#[derive(Debug)] pub struct SentinelJsonSnapshot(pub String);
+
+impl SentinelJsonSnapshot {
+    /// the parsed snapshot, or None (after reporting the error) if it is not a valid SentinelStore
+    pub fn sentinels (&self)->Option<SentinelStore> {
+        SentinelStore::from_json( &self.0).map_err( |e| eprintln!("@@ failed to parse sentinel snapshot: {:?}", e)).ok()
+    }
+}
+
+/// sent by JSON update callbacks of clients
+#[derive(Debug)] pub struct SentinelJsonUpdate(pub Arc<String>);
+
+/// sent by update callbacks of clients that keep their own SentinelStore mirror
+#[derive(Debug)] pub struct SentinelRecordUpdate(pub Arc<SentinelUpdate>);
+
+/// subscribe a client actor to the connector state once it got its SentinelsInitialized message. The actor gets a
+/// SentinelJsonSnapshot and a SentinelRecordUpdate for each new record, which it can sort into its own
+/// SentinelStore with `add_update()`. Usage: `subscribe_mirror!( self.hconn, &self.hself, self.id().to_string())`.
+/// The update callback is registered before the snapshot is requested so that we can't miss records in between.
+/// Updates that arrive before the snapshot are included in it, and re-adding them with `add_update()` is a no-op
+macro_rules! subscribe_mirror {
+    ($hconn:expr, $hself:expr, $id:expr) => {{
+        let hself = $hself;
+        $hconn.send_msg( $crate::actor::AddUpdateCallback {
+            id: $id,
+            action: msg_callback!( hself, |update:std::sync::Arc<$crate::SentinelUpdate>| $crate::actor::SentinelRecordUpdate(update))
+        }).await.ok();
+        $hconn.send_msg( $crate::actor::TriggerJsonSnapshot( msg_callback!( hself, |json:String| $crate::actor::SentinelJsonSnapshot(json)))).await.ok();
+    }}
+}
+pub(crate) use subscribe_mirror;
+
+/// internal message sent by the init task of a source once it has retrieved (or failed to retrieve) the initial sentinel data
+#[derive(Debug)] pub struct SourceInit { pub source_id: SourceId, pub result: Result<SentinelStore> }
+
+/// internal message sent by the refresh task of a source if the server reported new devices or stopped reporting known ones.
+/// Added sentinels are already initialized and namespaced
+#[derive(Debug)] pub struct SourceDevicesChanged { pub source_id: SourceId, pub added: Vec<Sentinel>, pub removed: Vec<DeviceId> }
+
+/// internal message sent by the websocket task of a source if the websocket was closed by the server
+#[derive(Debug)] pub struct SourceClosed(pub SourceId);
+
 define_actor_msg_type! { pub SentinelConnectorMsg = 
     // messages we get from other actors
     AddInitCallback |
+    AddDeviceCallback |
     AddUpdateCallback |
     AddJsonUpdateCallback |
     TriggerJsonSnapshot |
+    SendWsCmd |
 
     // messages we get from ourself (spawned tasks)
-    SentinelStore |
-    SensorRecord<AccelerometerData> |
-    SensorRecord<AnemometerData> |
-    SensorRecord<CloudcoverData> |
-    SensorRecord<FireData> |
-    SensorRecord<ImageData> |
-    SensorRecord<GasData> |
-    SensorRecord<GpsData> |
-    SensorRecord<GyroscopeData> |
-    SensorRecord<OrientationData> |
-    SensorRecord<MagnetometerData> |
-    SensorRecord<PersonData> |
-    SensorRecord<PowerData> |
-    SensorRecord<SmokeData> |
-    SensorRecord<ThermometerData> |
-    SensorRecord<ValveData> |
-    SensorRecord<VocData> |
+    SourceInit |
+    SourceDevicesChanged |
+    SourceClosed |
+    SentinelUpdate |
     OdinSentinelError
 }
 
 impl_actor! { match msg for Actor<SentinelConnector,SentinelConnectorMsg> as 
     _Start_ => cont! { 
         let hself = self.hself.clone();
-        let config = self.config.clone();
+        self.load_checkpoint( hself).await;
 
-        spawn( SentinelConnector::run_init_task( hself, config)); // this can take some time so we have to spawn
+        for src in &self.sources {
+            let hself = self.hself.clone();
+            let source_id = src.source_id.clone();
+            let config = src.config.clone();
+            let resume = self.sentinels.resume_points( &source_id); // empty if we didn't load a checkpoint
+
+            spawn( SentinelConnector::run_init_task( hself, source_id, config, resume)); // this can take some time so we have to spawn
+        }
     }
     AddInitCallback => cont! {
+        if self.initialized { // we already have data (e.g. from a checkpoint) - don't make the client wait for the next init
+            msg.action.trigger(()).await;
+        }
         self.init_callbacks.add( msg.id, msg.action )
     }
+    AddDeviceCallback => cont! {
+        self.device_callbacks.add( msg.id, msg.action )
+    }
     AddUpdateCallback => cont! {
         self.update_callbacks.add( msg.id, msg.action )
     }
@@ -220,26 +587,49 @@ impl_actor! { match msg for Actor<SentinelConnector,SentinelConnectorMsg> as
             msg.0.trigger(s).await;
         }
     }
-    SentinelStore => cont! { // we get this once run_init_task() is completed
+    SendWsCmd => cont! {
+        if let Err(e) = self.route_ws_cmd( msg.0).await {
+            eprintln!("@@ failed to send command: {:?}", e);
+        }
+    }
+    SourceInit => cont! { // we get this once run_init_task() of a source is completed
         let hself = self.hself.clone();
-        self.set_sentinels(msg).await;
-        self.open_websocket( hself).await
+        self.init_source( hself, msg).await
+    }
+    SourceDevicesChanged => cont! {
+        let hself = self.hself.clone();
+        self.update_devices( hself, msg).await
+    }
+    SourceClosed => cont! {
+        eprintln!("@@ websocket of source '{}' closed by server", msg.0);
+        if let Some(idx) = self.source_index( &msg.0) {
+            self.cleanup_websocket( idx);
+            // TODO - check if we should restart the init here
+        }
     }
     _Timer_ => cont! { 
         match msg.id {
-            PING_TIMER => { 
-                self.send_ws_cmd( WsCmd::new_ping("ping")).await; 
+            CHECKPOINT_TIMER => {
+                self.save_checkpoint();
+            }
+            id if id >= REFRESH_TIMER_BASE => {
+                let idx = (id - REFRESH_TIMER_BASE) as usize;
+                if idx < self.sources.len() {
+                    let hself = self.hself.clone();
+                    self.refresh_devices( hself, idx);
+                }
+            }
+            id if id >= PING_TIMER_BASE => { 
+                let idx = (id - PING_TIMER_BASE) as usize;
+                if idx < self.sources.len() {
+                    self.send_ws_cmd( idx, WsCmd::new_ping("ping")).await; 
+                }
             }
             _ => {}
         }
     }
     OdinSentinelError => cont! {
         match msg {
-            OdinSentinelError::WsClosedError => {
-                eprintln!("@@ websocket closed by server");
-                self.cleanup_websocket();
-                // TODO - check if we should restart the init here
-            }
             OdinSentinelError::JsonError(e) => {
                 eprintln!("@@ {:?}", e);
             }
@@ -250,22 +640,7 @@ impl_actor! { match msg for Actor<SentinelConnector,SentinelConnectorMsg> as
         }
     }
     _Terminate_ => stop! {
-        self.cleanup_websocket()
-    }
-    SensorRecord<AccelerometerData> => cont! { self.update_accel(msg).await }
-    SensorRecord<AnemometerData>    => cont! { self.update_anemo(msg).await }
-    SensorRecord<CloudcoverData>    => cont! { self.update_cloudcover(msg).await }
-    SensorRecord<FireData>          => cont! { self.update_fire(msg).await }
-    SensorRecord<ImageData>         => cont! { self.update_image(msg).await }
-    SensorRecord<GasData>           => cont! { self.update_gas(msg).await }
-    SensorRecord<GpsData>           => cont! { self.update_gps(msg).await }
-    SensorRecord<GyroscopeData>     => cont! { self.update_gyro(msg).await }
-    SensorRecord<OrientationData>   => cont! { self.update_orientation(msg).await }
-    SensorRecord<MagnetometerData>  => cont! { self.update_mag(msg).await }
-    SensorRecord<PersonData>        => cont! { self.update_person(msg).await }
-    SensorRecord<PowerData>         => cont! { self.update_power(msg).await }
-    SensorRecord<SmokeData>         => cont! { self.update_smoke(msg).await }
-    SensorRecord<ThermometerData>   => cont! { self.update_thermo(msg).await }
-    SensorRecord<ValveData>         => cont! { self.update_valve(msg).await }
-    SensorRecord<VocData>           => cont! { self.update_voc(msg).await }
+        self.cleanup()
+    }
+    SentinelUpdate => cont! { self.update(msg).await }
 }
\ No newline at end of file
@@ -0,0 +1,146 @@
+/*
+ * Copyright (c) 2024, United States Government, as represented by the
+ * Administrator of the National Aeronautics and Space Administration.
+ * All rights reserved.
+ *
+ * The RACE - Runtime for Airspace Concept Evaluation platform is licensed
+ * under the Apache License, Version 2.0 (the "License"); you may not use
+ * this file except in compliance with the License. You may obtain a copy
+ * of the License at http://www.apache.org/licenses/LICENSE-2.0.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! downsampling of numeric record fields into time bucketed series (e.g. hourly min/mean/max temperature or
+//! 10 minute max fire probability). Aggregation works on generic JSON records so that it can be used for the
+//! SentinelStore and for paginated record history alike. Quantity fields are converted into the selected units
+//! (see units module), and the resulting Series are serializable so that servers and exporters can emit them as-is
+
+use std::{collections::BTreeMap,time::Duration};
+use chrono::{DateTime,TimeZone,Utc};
+use reqwest::Client;
+use serde::{Deserialize,Serialize};
+use serde_json::Value;
+use crate::*;
+use crate::units::{Units,quantity_kind,is_direction};
+
+/// aggregated values of all samples within [start, start+interval)
+#[derive(Serialize,Deserialize,Debug,Clone,PartialEq)]
+#[serde(rename_all="camelCase")]
+pub struct Bucket {
+    pub start: DateTime<Utc>,
+    pub count: usize,
+    pub min: f64,
+    pub mean: f64,
+    pub max: f64,
+    pub last: f64, // value of the newest sample
+}
+
+/// the aggregated series of one field of a single sensor
+#[derive(Serialize,Deserialize,Debug,Clone,PartialEq)]
+#[serde(rename_all="camelCase")]
+pub struct Series {
+    pub device_id: DeviceId,
+    pub sensor_no: u32,
+    pub capability: SensorCapability,
+    pub field: String,          // flattened payload field name (e.g. "fireProb" or "temperature")
+    pub unit: Option<String>,   // unit symbol if the field is a quantity
+    pub interval: Duration,
+    pub buckets: Vec<Bucket>,   // in ascending time order, buckets without samples are omitted
+}
+
+/// sample accumulator. We also sum up unit vectors so that directions (degrees) can be averaged on the circle,
+/// where the arithmetic mean of 350 and 10 would be 180
+struct Acc { count: usize, min: f64, max: f64, sum: f64, sum_sin: f64, sum_cos: f64, last: (DateTime<Utc>,f64) }
+
+impl Acc {
+    fn new (t: DateTime<Utc>, v: f64)->Self {
+        let (sin,cos) = v.to_radians().sin_cos();
+        Acc { count: 1, min: v, max: v, sum: v, sum_sin: sin, sum_cos: cos, last: (t,v) }
+    }
+
+    fn add (&mut self, t: DateTime<Utc>, v: f64) {
+        let (sin,cos) = v.to_radians().sin_cos();
+        self.count += 1;
+        self.min = self.min.min( v);
+        self.max = self.max.max( v);
+        self.sum += v;
+        self.sum_sin += sin;
+        self.sum_cos += cos;
+        if t >= self.last.0 { self.last = (t,v) }
+    }
+
+    fn mean (&self)->f64 { self.sum / self.count as f64 }
+
+    /// circular mean in [0,360) degrees
+    fn mean_direction (&self)->f64 { self.sum_sin.atan2( self.sum_cos).to_degrees().rem_euclid( 360.0) }
+}
+
+fn bucket_start (t: DateTime<Utc>, interval_ms: i64)->DateTime<Utc> {
+    let ms = t.timestamp_millis();
+    Utc.timestamp_millis_opt( ms - ms.rem_euclid( interval_ms)).single().unwrap_or( t)
+}
+
+/// the numeric value of a flattened payload field, converted into the selected unit if it is a quantity
+fn field_value (capability: SensorCapability, rec: &Value, field: &str, units: &Units)->Option<f64> {
+    let mut columns = Vec::new();
+    flatten_json( "", rec.get( capability.property_name())?, &mut columns);
+    let v = columns.iter().find( |(name,_)| name == field)?.1.as_f64()?;
+
+    Some( match quantity_kind( capability, field) {
+        Some(kind) => units.convert( kind, v),
+        None => v
+    })
+}
+
+/// aggregate a field of JSON records (of the given capability) into one Series per (device,sensor). Records without
+/// a numeric value for the field are skipped. Buckets are aligned to multiples of the interval since the epoch.
+/// Means of direction fields (e.g. anemometer angle) are circular means
+pub fn aggregate (capability: SensorCapability, records: &[Value], field: &str, interval: Duration, units: &Units)->Result<Vec<Series>> {
+    let interval_ms = interval.as_millis() as i64;
+    if interval_ms <= 0 { return Err( op_failed("aggregation interval must not be zero")) }
+
+    let mut accs: BTreeMap<(DeviceId,u32),BTreeMap<DateTime<Utc>,Acc>> = BTreeMap::new();
+    for rec in records {
+        let (Some(t), Some(v)) = (json_record_time( rec), field_value( capability, rec, field, units)) else { continue };
+        let device_id = rec.get("deviceId").and_then( |v| v.as_str()).unwrap_or_default().to_string();
+        let sensor_no = rec.get("sensorNo").and_then( |v| v.as_u64()).unwrap_or_default() as u32;
+
+        accs.entry( (device_id, sensor_no)).or_default()
+            .entry( bucket_start( t, interval_ms))
+            .and_modify( |acc| acc.add( t, v))
+            .or_insert_with( || Acc::new( t, v));
+    }
+
+    let unit = quantity_kind( capability, field).map( |kind| units.symbol( kind).to_string());
+    let direction = is_direction( capability, field);
+    Ok( accs.into_iter().map( |((device_id, sensor_no), buckets)| Series {
+        device_id, sensor_no, capability,
+        field: field.to_string(),
+        unit: unit.clone(),
+        interval,
+        buckets: buckets.into_iter().map( |(start,acc)| Bucket {
+            start, count: acc.count, min: acc.min, max: acc.max, last: acc.last.1,
+            mean: if direction { acc.mean_direction() } else { acc.mean() }
+        }).collect()
+    }).collect())
+}
+
+impl SentinelStore {
+    /// aggregate a field of all records of the given capability we have in memory
+    pub fn aggregate (&self, capability: SensorCapability, field: &str, interval: Duration, units: &Units)->Result<Vec<Series>> {
+        aggregate( capability, &self.json_records( capability)?, field, interval, units)
+    }
+}
+
+/// retrieve the record history of a sensor from the server and aggregate the given field
+pub async fn get_aggregated_history (client: &Client, base_uri: &str, access_token: &str, device_id: &str, sensor_no: u32,
+                                     capability: SensorCapability, start: DateTime<Utc>, end: DateTime<Utc>, page_size: usize,
+                                     field: &str, interval: Duration, units: &Units)->Result<Vec<Series>> {
+    let records = get_record_history( client, base_uri, access_token, device_id, sensor_no, capability, start, end, page_size).await?;
+    aggregate( capability, &records, field, interval, units)
+}
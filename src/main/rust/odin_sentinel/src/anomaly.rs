@@ -0,0 +1,362 @@
+/*
+ * Copyright (c) 2024, United States Government, as represented by the
+ * Administrator of the National Aeronautics and Space Administration.
+ * All rights reserved.
+ *
+ * The RACE - Runtime for Airspace Concept Evaluation platform is licensed
+ * under the Apache License, Version 2.0 (the "License"); you may not use
+ * this file except in compliance with the License. You may obtain a copy
+ * of the License at http://www.apache.org/licenses/LICENSE-2.0.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! adaptive per-device baselines for gas and VOC readings, and z-score anomaly detection. Absolute values differ
+//! between units, hence each (device,sensor,signal) has its own baseline that consists of an exponentially weighted
+//! moving average level, a diurnal (hour of day) offset and an EWMA residual variance. Anomalies are reported as
+//! AnomalyEvents, and the BaselineTracker keeps recent smoke indicative anomalies that are used to confirm
+//! camera based fire/smoke detections of the same device, which the AirQualityMonitor reports as DetectionConfirmations
+
+use std::{collections::{HashMap,VecDeque},sync::Arc,time::Duration};
+use chrono::{DateTime,Timelike,Utc};
+use serde::{Deserialize,Serialize};
+use odin_actor::prelude::*;
+use odin_actor::tokio_kanal::{Actor,ActorHandle};
+use crate::*;
+use crate::actor::{SentinelConnectorMsg,AddInitCallback,SentinelsInitialized,SentinelJsonSnapshot,SentinelRecordUpdate,
+                   subscribe_mirror};
+
+const DIURNAL_BINS: usize = 24;
+const MAX_RECENT_ANOMALIES: usize = 100; // per device
+const MAX_PENDING_DETECTIONS: usize = 20; // per device
+
+#[derive(Deserialize,Serialize,Debug,Clone)]
+pub struct BaselineConfig {
+    pub time_constant: Duration, // of the level and variance EWMA
+    pub diurnal_rate: f64,       // EWMA weight of new samples for their hour-of-day offset (0 disables the diurnal component)
+    pub min_samples: usize,      // number of samples before we compute scores
+    pub z_threshold: f64,        // absolute z-score above which we report anomalies
+    pub confirmation_window: Duration, // max time between a fire/smoke detection and a smoke indicative anomaly that confirms it
+}
+
+impl Default for BaselineConfig {
+    fn default()->Self {
+        BaselineConfig { 
+            time_constant: Duration::from_secs(6*3600), diurnal_rate: 0.1, min_samples: 10, z_threshold: 3.0,
+            confirmation_window: Duration::from_secs(600)
+        }
+    }
+}
+
+/// the gas and VOC quantities we track
+#[derive(Serialize,Deserialize,Debug,PartialEq,Eq,Hash,Copy,Clone)]
+#[serde(rename_all="camelCase")]
+pub enum AirSignal {
+    GasResistance,
+    Humidity,
+    Pressure,
+    Tvoc,
+    ECo2,
+}
+
+impl AirSignal {
+    /// the sign of changes that indicate smoke (VOCs decrease the gas sensor resistance), or 0 if the signal is not indicative
+    pub fn smoke_direction (&self)->f64 {
+        match self {
+            AirSignal::GasResistance => -1.0,
+            AirSignal::Tvoc | AirSignal::ECo2 => 1.0,
+            AirSignal::Humidity | AirSignal::Pressure => 0.0,
+        }
+    }
+
+    /// the reporting resolution of the signal, which is the floor for its baseline standard deviation. Otherwise a signal
+    /// that was constant during warm-up would never get a z-score
+    pub fn resolution (&self)->f64 {
+        match self {
+            AirSignal::GasResistance => 1.0, // Ohm
+            AirSignal::Humidity => 0.1,      // percent
+            AirSignal::Pressure => 0.1,      // hPa
+            AirSignal::Tvoc => 1.0,          // ppb
+            AirSignal::ECo2 => 1.0,          // ppm
+        }
+    }
+}
+
+/// the analysis update for a single gas or VOC sample
+#[derive(Serialize,Deserialize,Debug,Clone)]
+#[serde(rename_all="camelCase")]
+pub struct AnomalyScore {
+    pub device_id: DeviceId,
+    pub sensor_no: u32,
+    pub record_id: String,
+    pub time_recorded: DateTime<Utc>,
+    pub signal: AirSignal,
+    pub value: f64,
+    pub expected: f64,
+    pub z_score: f64,
+}
+
+impl AnomalyScore {
+    /// the z-score in the direction of smoke (negative if the change points the other way)
+    pub fn smoke_score (&self)->f64 {
+        self.z_score * self.signal.smoke_direction()
+    }
+}
+
+#[derive(Serialize,Deserialize,Debug,Clone)]
+#[serde(rename_all="camelCase")]
+pub struct AnomalyEvent {
+    pub score: AnomalyScore,
+    pub smoke_indicative: bool,
+}
+
+/// a camera based fire or smoke detection that is supported by smoke indicative anomalies of the same device
+#[derive(Serialize,Deserialize,Debug,Clone)]
+#[serde(rename_all="camelCase")]
+pub struct DetectionConfirmation {
+    pub device_id: DeviceId,
+    pub capability: SensorCapability, // Fire or Smoke
+    pub detection_id: String,
+    pub time_recorded: DateTime<Utc>, // of the detection
+    pub smoke_score: f64,             // max smoke score of the anomalies within the confirmation window
+}
+
+#[derive(Debug,Clone)]
+struct Baseline {
+    level: f64,
+    variance: f64,
+    diurnal: [f64; DIURNAL_BINS],
+    samples: usize,
+    last: DateTime<Utc>,
+}
+
+impl Baseline {
+    fn new (value: f64, t: DateTime<Utc>)->Self {
+        Baseline { level: value, variance: 0.0, diurnal: [0.0; DIURNAL_BINS], samples: 1, last: t }
+    }
+
+    fn expected (&self, t: DateTime<Utc>)->f64 {
+        self.level + self.diurnal[t.hour() as usize]
+    }
+
+    /// update with a new sample and return its (pre-update) z-score if we have seen enough samples
+    fn update (&mut self, value: f64, t: DateTime<Utc>, min_std: f64, config: &BaselineConfig)->(f64,Option<f64>) {
+        let bin = t.hour() as usize;
+        let expected = self.expected( t);
+        let residual = value - expected;
+
+        let z = if self.samples >= config.min_samples { Some( residual / self.variance.sqrt().max( min_std)) } else { None };
+
+        // time based EWMA weight, using the sample average during warm-up
+        let dt = (t - self.last).num_milliseconds().max(0) as f64 / 1000.0;
+        let alpha = (1.0 - (-dt / config.time_constant.as_secs_f64().max(1.0)).exp()).max( 1.0 / (self.samples + 1) as f64);
+
+        self.level += alpha * (value - self.diurnal[bin] - self.level);
+        self.diurnal[bin] += config.diurnal_rate * (value - self.level - self.diurnal[bin]);
+        self.variance += alpha * (residual * residual - self.variance);
+        self.samples += 1;
+        if t > self.last { self.last = t }
+
+        (expected, z)
+    }
+}
+
+#[derive(Debug,Default)]
+pub struct BaselineTracker {
+    config: BaselineConfig,
+    baselines: HashMap<(DeviceId,u32,AirSignal),Baseline>,
+    anomalies: HashMap<DeviceId,VecDeque<AnomalyEvent>>, // recent smoke indicative anomalies, newest first
+    pending: HashMap<DeviceId,VecDeque<SentinelUpdate>>, // recent fire/smoke detections without confirmation, newest first
+}
+
+impl BaselineTracker {
+    pub fn new (config: BaselineConfig)->Self {
+        BaselineTracker { config, baselines: HashMap::new(), anomalies: HashMap::new(), pending: HashMap::new() }
+    }
+
+    /// learn from all gas and VOC records of a store (oldest first) without reporting anomalies
+    pub fn learn (&mut self, store: &SentinelStore) {
+        for sentinel in store.values() {
+            let mut updates: Vec<SentinelUpdate> = Vec::new();
+            updates.extend( sentinel.records::<GasData>().iter().map( |r| SentinelUpdate::from( r.clone())));
+            updates.extend( sentinel.records::<VocData>().iter().map( |r| SentinelUpdate::from( r.clone())));
+            updates.sort_by_key( |u| u.time_recorded());
+            for u in &updates { self.process( u); }
+        }
+        self.anomalies.clear();
+    }
+
+    /// update baselines with a new record and return its scores (if baselines are established)
+    pub fn process (&mut self, update: &SentinelUpdate)->Vec<AnomalyScore> {
+        let signals: Vec<(AirSignal,f64)> = match update {
+            SentinelUpdate::Gas(rec) => vec![
+                (AirSignal::GasResistance, rec.data.gas as f64),
+                (AirSignal::Humidity, rec.data.humidity),
+                (AirSignal::Pressure, rec.data.pressure),
+            ],
+            SentinelUpdate::Voc(rec) => vec![
+                (AirSignal::Tvoc, rec.data.tvoc as f64),
+                (AirSignal::ECo2, rec.data.e_co2 as f64),
+            ],
+            _ => return Vec::new()
+        };
+
+        let (device_id, record_id, t) = (update.device_id().to_string(), update.record_id().to_string(), update.time_recorded());
+        let sensor_no = update.sensor_no();
+
+        let mut scores = Vec::new();
+        for (signal, value) in signals {
+            match self.baselines.get_mut( &(device_id.clone(), sensor_no, signal)) {
+                Some(baseline) => {
+                    if let (expected, Some(z_score)) = baseline.update( value, t, signal.resolution(), &self.config) {
+                        scores.push( AnomalyScore { device_id: device_id.clone(), sensor_no, record_id: record_id.clone(), time_recorded: t, signal, value, expected, z_score });
+                    }
+                }
+                None => { self.baselines.insert( (device_id.clone(), sensor_no, signal), Baseline::new( value, t)); }
+            }
+        }
+        scores
+    }
+
+    /// process a new record and return the anomaly events it caused
+    pub fn anomalies (&mut self, update: &SentinelUpdate)->Vec<AnomalyEvent> {
+        let threshold = self.config.z_threshold;
+        let events: Vec<AnomalyEvent> = self.process( update).into_iter()
+            .filter( |s| s.z_score.abs() > threshold)
+            .map( |score| AnomalyEvent { smoke_indicative: score.smoke_score() > threshold, score })
+            .collect();
+
+        for e in events.iter().filter( |e| e.smoke_indicative) {
+            let recent = self.anomalies.entry( e.score.device_id.clone()).or_default();
+            recent.push_front( e.clone());
+            recent.truncate( MAX_RECENT_ANOMALIES);
+        }
+        events
+    }
+
+    /// the max smoke score of smoke indicative anomalies of a device within `window` before (and including) `t`.
+    /// This is the input for components that confirm camera detections
+    pub fn smoke_evidence (&self, device_id: &DeviceId, t: DateTime<Utc>, window: Duration)->Option<f64> {
+        let start = t - chrono::Duration::from_std( window).ok()?;
+        self.anomalies.get( device_id)?.iter()
+            .filter( |e| e.score.time_recorded <= t && e.score.time_recorded >= start)
+            .map( |e| e.score.smoke_score())
+            .max_by( |a,b| a.total_cmp( b))
+    }
+
+    /// the confirmation of a fire or smoke detection by anomalies within the confirmation window around its record time
+    fn confirm (&self, detection: &SentinelUpdate)->Option<DetectionConfirmation> {
+        let device_id = detection.device_id().to_string();
+        let window = self.config.confirmation_window;
+        let t = detection.time_recorded() + chrono::Duration::from_std( window).ok()?; // anomalies can lag the detection
+        let smoke_score = self.smoke_evidence( &device_id, t, 2 * window)?;
+
+        Some( DetectionConfirmation {
+            device_id,
+            capability: detection.capability(),
+            detection_id: detection.record_id().to_string(),
+            time_recorded: detection.time_recorded(),
+            smoke_score
+        })
+    }
+
+    /// detection confirmations caused by a new record, which has to be processed by `anomalies()` first if it is a gas or
+    /// VOC record. Fire and smoke detections that are not yet confirmed are kept until a later anomaly confirms them
+    pub fn confirmations (&mut self, update: &SentinelUpdate)->Vec<DetectionConfirmation> {
+        let device_id = update.device_id().to_string();
+
+        match update {
+            SentinelUpdate::Fire(_) | SentinelUpdate::Smoke(_) => {
+                match self.confirm( update) {
+                    Some(confirmation) => vec![confirmation],
+                    None => {
+                        let pending = self.pending.entry( device_id).or_default();
+                        pending.push_front( update.clone());
+                        pending.truncate( MAX_PENDING_DETECTIONS);
+                        Vec::new()
+                    }
+                }
+            }
+            SentinelUpdate::Gas(_) | SentinelUpdate::Voc(_) => {
+                let Some(pending) = self.pending.remove( &device_id) else { return Vec::new() };
+                let mut confirmations = Vec::new();
+                let mut unconfirmed = VecDeque::new();
+                for detection in pending {
+                    match self.confirm( &detection) {
+                        Some(confirmation) => confirmations.push( confirmation),
+                        None => unconfirmed.push_back( detection)
+                    }
+                }
+                if !unconfirmed.is_empty() { self.pending.insert( device_id, unconfirmed); }
+                confirmations
+            }
+            _ => Vec::new()
+        }
+    }
+}
+
+/* #region air quality monitor actor *************************************************************/
+
+#[derive(Debug)] pub struct AddAnomalyCallback { pub id: String, pub action: Callback<Arc<AnomalyEvent>> }
+
+#[derive(Debug)] pub struct AddConfirmationCallback { pub id: String, pub action: Callback<Arc<DetectionConfirmation>> }
+
+define_actor_msg_type! { pub AirQualityMonitorMsg = AddAnomalyCallback | AddConfirmationCallback | SentinelsInitialized | SentinelJsonSnapshot | SentinelRecordUpdate }
+
+pub struct AirQualityMonitor {
+    hconn: ActorHandle<SentinelConnectorMsg>,
+    tracker: BaselineTracker,
+    anomaly_callbacks: CallbackList<Arc<AnomalyEvent>>,
+    confirmation_callbacks: CallbackList<Arc<DetectionConfirmation>>,
+}
+
+impl AirQualityMonitor {
+    pub fn new (config: BaselineConfig, hconn: ActorHandle<SentinelConnectorMsg>)->Self {
+        AirQualityMonitor { 
+            hconn, 
+            tracker: BaselineTracker::new( config), 
+            anomaly_callbacks: CallbackList::new(), 
+            confirmation_callbacks: CallbackList::new() 
+        }
+    }
+
+    async fn update (&mut self, update: &SentinelUpdate) {
+        for event in self.tracker.anomalies( update) {
+            self.anomaly_callbacks.trigger( Arc::new(event)).await;
+        }
+        for confirmation in self.tracker.confirmations( update) {
+            self.confirmation_callbacks.trigger( Arc::new(confirmation)).await;
+        }
+    }
+}
+
+impl_actor! { match msg for Actor<AirQualityMonitor,AirQualityMonitorMsg> as
+    _Start_ => cont! {
+        let id = self.id().to_string();
+        let hself = &self.hself;
+        self.hconn.send_msg( AddInitCallback{id, action: msg_callback!(hself, SentinelsInitialized)}).await.ok();
+    }
+    AddAnomalyCallback => cont! {
+        self.anomaly_callbacks.add( msg.id, msg.action)
+    }
+    AddConfirmationCallback => cont! {
+        self.confirmation_callbacks.add( msg.id, msg.action)
+    }
+    SentinelsInitialized => cont! {
+        subscribe_mirror!( self.hconn, &self.hself, self.id().to_string());
+    }
+    SentinelJsonSnapshot => cont! {
+        if let Some(sentinels) = msg.sentinels() {
+            self.tracker.learn( &sentinels) // history is only used to establish baselines
+        }
+    }
+    SentinelRecordUpdate => cont! {
+        self.update( &msg.0).await
+    }
+}
+
+/* #endregion air quality monitor actor */
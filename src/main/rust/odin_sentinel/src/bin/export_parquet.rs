@@ -0,0 +1,148 @@
+/*
+ * Copyright (c) 2024, United States Government, as represented by the
+ * Administrator of the National Aeronautics and Space Administration.
+ * All rights reserved.
+ *
+ * The RACE - Runtime for Airspace Concept Evaluation platform is licensed
+ * under the Apache License, Version 2.0 (the "License"); you may not use
+ * this file except in compliance with the License. You may obtain a copy
+ * of the License at http://www.apache.org/licenses/LICENSE-2.0.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+#![allow(unused)]
+
+#[macro_use]
+extern crate lazy_static;
+
+use std::path::PathBuf;
+use chrono::{DateTime,Utc};
+use serde_json::Value;
+use odin_sentinel::{SentinelConfig,SentinelStore,SensorCapability,init_sentinel_store_from_config,
+                    get_device_list_from_config,get_sensor_list,get_record_history};
+use odin_sentinel::parquet::write_partitioned;
+use odin_sentinel::privacy::{PrivacyFilter,PrivacyPolicy};
+use anyhow::Result;
+use odin_config::load_config;
+use structopt::StructOpt;
+use strum::IntoEnumIterator;
+use tokio;
+use reqwest;
+
+#[derive(StructOpt)]
+#[structopt(about = "Delphire Sentinel Parquet export tool")]
+struct CliOpts {
+    /// run verbose
+    #[structopt(short,long)]
+    verbose: bool,
+
+    /// optional checkpoint (*.json) or record archive (*.jsonl) to export instead of querying the server
+    #[structopt(short,long)]
+    input: Option<PathBuf>,
+
+    /// retrieve the server history since this time (RFC 3339) instead of the last configured records
+    #[structopt(long)]
+    start: Option<DateTime<Utc>>,
+
+    /// end of the server history to retrieve (RFC 3339, defaults to now)
+    #[structopt(long)]
+    end: Option<DateTime<Utc>>,
+
+    /// page size for history queries
+    #[structopt(long,default_value="100")]
+    page_size: usize,
+
+    /// comma separated list of capabilities to export (e.g. "fire,smoke,thermometer"), defaults to all
+    #[structopt(long)]
+    capabilities: Option<String>,
+
+    /// privacy policy config (*.ron) for person records and their images, defaults to redacting all person images
+    #[structopt(long)]
+    privacy: Option<PathBuf>,
+
+    /// directory where to store the partitioned parquet files
+    #[structopt(short,long,default_value="parquet")]
+    output: PathBuf,
+
+    /// path to sentinel config file
+    config_path: PathBuf
+}
+
+lazy_static! {
+    static ref ARGS: CliOpts = CliOpts::from_args();
+}
+
+#[tokio::main]
+async fn main()->Result<()> {
+    let sentinel_config: SentinelConfig = load_config( &ARGS.config_path)?;
+    let capabilities = SensorCapability::from_names( ARGS.capabilities.as_deref().unwrap_or(""))?;
+    let mut privacy = PrivacyFilter::new( match &ARGS.privacy {
+        Some(path) => load_config( path)?,
+        None => PrivacyPolicy::redact_all()
+    });
+
+    let paths = if let Some(start) = ARGS.start {
+        export_history( &sentinel_config, &capabilities, &mut privacy, start, ARGS.end.unwrap_or_else( Utc::now)).await?
+    } else {
+        let mut sentinel_store = if let Some(path) = &ARGS.input {
+            if path.extension().map_or( false, |ext| ext == "jsonl") {
+                SentinelStore::load_record_archive( path)?
+            } else {
+                SentinelStore::load( path)?
+            }
+        } else {
+            let http_client = reqwest::Client::new();
+            init_sentinel_store_from_config( &http_client, &sentinel_config).await?
+        };
+        privacy.filter_store( &mut sentinel_store);
+        sentinel_store.export_parquet( &ARGS.output, &capabilities)?
+    };
+
+    if ARGS.verbose {
+        for path in &paths { println!("{}", path.display()) }
+    }
+    println!("exported {} parquet files to {}", paths.len(), ARGS.output.display());
+    Ok(())
+}
+
+/// page through the server history of all (accepted) devices and sensors
+async fn export_history (config: &SentinelConfig, capabilities: &[SensorCapability], privacy: &mut PrivacyFilter, start: DateTime<Utc>, end: DateTime<Utc>)->Result<Vec<PathBuf>> {
+    let client = reqwest::Client::new();
+    let mut paths = Vec::new();
+
+    for device in get_device_list_from_config( &client, config).await?.data {
+        // partitions are per device and day, i.e. we have to collect the records of all sensors before writing
+        let mut device_records: Vec<(SensorCapability,Vec<Value>)> = Vec::new();
+
+        let sensor_list = get_sensor_list( &client, &config.base_uri, &config.access_token, &device.id).await?;
+        for sensor in &sensor_list.data {
+            for capability in &sensor.capabilities {
+                if config.device_filter.accepts_capability( *capability) && (capabilities.is_empty() || capabilities.contains( capability) || is_image_evidence( *capability, capabilities)) {
+                    if ARGS.verbose { println!("retrieving {:?} history of {}/{}", capability, device.id, sensor.no) }
+                    let mut records = get_record_history( &client, &config.base_uri, &config.access_token, &device.id, sensor.no,
+                                                          *capability, start, end, ARGS.page_size).await?;
+                    match device_records.iter_mut().find( |(c,_)| c == capability) {
+                        Some((_,list)) => list.append( &mut records),
+                        None => device_records.push( (*capability, records))
+                    }
+                }
+            }
+        }
+
+        privacy.filter_json_records( &mut device_records);
+        device_records.retain( |(c,_)| capabilities.is_empty() || capabilities.contains( c));
+        for (capability, records) in &device_records {
+            paths.append( &mut write_partitioned( &ARGS.output, *capability, records)?);
+        }
+    }
+    Ok(paths)
+}
+
+/// person records determine which images have to be filtered, i.e. we need them if we export images
+fn is_image_evidence (capability: SensorCapability, capabilities: &[SensorCapability])->bool {
+    capability == SensorCapability::Person && capabilities.contains( &SensorCapability::Image)
+}
@@ -21,7 +21,11 @@ extern crate lazy_static;
 
 use std::{process::Output, path::PathBuf, str::FromStr, fmt::{Display,Formatter}, fs::File, io::Write};
 
-use odin_sentinel::{SentinelConfig,init_sentinel_store_from_config};
+use chrono::{DateTime,Utc};
+use odin_sentinel::{SentinelConfig,SentinelStore,init_sentinel_store_from_config,geojson::GeoJsonOpts};
+use odin_sentinel::{timeline::TimeWindow,czml::CzmlOpts,kml::KmlOpts,SensorCapability};
+use odin_sentinel::units::{Units,TemperatureUnit,SpeedUnit,PotentialUnit,CurrentUnit};
+use odin_sentinel::privacy::{PrivacyFilter,PrivacyPolicy};
 use anyhow::Result;
 use odin_config::load_config;
 use structopt::StructOpt;
@@ -37,7 +41,7 @@ struct ParseArgError(String);
 
 #[derive(Debug,EnumString)]
 #[strum(serialize_all="snake_case")]
-enum OutputFormat { Rust, Ron, Json }
+enum OutputFormat { Rust, Ron, Json, Geojson, Czml, Kml, Csv }
 
 
 #[derive(StructOpt)]
@@ -51,11 +55,51 @@ struct CliOpts {
     #[structopt(short,long)]
     pretty: bool,
 
-    /// output format (rust,ron,json)
+    /// output format (rust,ron,json,geojson,czml,kml,csv)
     #[structopt(short,long,default_value="rust")]
     format: OutputFormat,
 
-    /// optional path where to store output
+    /// optional checkpoint (*.json) or record archive (*.jsonl) to read instead of querying the server
+    #[structopt(short,long)]
+    input: Option<PathBuf>,
+
+    /// start of time window for czml/kml output (RFC 3339, defaults to the first record)
+    #[structopt(long)]
+    start: Option<DateTime<Utc>>,
+
+    /// end of time window for czml/kml output (RFC 3339, defaults to the last record)
+    #[structopt(long)]
+    end: Option<DateTime<Utc>>,
+
+    /// include fire/smoke detection features in geojson output
+    #[structopt(long)]
+    detections: bool,
+
+    /// comma separated list of capabilities for csv output (e.g. "thermometer,power"), defaults to all
+    #[structopt(long)]
+    capabilities: Option<String>,
+
+    /// temperature unit for csv output (K,C,F)
+    #[structopt(long,default_value="C")]
+    temperature_unit: TemperatureUnit,
+
+    /// speed unit for csv output (m/s,km/h,mph)
+    #[structopt(long,default_value="m/s")]
+    speed_unit: SpeedUnit,
+
+    /// voltage unit for csv output (V,mV)
+    #[structopt(long,default_value="V")]
+    voltage_unit: PotentialUnit,
+
+    /// current unit for csv output (A,mA)
+    #[structopt(long,default_value="A")]
+    current_unit: CurrentUnit,
+
+    /// privacy policy config (*.ron) for person records and their images, defaults to redacting all person images
+    #[structopt(long)]
+    privacy: Option<PathBuf>,
+
+    /// optional path where to store output (the output directory for csv files, defaults to current dir)
     #[structopt(short,long)]
     output: Option<PathBuf>,
 
@@ -72,16 +116,43 @@ lazy_static! {
 #[tokio::main]
 async fn main()->Result<()> {
     let sentinel_config: SentinelConfig = load_config( &ARGS.config_path)?;
-    let http_client = reqwest::Client::new();
-
-    let sentinel_store = init_sentinel_store_from_config( &http_client, &sentinel_config).await?;
+    let mut sentinel_store = if let Some(path) = &ARGS.input {
+        if path.extension().map_or( false, |ext| ext == "jsonl") {
+            SentinelStore::load_record_archive( path)?
+        } else {
+            SentinelStore::load( path)?
+        }
+    } else {
+        let http_client = reqwest::Client::new();
+        init_sentinel_store_from_config( &http_client, &sentinel_config).await?
+    };
+    PrivacyFilter::new( privacy_policy()?).filter_store( &mut sentinel_store);
 
     match ARGS.format {
         OutputFormat::Json => {
-            produce_output( sentinel_store.to_json( ARGS.pretty)?);
+            produce_output( sentinel_store.to_json( ARGS.pretty)?)?;
         },
         OutputFormat::Ron => {
-            produce_output( sentinel_store.to_ron( ARGS.pretty)?);
+            produce_output( sentinel_store.to_ron( ARGS.pretty)?)?;
+        },
+        OutputFormat::Geojson => {
+            let opts = GeoJsonOpts::from_config( &sentinel_config, ARGS.detections);
+            produce_output( sentinel_store.to_geojson( &opts, ARGS.pretty)?)?;
+        },
+        OutputFormat::Czml => {
+            let window = get_time_window( &sentinel_store)?;
+            produce_output( sentinel_store.to_czml( &window, &CzmlOpts::default(), ARGS.pretty)?)?;
+        },
+        OutputFormat::Kml => {
+            let window = get_time_window( &sentinel_store)?;
+            produce_output( sentinel_store.to_kml( &window, &KmlOpts::default()))?;
+        },
+        OutputFormat::Csv => {
+            let units = Units { temperature: ARGS.temperature_unit, speed: ARGS.speed_unit, potential: ARGS.voltage_unit, current: ARGS.current_unit };
+            let dir = ARGS.output.clone().unwrap_or_else( || PathBuf::from("."));
+            for path in sentinel_store.export_csv( &dir, &SensorCapability::from_names( ARGS.capabilities.as_deref().unwrap_or(""))?, &units)? {
+                println!("{}", path.display());
+            }
         },
         OutputFormat::Rust => {
             if ARGS.pretty {
@@ -95,6 +166,20 @@ async fn main()->Result<()> {
     Ok(())
 }
 
+fn privacy_policy ()->Result<PrivacyPolicy> {
+    Ok( match &ARGS.privacy {
+        Some(path) => load_config( path)?,
+        None => PrivacyPolicy::redact_all()
+    })
+}
+
+fn get_time_window (sentinel_store: &SentinelStore)->Result<TimeWindow> {
+    let covering = TimeWindow::covering( sentinel_store);
+    let start = ARGS.start.or( covering.map( |w| w.start)).ok_or( anyhow::anyhow!("no start time"))?;
+    let end = ARGS.end.or( covering.map( |w| w.end)).ok_or( anyhow::anyhow!("no end time"))?;
+    Ok(TimeWindow::new( start, end))
+}
+
 fn produce_output (s: String)->Result<()> {
     if let Some(path) = &ARGS.output {
         let mut file = File::create(path)?;
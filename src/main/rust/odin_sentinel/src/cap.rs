@@ -0,0 +1,298 @@
+/*
+ * Copyright (c) 2024, United States Government, as represented by the
+ * Administrator of the National Aeronautics and Space Administration.
+ * All rights reserved.
+ *
+ * The RACE - Runtime for Airspace Concept Evaluation platform is licensed
+ * under the Apache License, Version 2.0 (the "License"); you may not use
+ * this file except in compliance with the License. You may obtain a copy
+ * of the License at http://www.apache.org/licenses/LICENSE-2.0.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Common Alerting Protocol (CAP 1.2) output for fire and smoke alarms. The CapPublisher actor mirrors the
+//! SentinelConnector state and issues a new alert if the alert state of a device/capability changes, an Update
+//! if it changes while an alert is active and a Cancel once it drops back to normal
+
+use std::{collections::HashMap,path::PathBuf,sync::Arc,fmt::Write};
+use chrono::{DateTime,Utc};
+use serde::{Deserialize,Serialize};
+use reqwest::Client;
+use odin_actor::prelude::*;
+use odin_actor::tokio_kanal::{Actor,ActorHandle};
+use crate::*;
+use crate::actor::{SentinelConnectorMsg,AddInitCallback,SentinelsInitialized,SentinelJsonSnapshot,SentinelRecordUpdate,
+                   subscribe_mirror};
+use crate::timeline::{AlertState,AlertThresholds};
+use crate::position::{PositionConfig,PositionEstimate};
+
+const EARTH_RADIUS_KM: f64 = 6371.0;
+
+#[derive(Deserialize,Serialize,Debug,Clone)]
+pub enum CapArea {
+    Circle { radius_km: f64 },
+    Polygon { radius_km: f64, n_points: usize }, // regular polygon approximating a circle
+}
+
+#[derive(Deserialize,Serialize,Debug,Clone)]
+pub enum CapOutput {
+    Directory(PathBuf), // write each alert as <identifier>.xml
+    HttpPost(String),   // POST each alert to this URI
+    Callbacks,          // only trigger CAP callbacks (see AddCapCallback)
+}
+
+#[derive(Deserialize,Serialize,Debug,Clone)]
+pub struct CapConfig {
+    pub sender: String,                 // CAP sender id, e.g. "odin@example.org"
+    pub sender_name: String,            // human readable sender
+    pub thresholds: AlertThresholds,    // probabilities for Moderate (warning) and Severe (alert)
+    pub extreme_prob: f64,              // probability for Extreme severity / Observed certainty
+    pub area: CapArea,
+    pub image_base_uri: Option<String>, // used to turn image filenames into resource URIs
+    pub outputs: Vec<CapOutput>,
+    #[serde(default)]
+    pub position: PositionConfig,       // how we estimate sentinel positions from GPS fixes
+}
+
+/* #region CAP XML *******************************************************************************/
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum CapMsgType { Alert, Update, Cancel }
+
+pub fn severity (config: &CapConfig, prob: f64)->&'static str {
+    if prob >= config.extreme_prob { "Extreme" }
+    else if prob >= config.thresholds.alert { "Severe" }
+    else if prob >= config.thresholds.warning { "Moderate" }
+    else { "Minor" }
+}
+
+pub fn certainty (config: &CapConfig, prob: f64)->&'static str {
+    if prob >= config.extreme_prob { "Observed" }
+    else if prob >= config.thresholds.alert { "Likely" }
+    else if prob >= config.thresholds.warning { "Possible" }
+    else { "Unlikely" }
+}
+
+fn cap_time (t: DateTime<Utc>)->String {
+    t.to_rfc3339_opts( chrono::SecondsFormat::Secs, false) // CAP does not allow 'Z'
+}
+
+/// the CAP area element for a sentinel position
+pub fn cap_area (area: &CapArea, device_name: &str, pos: &PositionEstimate)->String {
+    let lat = pos.latitude;
+    let lon = pos.longitude;
+    let mut s = format!("<area><areaDesc>vicinity of sentinel {}</areaDesc>", xml_escape( device_name));
+
+    match area {
+        CapArea::Circle { radius_km } => {
+            write!( s, "<circle>{lat},{lon} {radius_km}</circle>").ok();
+        }
+        CapArea::Polygon { radius_km, n_points } => {
+            let n = (*n_points).max(3);
+            let dlat = (radius_km / EARTH_RADIUS_KM).to_degrees();
+            let dlon = dlat / lat.to_radians().cos();
+            let mut pts: Vec<String> = (0..n).map( |i| {
+                let a = (i as f64) * std::f64::consts::TAU / (n as f64);
+                format!("{:.6},{:.6}", lat + dlat * a.cos(), lon + dlon * a.sin())
+            }).collect();
+            pts.push( pts[0].clone()); // CAP polygons have to be closed
+            write!( s, "<polygon>{}</polygon>", pts.join(" ")).ok();
+        }
+    }
+    if let Some(alt) = pos.altitude {
+        write!( s, "<altitude>{}</altitude>", alt * 3.28084).ok(); // CAP altitude is in feet
+    }
+    s.push_str("</area>");
+    s
+}
+
+fn mime_type (filename: &str)->&'static str {
+    let ext = filename.rsplit('.').next().unwrap_or("").to_lowercase();
+    match ext.as_str() {
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "webp" => "image/webp",
+        "mp4" => "video/mp4",
+        _ => "application/octet-stream"
+    }
+}
+
+/// the image evidence resources of a record
+fn cap_resources (config: &CapConfig, sentinel: &Sentinel, evidences: &Vec<RecordId>)->String {
+    let mut s = String::new();
+    for img in evidences.iter().filter_map( |e| sentinel.image_record(e)) {
+        let desc = if img.data.is_infrared { "infrared image" } else { "visible image" };
+        write!( s, "<resource><resourceDesc>{}</resourceDesc><mimeType>{}</mimeType>", desc, mime_type( &img.data.filename)).ok();
+        if let Some(base_uri) = &config.image_base_uri {
+            write!( s, "<uri>{}/{}</uri>", xml_escape( base_uri.trim_end_matches('/')), xml_escape( &img.data.filename)).ok();
+        }
+        s.push_str("</resource>");
+    }
+    s
+}
+
+/// alert identifiers keep the device id case since device ids are case sensitive
+pub fn alert_identifier (config: &CapConfig, device_id: &str, capability: SensorCapability, sent: DateTime<Utc>)->String {
+    format!("{}-{}-{}-{}", config.sender, device_id, capability.property_name(), sent.timestamp_millis())
+}
+
+/// CAP references are "sender,identifier,sent" triplets
+pub fn alert_reference (config: &CapConfig, identifier: &str, sent: DateTime<Utc>)->String {
+    format!("{},{},{}", config.sender, identifier, cap_time( sent))
+}
+
+/// the CAP alert for the latest fire or smoke record of a sentinel, or None if there is no such record or position
+pub fn cap_alert (config: &CapConfig, sentinel: &Sentinel, capability: SensorCapability, 
+                  identifier: &str, sent: DateTime<Utc>, msg_type: CapMsgType, references: Option<&str>)->Option<String> {
+    let (prob, time, evidences, event) = match capability {
+        SensorCapability::Fire => sentinel.fire.front().map( |r| (r.data.confidence(), r.time_recorded, &r.evidences, "Fire detection"))?,
+        SensorCapability::Smoke => sentinel.smoke.front().map( |r| (r.data.confidence(), r.time_recorded, &r.evidences, "Smoke detection"))?,
+        _ => return None
+    };
+    let pos = sentinel.position( &config.position)?;
+    let name = xml_escape( &sentinel.device_name);
+
+    let mut s = String::new();
+    s.push_str( r#"<?xml version="1.0" encoding="UTF-8"?><alert xmlns="urn:oasis:names:tc:emergency:cap:1.2">"#);
+    write!( s, "<identifier>{}</identifier><sender>{}</sender><sent>{}</sent>", 
+            xml_escape( identifier), xml_escape( &config.sender), cap_time( sent)).ok();
+    write!( s, "<status>Actual</status><msgType>{:?}</msgType><scope>Public</scope>", msg_type).ok();
+    if let Some(references) = references {
+        write!( s, "<references>{}</references>", xml_escape( references)).ok();
+    }
+
+    s.push_str("<info><language>en-US</language><category>Fire</category>");
+    write!( s, "<event>{event}</event>").ok();
+    if msg_type == CapMsgType::Cancel {
+        s.push_str("<responseType>AllClear</responseType><urgency>Past</urgency>");
+    } else {
+        s.push_str("<responseType>Monitor</responseType><urgency>Immediate</urgency>");
+    }
+    write!( s, "<severity>{}</severity><certainty>{}</certainty>", severity( config, prob), certainty( config, prob)).ok();
+    write!( s, "<effective>{}</effective><senderName>{}</senderName>", cap_time( time), xml_escape( &config.sender_name)).ok();
+    write!( s, "<headline>{} at sentinel {}</headline>", event, name).ok();
+    write!( s, "<description>{} probability {:.2} reported by sentinel {} ({}) at {}</description>",
+            event, prob, name, xml_escape( &sentinel.device_id), cap_time( time)).ok();
+    write!( s, "<parameter><valueName>probability</valueName><value>{:.3}</value></parameter>", prob).ok();
+    s.push_str( &cap_resources( config, sentinel, evidences));
+    s.push_str( &cap_area( &config.area, &sentinel.device_name, &pos));
+    s.push_str("</info></alert>");
+
+    Some(s)
+}
+
+/* #endregion CAP XML */
+
+/* #region CAP publisher actor *******************************************************************/
+
+#[derive(Debug)] pub struct AddCapCallback { pub id: String, pub action: Callback<Arc<String>> }
+
+define_actor_msg_type! { pub CapPublisherMsg = SentinelsInitialized | SentinelJsonSnapshot | SentinelRecordUpdate | AddCapCallback }
+
+/// the last alert we issued for a device/capability
+struct ActiveAlert {
+    state: AlertState,
+    identifier: String,
+    sent: DateTime<Utc>,
+}
+
+pub struct CapPublisher {
+    config: CapConfig,
+    hconn: ActorHandle<SentinelConnectorMsg>,
+    sentinels: SentinelStore, // our mirror of the connector state
+    alerts: HashMap<(DeviceId,SensorCapability),ActiveAlert>,
+    http_client: Client,
+    cap_callbacks: CallbackList<Arc<String>>,
+}
+
+impl CapPublisher {
+    pub fn new (config: CapConfig, hconn: ActorHandle<SentinelConnectorMsg>)->Self {
+        CapPublisher { config, hconn, sentinels: SentinelStore::new(), alerts: HashMap::new(), http_client: Client::new(), cap_callbacks: CallbackList::new() }
+    }
+
+    async fn update (&mut self, update: &SentinelUpdate)->Result<()> {
+        let (device_id, capability) = self.sentinels.add_update( update.clone());
+        if capability != SensorCapability::Fire && capability != SensorCapability::Smoke { return Ok(()) }
+
+        let sentinel = self.sentinels.get( &device_id).ok_or( OdinSentinelError::NoSuchDeviceError( device_id.clone()))?;
+        let prob = match capability {
+            SensorCapability::Fire => sentinel.fire.front().map( |r| r.data.confidence()),
+            _ => sentinel.smoke.front().map( |r| r.data.confidence()),
+        }.unwrap_or(0.0);
+        let state = self.config.thresholds.state( prob);
+
+        let key = (device_id, capability);
+        let prev = self.alerts.get( &key);
+        let msg_type = match (prev, state) {
+            (None, AlertState::Normal) => return Ok(()),
+            (None, _) => CapMsgType::Alert,
+            (Some(a), s) if a.state == s => return Ok(()),
+            (Some(_), AlertState::Normal) => CapMsgType::Cancel,
+            (Some(_), _) => CapMsgType::Update,
+        };
+
+        let sent = Utc::now();
+        let identifier = alert_identifier( &self.config, &key.0, capability, sent);
+        let references = prev.map( |a| alert_reference( &self.config, &a.identifier, a.sent));
+
+        if let Some(cap) = cap_alert( &self.config, sentinel, capability, &identifier, sent, msg_type, references.as_deref()) {
+            self.publish( &identifier, cap).await;
+            if msg_type == CapMsgType::Cancel {
+                self.alerts.remove( &key);
+            } else {
+                self.alerts.insert( key, ActiveAlert { state, identifier, sent });
+            }
+        }
+        Ok(())
+    }
+
+    async fn publish (&mut self, identifier: &str, cap: String) {
+        for output in &self.config.outputs {
+            let res = match output {
+                CapOutput::Directory(dir) => {
+                    let path = dir.join( format!("{}.xml", identifier.replace( |c: char| !c.is_ascii_alphanumeric() && c != '-', "_")));
+                    std::fs::write( path, &cap).map_err( OdinSentinelError::from)
+                }
+                CapOutput::HttpPost(uri) => {
+                    self.http_client.post( uri).header( "Content-Type", "application/cap+xml").body( cap.clone()).send().await
+                        .and_then( |r| r.error_for_status()).map( |_| ()).map_err( OdinSentinelError::from)
+                }
+                CapOutput::Callbacks => Ok(())
+            };
+            if let Err(e) = res {
+                eprintln!("@@ failed to publish CAP alert {}: {:?}", identifier, e);
+            }
+        }
+
+        self.cap_callbacks.trigger( Arc::new(cap)).await;
+    }
+}
+
+impl_actor! { match msg for Actor<CapPublisher,CapPublisherMsg> as
+    _Start_ => cont! {
+        let id = self.id().to_string();
+        let hself = &self.hself;
+        self.hconn.send_msg( AddInitCallback{id, action: msg_callback!(hself, SentinelsInitialized)}).await.ok();
+    }
+    SentinelsInitialized => cont! {
+        subscribe_mirror!( self.hconn, &self.hself, self.id().to_string());
+    }
+    SentinelJsonSnapshot => cont! { // we only alert on new records - the snapshot just gives us positions and images
+        if let Some(sentinels) = msg.sentinels() { self.sentinels = sentinels }
+    }
+    SentinelRecordUpdate => cont! {
+        if let Err(e) = self.update( &msg.0).await {
+            eprintln!("@@ failed to process sentinel update: {:?}", e);
+        }
+    }
+    AddCapCallback => cont! {
+        self.cap_callbacks.add( msg.id, msg.action)
+    }
+}
+
+/* #endregion CAP publisher actor */
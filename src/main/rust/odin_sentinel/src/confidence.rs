@@ -0,0 +1,114 @@
+/*
+ * Copyright (c) 2024, United States Government, as represented by the
+ * Administrator of the National Aeronautics and Space Administration.
+ * All rights reserved.
+ *
+ * The RACE - Runtime for Airspace Concept Evaluation platform is licensed
+ * under the Apache License, Version 2.0 (the "License"); you may not use
+ * this file except in compliance with the License. You may obtain a copy
+ * of the License at http://www.apache.org/licenses/LICENSE-2.0.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! cloud cover aware confidence of camera based detections. Cameras report CloudcoverData, and fire/smoke
+//! detections under heavy cloud cover or fog are less reliable. We annotate FireData and SmokeData records with
+//! an effective probability that is the raw probability multiplied by a weighting factor, which is interpolated from a
+//! configurable curve for the most recent cloud cover of the sensors that produced the detection (its evidence images,
+//! or the detection sensor itself). Alarm thresholds act on `FireData::confidence()` / `SmokeData::confidence()`
+
+use std::time::Duration;
+use chrono::{DateTime,Utc};
+use serde::{Deserialize,Serialize};
+use crate::*;
+
+#[derive(Deserialize,Serialize,Debug,Clone)]
+pub struct CloudWeighting {
+    pub curve: Vec<(f64,f64)>, // (cloud cover percent, factor) points in ascending percent order. Empty means no weighting
+    pub max_age: Duration,     // cloud cover records older than this (relative to the detection) are ignored
+}
+
+impl Default for CloudWeighting {
+    fn default()->Self {
+        CloudWeighting { 
+            curve: vec![ (0.0, 1.0), (50.0, 0.9), (80.0, 0.6), (100.0, 0.3) ],
+            max_age: Duration::from_secs(3600)
+        }
+    }
+}
+
+impl CloudWeighting {
+    /// piecewise linear interpolation of the curve, clamped to its end points
+    pub fn factor (&self, percent: f64)->f64 {
+        let (first, last) = match (self.curve.first(), self.curve.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return 1.0
+        };
+        if percent <= first.0 { return first.1 }
+        if percent >= last.0 { return last.1 }
+
+        for w in self.curve.windows(2) {
+            let ((p0,f0), (p1,f1)) = (w[0], w[1]);
+            if percent <= p1 {
+                return if p1 > p0 { f0 + (f1 - f0) * (percent - p0) / (p1 - p0) } else { f1 }
+            }
+        }
+        last.1
+    }
+}
+
+/// the most recent cloud cover (percent) reported by one of the given sensors within max_age before t
+pub fn cloud_cover (sentinel: &Sentinel, sensor_nos: &[u32], t: DateTime<Utc>, max_age: Duration)->Option<f64> {
+    let start = t - chrono::Duration::from_std( max_age).ok()?;
+    sentinel.records::<CloudcoverData>().iter()
+        .find( |r| sensor_nos.contains( &r.sensor_no) && r.time_recorded <= t && r.time_recorded >= start)
+        .map( |r| r.data.percent as f64)
+}
+
+/// the sensors that produced a detection - the cameras of its evidence images and the detection sensor itself
+fn detection_sensors (sentinel: &Sentinel, sensor_no: u32, evidences: &[RecordId])->Vec<u32> {
+    let mut sensor_nos: Vec<u32> = evidences.iter().filter_map( |e| sentinel.image_record( e)).map( |img| img.sensor_no).collect();
+    sensor_nos.push( sensor_no);
+    sensor_nos
+}
+
+/// the cloud cover adjusted probability of a detection, or None if there is no applicable cloud cover record
+pub fn effective_prob<T> (sentinel: &Sentinel, weighting: &CloudWeighting, rec: &SensorRecord<T>, prob: f64)->Option<f64> where T: RecordDataBounds {
+    let sensor_nos = detection_sensors( sentinel, rec.sensor_no, &rec.evidences);
+    let percent = cloud_cover( sentinel, &sensor_nos, rec.time_recorded, weighting.max_age)?;
+    Some( (prob * weighting.factor( percent)).clamp( 0.0, 1.0))
+}
+
+impl SentinelUpdate {
+    /// set the effective probability of fire and smoke records
+    pub fn annotate_confidence (&mut self, sentinel: &Sentinel, weighting: &CloudWeighting) {
+        match self {
+            SentinelUpdate::Fire(rec) => rec.data.effective_prob = effective_prob( sentinel, weighting, rec, rec.data.fire_prob),
+            SentinelUpdate::Smoke(rec) => rec.data.effective_prob = effective_prob( sentinel, weighting, rec, rec.data.smoke_prob),
+            _ => {}
+        }
+    }
+}
+
+impl Sentinel {
+    /// set the effective probabilities of all fire and smoke records
+    pub fn annotate_confidences (&mut self, weighting: &CloudWeighting) {
+        let fire: Vec<Option<f64>> = self.fire.iter().map( |r| effective_prob( self, weighting, r, r.data.fire_prob)).collect();
+        for (rec,p) in self.fire.iter_mut().zip( fire) { rec.data.effective_prob = p }
+
+        let smoke: Vec<Option<f64>> = self.smoke.iter().map( |r| effective_prob( self, weighting, r, r.data.smoke_prob)).collect();
+        for (rec,p) in self.smoke.iter_mut().zip( smoke) { rec.data.effective_prob = p }
+    }
+}
+
+impl SentinelStore {
+    pub fn annotate_confidences (&mut self, weighting: &CloudWeighting) {
+        for sentinel in self.sentinels.values_mut() {
+            sentinel.annotate_confidences( weighting);
+        }
+    }
+}
@@ -0,0 +1,273 @@
+/*
+ * Copyright (c) 2024, United States Government, as represented by the
+ * Administrator of the National Aeronautics and Space Administration.
+ * All rights reserved.
+ *
+ * The RACE - Runtime for Airspace Concept Evaluation platform is licensed
+ * under the Apache License, Version 2.0 (the "License"); you may not use
+ * this file except in compliance with the License. You may obtain a copy
+ * of the License at http://www.apache.org/licenses/LICENSE-2.0.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Cursor-on-Target (CoT) output for TAK clients. The CotPublisher actor mirrors the SentinelConnector state
+//! and sends a position event for each GPS update and alarm events for fire/smoke records that exceed the
+//! configured thresholds, using stable UIDs per device. Once the probability drops back below the thresholds
+//! we send a clearing event that is already stale so that TAK clients remove the alarm.
+//! Event times are send times (TAK clients drop events that are stale on arrival), the record times are
+//! reported in the event remarks
+
+use std::{collections::HashSet,net::SocketAddr,time::Duration};
+use chrono::{DateTime,Utc};
+use serde::{Deserialize,Serialize};
+use tokio::{net::{UdpSocket,TcpStream},io::AsyncWriteExt};
+use odin_actor::prelude::*;
+use odin_actor::tokio_kanal::{Actor,ActorHandle};
+use crate::*;
+use crate::actor::{SentinelConnectorMsg,AddInitCallback,SentinelsInitialized,SentinelJsonSnapshot,SentinelRecordUpdate,
+                   subscribe_mirror};
+use crate::timeline::{AlertState,AlertThresholds};
+use crate::position::{PositionConfig,PositionEstimate};
+
+const UNKNOWN_ERROR: f64 = 9999999.0; // CoT value for unknown hae/ce/le
+
+#[derive(Deserialize,Serialize,Debug,Clone)]
+pub enum CotEndpoint {
+    Udp(String), // unicast or multicast "<addr>:<port>"
+    Tcp(String), // "<host>:<port>" of a TAK server/client that accepts CoT streams
+}
+
+#[derive(Deserialize,Serialize,Debug,Clone)]
+pub struct CotConfig {
+    pub endpoint: CotEndpoint,
+    pub multicast_ttl: Option<u32>,
+    pub uid_prefix: String,        // prepended to device ids to form stable event UIDs
+    pub position_type: String,     // CoT type of sentinel position events (e.g. "a-f-G-E-S")
+    pub alarm_type: String,        // CoT type of fire/smoke alarm events
+    pub stale: Duration,           // how long events are valid
+    pub thresholds: AlertThresholds, // fire/smoke probabilities for alarms
+    #[serde(default)]
+    pub position: PositionConfig,  // how we estimate sentinel positions from GPS fixes
+}
+
+/* #region CoT XML *******************************************************************************/
+
+fn cot_time (t: DateTime<Utc>)->String {
+    t.to_rfc3339_opts( chrono::SecondsFormat::Millis, true)
+}
+
+fn cot_point (pos: &PositionEstimate)->String {
+    let hae = pos.altitude.unwrap_or( UNKNOWN_ERROR);
+    format!(r#"<point lat="{}" lon="{}" hae="{}" ce="{}" le="{}"/>"#, 
+            pos.latitude, pos.longitude, hae, pos.accuracy, UNKNOWN_ERROR)
+}
+
+fn cot_event (uid: &str, cot_type: &str, stale: Duration, pos: &PositionEstimate, callsign: &str, remarks: &str)->String {
+    let time = Utc::now();
+    let stale_time = time + chrono::Duration::from_std( stale).unwrap_or( chrono::Duration::minutes(5));
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><event version="2.0" uid="{}" type="{}" how="m-g" time="{}" start="{}" stale="{}">{}<detail><contact callsign="{}"/><remarks>{}</remarks></detail></event>"#,
+        xml_escape(uid), xml_escape(cot_type), cot_time(time), cot_time(time), cot_time(stale_time),
+        cot_point(pos), xml_escape(callsign), xml_escape(remarks)
+    )
+}
+
+pub fn position_uid (config: &CotConfig, device_id: &str)->String {
+    format!("{}{}", config.uid_prefix, device_id)
+}
+
+/// alarm UIDs extend the position UID of the device (which keeps the device id case) with the capability name
+pub fn alarm_uid (config: &CotConfig, device_id: &str, capability: SensorCapability)->String {
+    format!("{}-{}", position_uid( config, device_id), capability.property_name())
+}
+
+/// the position event for the estimated position of a sentinel, or None if it has no GPS fix yet
+pub fn position_event (config: &CotConfig, sentinel: &Sentinel)->Option<String> {
+    let pos = sentinel.position( &config.position)?;
+    let remarks = format!("sentinel {} fix at {}", sentinel.device_id, cot_time( pos.time_recorded));
+    Some( cot_event( &position_uid( config, &sentinel.device_id), &config.position_type, config.stale, &pos, &sentinel.device_name, &remarks))
+}
+
+/// probability, state and time of the latest fire or smoke record of a sentinel
+fn latest_alarm_state (config: &CotConfig, sentinel: &Sentinel, capability: SensorCapability)->Option<(f64,AlertState,DateTime<Utc>)> {
+    let (prob, time) = match capability {
+        SensorCapability::Fire => sentinel.fire.front().map( |r| (r.data.confidence(), r.time_recorded))?,
+        SensorCapability::Smoke => sentinel.smoke.front().map( |r| (r.data.confidence(), r.time_recorded))?,
+        _ => return None
+    };
+    Some( (prob, config.thresholds.state( prob), time))
+}
+
+fn alarm_remarks (state: AlertState, capability: SensorCapability, prob: f64, time: DateTime<Utc>)->String {
+    let state = format!("{:?}", state).to_lowercase();
+    format!("{} {} probability {:.2} at {}", state, capability.property_name(), prob, cot_time( time))
+}
+
+/// the alarm event for the latest fire or smoke record of a sentinel, or None if there is no position or
+/// the probability is below the warning threshold
+pub fn alarm_event (config: &CotConfig, sentinel: &Sentinel, capability: SensorCapability)->Option<String> {
+    let (prob, state, time) = latest_alarm_state( config, sentinel, capability)?;
+    if state == AlertState::Normal { return None }
+
+    let pos = sentinel.position( &config.position)?;
+    let callsign = format!("{} {}", sentinel.device_name, capability.property_name());
+    let remarks = alarm_remarks( state, capability, prob, time);
+    Some( cot_event( &alarm_uid( config, &sentinel.device_id, capability), &config.alarm_type, config.stale, &pos, &callsign, &remarks))
+}
+
+/// the event that clears a previous alarm of a sentinel, which has the alarm UID and is stale when sent.
+/// Returns None if there is no position or the latest fire or smoke record still exceeds the warning threshold
+pub fn clear_event (config: &CotConfig, sentinel: &Sentinel, capability: SensorCapability)->Option<String> {
+    let (prob, state, time) = latest_alarm_state( config, sentinel, capability)?;
+    if state != AlertState::Normal { return None }
+
+    let pos = sentinel.position( &config.position)?;
+    let callsign = format!("{} {}", sentinel.device_name, capability.property_name());
+    let remarks = alarm_remarks( state, capability, prob, time);
+    Some( cot_event( &alarm_uid( config, &sentinel.device_id, capability), &config.alarm_type, Duration::ZERO, &pos, &callsign, &remarks))
+}
+
+/* #endregion CoT XML */
+
+/* #region CoT sender ****************************************************************************/
+
+pub enum CotSender {
+    Udp(UdpSocket, SocketAddr),
+    Tcp(TcpStream),
+}
+
+impl CotSender {
+    pub async fn connect (endpoint: &CotEndpoint, multicast_ttl: Option<u32>)->Result<Self> {
+        match endpoint {
+            CotEndpoint::Udp(addr) => {
+                let addr: SocketAddr = addr.parse().map_err( |e| op_failed( format!("invalid CoT address {addr}: {e}")))?;
+                let bind_addr = if addr.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+                let socket = UdpSocket::bind( bind_addr).await?;
+                if let (true, Some(ttl)) = (addr.ip().is_multicast(), multicast_ttl) {
+                    if addr.is_ipv4() {
+                        socket.set_multicast_ttl_v4( ttl)?;
+                    } else {
+                        socket2::SockRef::from( &socket).set_multicast_hops_v6( ttl)?; // tokio only exposes the v4 TTL
+                    }
+                }
+                Ok( CotSender::Udp( socket, addr))
+            }
+            CotEndpoint::Tcp(addr) => {
+                Ok( CotSender::Tcp( TcpStream::connect( addr.as_str()).await?))
+            }
+        }
+    }
+
+    pub async fn send (&mut self, event: &str)->Result<()> {
+        match self {
+            CotSender::Udp(socket, addr) => { socket.send_to( event.as_bytes(), *addr).await?; }
+            CotSender::Tcp(stream) => { stream.write_all( event.as_bytes()).await?; }
+        }
+        Ok(())
+    }
+}
+
+/* #endregion CoT sender */
+
+/* #region CoT publisher actor *******************************************************************/
+
+define_actor_msg_type! { pub CotPublisherMsg = SentinelsInitialized | SentinelJsonSnapshot | SentinelRecordUpdate }
+
+pub struct CotPublisher {
+    config: CotConfig,
+    hconn: ActorHandle<SentinelConnectorMsg>,
+    sentinels: SentinelStore, // our mirror of the connector state
+    sender: Option<CotSender>, // (re-)connected on demand
+    alarms: HashSet<String>, // UIDs of alarms we have sent and not cleared yet
+}
+
+impl CotPublisher {
+    pub fn new (config: CotConfig, hconn: ActorHandle<SentinelConnectorMsg>)->Self {
+        CotPublisher { config, hconn, sentinels: SentinelStore::new(), sender: None, alarms: HashSet::new() }
+    }
+
+    /// the alarm event if the latest fire/smoke record exceeds the thresholds, or the clearing event if it does not
+    /// but we have sent an alarm for it before
+    fn alarm_update (config: &CotConfig, alarms: &mut HashSet<String>, sentinel: &Sentinel, capability: SensorCapability)->Option<String> {
+        if let Some(event) = alarm_event( config, sentinel, capability) {
+            alarms.insert( alarm_uid( config, &sentinel.device_id, capability));
+            Some(event)
+        } else if alarms.remove( &alarm_uid( config, &sentinel.device_id, capability)) {
+            clear_event( config, sentinel, capability)
+        } else {
+            None
+        }
+    }
+
+    async fn send_event (&mut self, event: String) {
+        if self.sender.is_none() {
+            match CotSender::connect( &self.config.endpoint, self.config.multicast_ttl).await {
+                Ok(sender) => self.sender = Some(sender),
+                Err(e) => { eprintln!("@@ failed to connect CoT endpoint: {:?}", e); return }
+            }
+        }
+
+        if let Some(sender) = &mut self.sender {
+            if let Err(e) = sender.send( &event).await {
+                eprintln!("@@ failed to send CoT event: {:?}", e);
+                self.sender = None; // reconnect on next event
+            }
+        }
+    }
+
+    async fn send_all (&mut self) {
+        let mut events = Vec::new();
+        for sentinel in self.sentinels.values() {
+            events.extend( position_event( &self.config, sentinel));
+            events.extend( Self::alarm_update( &self.config, &mut self.alarms, sentinel, SensorCapability::Fire));
+            events.extend( Self::alarm_update( &self.config, &mut self.alarms, sentinel, SensorCapability::Smoke));
+        }
+        for event in events {
+            self.send_event( event).await;
+        }
+    }
+
+    async fn update (&mut self, update: &SentinelUpdate)->Result<()> {
+        let (device_id, capability) = self.sentinels.add_update( update.clone());
+        let sentinel = self.sentinels.get( &device_id).ok_or( OdinSentinelError::NoSuchDeviceError( device_id.clone()))?;
+
+        let event = match capability {
+            SensorCapability::Gps => position_event( &self.config, sentinel),
+            SensorCapability::Fire | SensorCapability::Smoke => Self::alarm_update( &self.config, &mut self.alarms, sentinel, capability),
+            _ => None
+        };
+        if let Some(event) = event {
+            self.send_event( event).await;
+        }
+        Ok(())
+    }
+}
+
+impl_actor! { match msg for Actor<CotPublisher,CotPublisherMsg> as
+    _Start_ => cont! {
+        let id = self.id().to_string();
+        let hself = &self.hself;
+        self.hconn.send_msg( AddInitCallback{id, action: msg_callback!(hself, SentinelsInitialized)}).await.ok();
+    }
+    SentinelsInitialized => cont! {
+        subscribe_mirror!( self.hconn, &self.hself, self.id().to_string());
+    }
+    SentinelJsonSnapshot => cont! {
+        if let Some(sentinels) = msg.sentinels() {
+            self.sentinels = sentinels;
+            self.send_all().await;
+        }
+    }
+    SentinelRecordUpdate => cont! {
+        if let Err(e) = self.update( &msg.0).await {
+            eprintln!("@@ failed to process sentinel update: {:?}", e);
+        }
+    }
+}
+
+/* #endregion CoT publisher actor */
@@ -0,0 +1,109 @@
+/*
+ * Copyright (c) 2024, United States Government, as represented by the
+ * Administrator of the National Aeronautics and Space Administration.
+ * All rights reserved.
+ *
+ * The RACE - Runtime for Airspace Concept Evaluation platform is licensed
+ * under the Apache License, Version 2.0 (the "License"); you may not use
+ * this file except in compliance with the License. You may obtain a copy
+ * of the License at http://www.apache.org/licenses/LICENSE-2.0.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! CSV export of sensor records, one table per capability. Records are flattened the same way as for
+//! Parquet export, but quantities are converted into user selectable units which are shown in the header row
+//! (e.g. `temperature [°F]`)
+
+use std::{fs,path::{Path,PathBuf}};
+use serde_json::Value;
+use strum::IntoEnumIterator;
+use crate::*;
+use crate::units::{Units,QuantityKind,quantity_kind};
+
+/// RFC 4180 field quoting
+fn csv_field (s: &str)->String {
+    if s.contains( |c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn csv_value (value: Option<&Value>, kind: Option<QuantityKind>, units: &Units)->String {
+    match (value, kind) {
+        (None, _) | (Some(Value::Null), _) => String::new(),
+        (Some(Value::Number(n)), Some(kind)) => n.as_f64().map( |v| format!("{:.3}", units.convert( kind, v))).unwrap_or_default(),
+        (Some(Value::String(s)), _) => csv_field( s),
+        (Some(v), _) => csv_field( &v.to_string())
+    }
+}
+
+/// create a CSV table (with header row) from JSON records of the given capability, sorted by device and time
+pub fn csv_table (capability: SensorCapability, records: &[Value], units: &Units)->String {
+    let mut records: Vec<&Value> = records.iter().filter( |r| json_record_time(r).is_some()).collect();
+    records.sort_by_key( |r| (r.get("deviceId").and_then( |v| v.as_str()).unwrap_or_default().to_string(), json_record_time(r)));
+
+    let mut names: Vec<String> = Vec::new();
+    let mut rows: Vec<Vec<(String,Value)>> = Vec::with_capacity( records.len());
+    for rec in &records {
+        let mut columns = Vec::new();
+        if let Some(payload) = rec.get( capability.property_name()) {
+            flatten_json( "", payload, &mut columns);
+        }
+        for (name,_) in &columns {
+            if !names.contains(name) { names.push( name.clone()) }
+        }
+        rows.push( columns);
+    }
+    let kinds: Vec<Option<QuantityKind>> = names.iter().map( |n| quantity_kind( capability, n)).collect();
+
+    let mut csv = String::from("id,device_id,sensor_no,time_recorded");
+    for (name,kind) in names.iter().zip( kinds.iter()) {
+        csv.push(',');
+        match kind {
+            Some(kind) => csv.push_str( &csv_field( &format!("{} [{}]", name, units.symbol( *kind)))),
+            None => csv.push_str( &csv_field( name))
+        }
+    }
+    csv.push('\n');
+
+    for (rec,row) in records.iter().zip( rows.iter()) {
+        csv.push_str( &csv_value( rec.get("id"), None, units));
+        csv.push(',');
+        csv.push_str( &csv_value( rec.get("deviceId"), None, units));
+        csv.push(',');
+        csv.push_str( &csv_value( rec.get("sensorNo"), None, units));
+        csv.push(',');
+        csv.push_str( &csv_value( rec.get("timeRecorded"), None, units));
+
+        for (name,kind) in names.iter().zip( kinds.iter()) {
+            csv.push(',');
+            csv.push_str( &csv_value( row.iter().find( |(n,_)| n == name).map( |(_,v)| v), *kind, units));
+        }
+        csv.push('\n');
+    }
+    csv
+}
+
+impl SentinelStore {
+    /// write one `{capability}.csv` file per selected capability (all if empty) that has records
+    pub fn export_csv (&self, dir: &Path, capabilities: &[SensorCapability], units: &Units)->Result<Vec<PathBuf>> {
+        fs::create_dir_all( dir)?;
+
+        let mut paths = Vec::new();
+        for capability in SensorCapability::iter().filter( |c| capabilities.is_empty() || capabilities.contains(c)) {
+            let records = self.json_records( capability)?;
+            if !records.is_empty() {
+                let path = dir.join( format!("{}.csv", capability.property_name()));
+                fs::write( &path, csv_table( capability, &records, units))?;
+                paths.push( path);
+            }
+        }
+        Ok(paths)
+    }
+}
@@ -0,0 +1,140 @@
+/*
+ * Copyright (c) 2024, United States Government, as represented by the
+ * Administrator of the National Aeronautics and Space Administration.
+ * All rights reserved.
+ *
+ * The RACE - Runtime for Airspace Concept Evaluation platform is licensed
+ * under the Apache License, Version 2.0 (the "License"); you may not use
+ * this file except in compliance with the License. You may obtain a copy
+ * of the License at http://www.apache.org/licenses/LICENSE-2.0.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! time-dynamic CZML export of sentinel history for Cesium
+
+use serde_json::{json,Value};
+use crate::*;
+use crate::timeline::*;
+use crate::position::PositionConfig;
+
+#[derive(Debug,Clone)]
+pub struct CzmlOpts {
+    pub thresholds: AlertThresholds,
+    pub icon: String,         // billboard image URI
+    pub clock_multiplier: f64,
+    pub position: PositionConfig, // how we estimate sentinel positions from GPS fixes
+}
+
+impl Default for CzmlOpts {
+    fn default()->Self {
+        CzmlOpts { thresholds: AlertThresholds::default(), icon: "sentinel-sym.png".to_string(), clock_multiplier: 60.0, position: PositionConfig::default() }
+    }
+}
+
+/// RGBA billboard color for alert states
+pub fn alert_color (state: AlertState)->[u8;4] {
+    match state {
+        AlertState::Normal  => [0, 255, 0, 255],
+        AlertState::Warning => [255, 255, 0, 255],
+        AlertState::Alert   => [255, 0, 0, 255],
+    }
+}
+
+fn document_packet (window: &TimeWindow, opts: &CzmlOpts)->Value {
+    json!({
+        "id": "document",
+        "name": "sentinels",
+        "version": "1.0",
+        "clock": {
+            "interval": window.to_iso_interval(),
+            "currentTime": window.start.to_rfc3339(),
+            "multiplier": opts.clock_multiplier,
+            "range": "LOOP_STOP",
+            "step": "SYSTEM_CLOCK_MULTIPLIER"
+        }
+    })
+}
+
+/// the packet for a sentinel, or None if we don't have a position for it within the window
+pub fn sentinel_packet (sentinel: &Sentinel, window: &TimeWindow, opts: &CzmlOpts)->Option<Value> {
+    let positions = positions( sentinel, &opts.position, window);
+    if positions.is_empty() { return None }
+
+    // cartographicDegrees samples are [t_secs_since_epoch, lon, lat, alt, ...]
+    let mut samples: Vec<f64> = Vec::with_capacity( positions.len() * 4);
+    for (t, pos) in &positions {
+        samples.push( (*t - window.start).num_milliseconds() as f64 / 1000.0);
+        samples.push( pos.longitude);
+        samples.push( pos.latitude);
+        samples.push( pos.altitude.unwrap_or(0.0));
+    }
+
+    let alerts = alert_intervals( sentinel, window, &opts.thresholds);
+    let colors: Vec<Value> = alerts.iter().map( |a| json!({ 
+        "interval": iso_interval( a.start, a.end), 
+        "rgba": alert_color( a.state) 
+    })).collect();
+    let fire_probs: Vec<Value> = alerts.iter().filter_map( |a| a.fire_prob.map( |p| json!({ 
+        "interval": iso_interval( a.start, a.end), 
+        "number": p 
+    }))).collect();
+    let smoke_probs: Vec<Value> = alerts.iter().filter_map( |a| a.smoke_prob.map( |p| json!({ 
+        "interval": iso_interval( a.start, a.end), 
+        "number": p 
+    }))).collect();
+    let states: Vec<Value> = alerts.iter().map( |a| json!({
+        "interval": iso_interval( a.start, a.end),
+        "string": a.state
+    })).collect();
+
+    Some( json!({
+        "id": sentinel.device_id,
+        "name": sentinel.device_name,
+        "availability": iso_interval( positions[0].0, window.end),
+        "position": {
+            "epoch": window.start.to_rfc3339(),
+            "cartographicDegrees": samples
+        },
+        "billboard": {
+            "image": opts.icon,
+            "color": colors,
+            "verticalOrigin": "BOTTOM"
+        },
+        "label": {
+            "text": sentinel.device_name,
+            "pixelOffset": { "cartesian2": [0, -40] }
+        },
+        "properties": {
+            "alertState": states,
+            "fireProb": fire_probs,
+            "smokeProb": smoke_probs
+        }
+    }))
+}
+
+/// the CZML document (packet array) for all sentinels of the store within the given window
+pub fn czml_document (store: &SentinelStore, window: &TimeWindow, opts: &CzmlOpts)->Value {
+    let mut sentinels = store.values();
+    sentinels.sort_by( |a,b| a.device_id.cmp( &b.device_id));
+
+    let mut packets = vec![ document_packet( window, opts) ];
+    packets.extend( sentinels.into_iter().filter_map( |s| sentinel_packet( s, window, opts)));
+
+    Value::Array( packets)
+}
+
+impl SentinelStore {
+    pub fn to_czml (&self, window: &TimeWindow, opts: &CzmlOpts, pretty: bool)->Result<String> {
+        let doc = czml_document( self, window, opts);
+        if pretty {
+            Ok(serde_json::to_string_pretty( &doc)?)
+        } else {
+            Ok(serde_json::to_string( &doc)?)
+        }
+    }
+}
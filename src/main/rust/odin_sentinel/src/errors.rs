@@ -59,6 +59,25 @@ pub enum OdinSentinelError {
     #[error("RON error {0}")]
     RonError( #[from] ron::error::Error),
 
+    #[error("RON parse error {0}")]
+    RonParseError( #[from] ron::error::SpannedError),
+
+    #[cfg(feature="sqlite")]
+    #[error("SQLite error {0}")]
+    SqliteError( #[from] rusqlite::Error),
+
+    #[cfg(feature="mqtt")]
+    #[error("MQTT client error {0}")]
+    MqttClientError( #[from] rumqttc::ClientError),
+
+    #[cfg(feature="parquet")]
+    #[error("Arrow error {0}")]
+    ArrowError( #[from] ::arrow::error::ArrowError),
+
+    #[cfg(feature="parquet")]
+    #[error("Parquet error {0}")]
+    ParquetError( #[from] ::parquet::errors::ParquetError),
+
     #[error("no data error {0}")]
     NoDataError(String),
 
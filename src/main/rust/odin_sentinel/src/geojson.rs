@@ -0,0 +1,173 @@
+/*
+ * Copyright (c) 2024, United States Government, as represented by the
+ * Administrator of the National Aeronautics and Space Administration.
+ * All rights reserved.
+ *
+ * The RACE - Runtime for Airspace Concept Evaluation platform is licensed
+ * under the Apache License, Version 2.0 (the "License"); you may not use
+ * this file except in compliance with the License. You may obtain a copy
+ * of the License at http://www.apache.org/licenses/LICENSE-2.0.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! GeoJSON export of sentinel positions and (optionally) fire/smoke detections and their camera rays
+
+use std::time::Duration;
+use chrono::{DateTime,Utc};
+use serde_json::{json,Value};
+use crate::*;
+use crate::pointing::{DetectionRay,DEFAULT_RAY_LENGTH};
+use crate::position::{PositionConfig,PositionEstimate};
+
+#[derive(Debug,Clone)]
+pub struct GeoJsonOpts {
+    pub include_detections: bool, // add a Point feature for each fire/smoke record we have
+    pub max_age: Duration,        // sentinels without records within this duration are reported as stale
+    pub ray_length: Option<f64>,  // add LineString features of this length (meters) for detection image rays
+    pub position: PositionConfig, // how we estimate sentinel positions from GPS fixes
+}
+
+impl GeoJsonOpts {
+    pub fn from_config (config: &SentinelConfig, include_detections: bool)->Self {
+        let ray_length = if include_detections { Some(DEFAULT_RAY_LENGTH) } else { None };
+        GeoJsonOpts { include_detections, max_age: config.max_age, ray_length, position: PositionConfig::default() }
+    }
+}
+
+/// GeoJSON Position of a GPS record - [lon,lat] or [lon,lat,alt]
+pub fn gps_position (gps: &GpsData)->Value {
+    if let Some(alt) = gps.altitude {
+        json!([ gps.longitude.degrees(), gps.latitude.degrees(), alt ])
+    } else {
+        json!([ gps.longitude.degrees(), gps.latitude.degrees() ])
+    }
+}
+
+/// GeoJSON Position of a position estimate
+pub fn estimate_position (pos: &PositionEstimate)->Value {
+    if let Some(alt) = pos.altitude {
+        json!([ pos.longitude, pos.latitude, alt ])
+    } else {
+        json!([ pos.longitude, pos.latitude ])
+    }
+}
+
+/// a Point Feature for the estimated position of the sentinel, or None if we don't have a GPS fix yet
+pub fn sentinel_feature (sentinel: &Sentinel, opts: &GeoJsonOpts, now: DateTime<Utc>)->Option<Value> {
+    let pos = sentinel.position( &opts.position)?;
+
+    Some( json!({
+        "type": "Feature",
+        "id": sentinel.device_id,
+        "geometry": { "type": "Point", "coordinates": estimate_position( &pos) },
+        "properties": {
+            "featureType": "sentinel",
+            "accuracy": pos.accuracy,
+            "deviceId": sentinel.device_id,
+            "deviceName": sentinel.device_name,
+            "lastUpdate": sentinel.last_update(),
+            "health": sentinel.health( now, opts.max_age),
+            "fireProb": sentinel.fire.front().map( |r| r.data.confidence()),
+            "smokeProb": sentinel.smoke.front().map( |r| r.data.confidence()),
+        }
+    }))
+}
+
+/// Point Features for all fire and smoke records of the sentinel, located at the estimated sentinel position
+/// when the detection was recorded
+pub fn detection_features (sentinel: &Sentinel, opts: &GeoJsonOpts)->Vec<Value> {
+    let mut features = Vec::new();
+
+    for rec in &sentinel.fire {
+        if let Some(f) = detection_feature( sentinel, opts, "fire", rec.data.confidence(), &rec.id, rec.time_recorded, &rec.evidences) {
+            features.push(f)
+        }
+    }
+    for rec in &sentinel.smoke {
+        if let Some(f) = detection_feature( sentinel, opts, "smoke", rec.data.confidence(), &rec.id, rec.time_recorded, &rec.evidences) {
+            features.push(f)
+        }
+    }
+
+    features
+}
+
+fn detection_feature (sentinel: &Sentinel, opts: &GeoJsonOpts, detection_type: &str, prob: f64, 
+                      record_id: &str, time_recorded: DateTime<Utc>, evidences: &Vec<RecordId>)->Option<Value> {
+    let pos = sentinel.position_at( &opts.position, time_recorded)?;
+
+    let images: Vec<Value> = evidences.iter().filter_map( |e| sentinel.image_record(e)).map( |img| json!({
+        "id": img.id,
+        "filename": img.data.filename,
+        "isInfrared": img.data.is_infrared,
+    })).collect();
+
+    Some( json!({
+        "type": "Feature",
+        "id": record_id,
+        "geometry": { "type": "Point", "coordinates": estimate_position( &pos) },
+        "properties": {
+            "featureType": detection_type,
+            "deviceId": sentinel.device_id,
+            "deviceName": sentinel.device_name,
+            "timeRecorded": time_recorded,
+            "probability": prob,
+            "images": images,
+        }
+    }))
+}
+
+/// a LineString Feature for a detection ray
+pub fn ray_feature (ray: &DetectionRay)->Value {
+    json!({
+        "type": "Feature",
+        "id": format!("{}-{}", ray.record_id, ray.image_id),
+        "geometry": { "type": "LineString", "coordinates": [ [ray.origin.1, ray.origin.0], [ray.end.1, ray.end.0] ] },
+        "properties": {
+            "featureType": "ray",
+            "deviceId": ray.device_id,
+            "detectionId": ray.record_id,
+            "imageId": ray.image_id,
+            "timeRecorded": ray.time_recorded,
+            "azimuth": ray.pointing.azimuth,
+            "elevation": ray.pointing.elevation,
+        }
+    })
+}
+
+pub fn feature_collection (store: &SentinelStore, opts: &GeoJsonOpts)->Value {
+    let now = Utc::now();
+    let mut sentinels = store.values();
+    sentinels.sort_by( |a,b| a.device_id.cmp( &b.device_id));
+
+    let mut features = Vec::new();
+    for sentinel in sentinels {
+        if let Some(f) = sentinel_feature( sentinel, opts, now) {
+            features.push(f);
+            if opts.include_detections {
+                features.extend( detection_features( sentinel, opts));
+                if let Some(length) = opts.ray_length {
+                    features.extend( sentinel.detection_rays( &opts.position, length).iter().map( ray_feature));
+                }
+            }
+        }
+    }
+
+    json!({ "type": "FeatureCollection", "features": features })
+}
+
+impl SentinelStore {
+    pub fn to_geojson (&self, opts: &GeoJsonOpts, pretty: bool)->Result<String> {
+        let fc = feature_collection( self, opts);
+        if pretty {
+            Ok(serde_json::to_string_pretty( &fc)?)
+        } else {
+            Ok(serde_json::to_string( &fc)?)
+        }
+    }
+}
@@ -0,0 +1,117 @@
+/*
+ * Copyright (c) 2024, United States Government, as represented by the
+ * Administrator of the National Aeronautics and Space Administration.
+ * All rights reserved.
+ *
+ * The RACE - Runtime for Airspace Concept Evaluation platform is licensed
+ * under the Apache License, Version 2.0 (the "License"); you may not use
+ * this file except in compliance with the License. You may obtain a copy
+ * of the License at http://www.apache.org/licenses/LICENSE-2.0.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! time-dynamic KML export of sentinel history for Google Earth. Each sentinel is a Folder with one Placemark
+//! per alert interval, using TimeSpan elements and alert state specific styles
+
+use std::fmt::Write;
+use chrono::{DateTime,Utc};
+use crate::*;
+use crate::timeline::*;
+use crate::position::PositionConfig;
+
+#[derive(Debug,Clone)]
+pub struct KmlOpts {
+    pub thresholds: AlertThresholds,
+    pub icon: String, // IconStyle href
+    pub position: PositionConfig, // how we estimate sentinel positions from GPS fixes
+}
+
+impl Default for KmlOpts {
+    fn default()->Self {
+        KmlOpts {
+            thresholds: AlertThresholds::default(),
+            icon: "http://maps.google.com/mapfiles/kml/shapes/triangle.png".to_string(),
+            position: PositionConfig::default()
+        }
+    }
+}
+
+/// KML colors are aabbggrr
+fn kml_color (state: AlertState)->&'static str {
+    match state {
+        AlertState::Normal  => "ff00ff00",
+        AlertState::Warning => "ff00ffff",
+        AlertState::Alert   => "ff0000ff",
+    }
+}
+
+fn style_id (state: AlertState)->&'static str {
+    match state {
+        AlertState::Normal  => "normal",
+        AlertState::Warning => "warning",
+        AlertState::Alert   => "alert",
+    }
+}
+
+fn kml_time (t: DateTime<Utc>)->String {
+    t.to_rfc3339_opts( chrono::SecondsFormat::Secs, true)
+}
+
+fn write_styles (kml: &mut String, opts: &KmlOpts) {
+    for state in [AlertState::Normal, AlertState::Warning, AlertState::Alert] {
+        write!( kml, r#"<Style id="{}"><IconStyle><color>{}</color><Icon><href>{}</href></Icon></IconStyle></Style>"#,
+                style_id(state), kml_color(state), xml_escape( &opts.icon)).ok();
+    }
+}
+
+/// write a Folder for the sentinel, or nothing if we don't have a position for it within the window
+pub fn write_sentinel_folder (kml: &mut String, sentinel: &Sentinel, window: &TimeWindow, opts: &KmlOpts) {
+    let positions = positions( sentinel, &opts.position, window);
+    if positions.is_empty() { return }
+
+    let name = xml_escape( &sentinel.device_name);
+    write!( kml, "<Folder><name>{}</name>", name).ok();
+
+    for a in alert_intervals( sentinel, window, &opts.thresholds) {
+        // use the last position that is not newer than the interval start, or the first one if there is none
+        let pos = positions.iter().rev().find( |(t,_)| *t <= a.start).or( positions.first()).map( |(_,pos)| pos).unwrap();
+
+        let mut description = format!("alert state: {:?}", a.state);
+        if let Some(p) = a.fire_prob { write!( description, ", fire: {:.2}", p).ok(); }
+        if let Some(p) = a.smoke_prob { write!( description, ", smoke: {:.2}", p).ok(); }
+
+        write!( kml, "<Placemark><name>{}</name><description>{}</description>", name, xml_escape( &description)).ok();
+        write!( kml, "<TimeSpan><begin>{}</begin><end>{}</end></TimeSpan>", kml_time( a.start), kml_time( a.end)).ok();
+        write!( kml, "<styleUrl>#{}</styleUrl>", style_id( a.state)).ok();
+        write!( kml, "<Point><coordinates>{},{},{}</coordinates></Point></Placemark>", 
+                pos.longitude, pos.latitude, pos.altitude.unwrap_or(0.0)).ok();
+    }
+
+    kml.push_str("</Folder>");
+}
+
+pub fn kml_document (store: &SentinelStore, window: &TimeWindow, opts: &KmlOpts)->String {
+    let mut sentinels = store.values();
+    sentinels.sort_by( |a,b| a.device_id.cmp( &b.device_id));
+
+    let mut kml = String::new();
+    kml.push_str( r#"<?xml version="1.0" encoding="UTF-8"?><kml xmlns="http://www.opengis.net/kml/2.2"><Document><name>sentinels</name>"#);
+    write_styles( &mut kml, opts);
+    for sentinel in sentinels {
+        write_sentinel_folder( &mut kml, sentinel, window, opts);
+    }
+    kml.push_str("</Document></kml>");
+
+    kml
+}
+
+impl SentinelStore {
+    pub fn to_kml (&self, window: &TimeWindow, opts: &KmlOpts)->String {
+        kml_document( self, window, opts)
+    }
+}
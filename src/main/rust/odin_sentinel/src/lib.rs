@@ -17,7 +17,8 @@
 #![allow(unused)]
 #![feature(trait_alias)]
 
-use std::{collections::{VecDeque,HashMap},fmt::{self,Debug},cmp::Ordering,future::Future, ops::RangeBounds, time::Duration, sync::atomic::{self,AtomicU64}};
+use std::{collections::{VecDeque,HashMap,HashSet},fmt::{self,Debug},cmp::Ordering,future::Future, ops::RangeBounds, time::Duration, sync::atomic::{self,AtomicU64}};
+use std::{fs,path::{Path,PathBuf}};
 use actor::SentinelConnectorMsg;
 use odin_actor::MsgReceiver;
 use odin_macro::define_algebraic_type;
@@ -31,8 +32,59 @@ use uom::si::f64::{Velocity,ThermodynamicTemperature,ElectricCurrent,ElectricPot
 use reqwest::Client;
 use paste::paste;
 
+/// the sensor capability registry. This is the only place that enumerates capabilities. Each entry has the form
+///     `<SensorCapability variant>: <Sentinel field> <- <record data type> as <record property name>`
+/// The registry is expanded by passing the entry list to a generator macro, which is how we define SensorCapability,
+/// the SensorRecord property aliases, SentinelUpdate, the Sentinel record lists and all the per-capability dispatch code.
+/// Adding a new Delphire sensor type only requires a new entry here plus the respective data struct
+macro_rules! for_each_capability {
+    ($m:ident) => {
+        $m! {
+            Accelerometer: accel       <- AccelerometerData as "accelerometer",
+            Anemometer:    anemo       <- AnemometerData    as "anemometer",
+            Cloudcover:    cloudcover  <- CloudcoverData    as "cloudcover",
+            Fire:          fire        <- FireData          as "fire",
+            Gas:           gas         <- GasData           as "gas",
+            Gps:           gps         <- GpsData           as "gps",
+            Gyroscope:     gyro        <- GyroscopeData     as "gyroscope",
+            Image:         image       <- ImageData         as "image",
+            Magnetometer:  mag         <- MagnetometerData  as "magnetometer",
+            Orientation:   orientation <- OrientationData   as "orientation",
+            Person:        person      <- PersonData        as "person",
+            Power:         power       <- PowerData         as "power",
+            Smoke:         smoke       <- SmokeData         as "smoke",
+            Thermometer:   thermo      <- ThermometerData   as "thermometer",
+            Valve:         valve       <- ValveData         as "valve",
+            Voc:           voc         <- VocData           as "voc"
+        }
+    }
+}
+
 pub mod actor;
 pub mod ws;
+pub mod storage;
+pub mod geojson;
+pub mod timeline;
+pub mod pointing;
+pub mod triangulation;
+pub mod wind;
+pub mod tamper;
+pub mod position;
+pub mod anomaly;
+pub mod confidence;
+pub mod power;
+pub mod privacy;
+pub mod czml;
+pub mod kml;
+pub mod cot;
+pub mod cap;
+pub mod sensorthings;
+#[cfg(feature="parquet")] pub mod parquet;
+pub mod units;
+pub mod csv;
+pub mod aggregate;
+#[cfg(feature="mqtt")] pub mod mqtt;
+use storage::SentinelStorage;
 
 mod errors;
 pub use errors::*;
@@ -50,58 +102,63 @@ pub fn get_next_msg_id ()->String {
 
 /* #region snesor record  ***************************************************************************/
 
+/// implemented by all record data types (generated from the capability registry). This is the key for generic
+/// record access, e.g. `sentinel.records::<FireData>()`
 pub trait CapabilityProvider {
     fn capability()->SensorCapability;
-}
 
-macro_rules! assoc_capability {
-    ($rec_type:ty : $cap:ident) => {
-        impl CapabilityProvider for $rec_type {
-            fn capability()->SensorCapability { SensorCapability::$cap }
-        }
-    };
+    fn records (sentinel: &Sentinel)->&VecDeque<SensorRecord<Self>> where Self: RecordDataBounds;
+    fn records_mut (sentinel: &mut Sentinel)->&mut VecDeque<SensorRecord<Self>> where Self: RecordDataBounds;
 }
 
 pub type DeviceId = String;
+
+/// the newest record time we already have for each (native) device id of a source
+pub type ResumePoints = HashMap<String,DateTime<Utc>>;
+
+/// the id of a Delphire backend (base_uri/access_token) we get sentinel data from. Used to namespace device ids
+/// if we merge several backends into one SentinelStore. The empty source id means device ids are used as-is
+pub type SourceId = String;
+
+/// separator between source id and native (server) device id in namespaced device ids
+pub const SOURCE_ID_SEP: char = ':';
+
+pub fn namespaced_device_id (source_id: &str, device_id: &str)->DeviceId {
+    if source_id.is_empty() { device_id.to_string() } else { format!("{source_id}{SOURCE_ID_SEP}{device_id}") }
+}
+
+/// split (possibly namespaced) device id into (source_id,native_device_id)
+pub fn split_device_id (device_id: &str)->(&str,&str) {
+    device_id.split_once( SOURCE_ID_SEP).unwrap_or( ("", device_id))
+}
+
 pub trait RecordDataBounds = CapabilityProvider + Serialize + for<'de2> Deserialize<'de2> + Debug + Clone + 'static;
 
-#[derive(Deserialize,Debug,Clone)]
-#[serde(bound = "T: Serialize, for<'de2> T: Deserialize<'de2>")]
-#[serde(rename_all="camelCase")]
-pub struct SensorRecord <T> where T: RecordDataBounds {   
-    pub id: String, 
-
-    pub time_recorded: DateTime<Utc>,
-    pub sensor_no: u32,
-    pub device_id: DeviceId,
-
-    pub evidences: Vec<RecordId>, 
-    pub claims: Vec<RecordId>,
-
-    // here is the crux - we get this as different properties ("gps" etc - it depends on T)
-    // since we need to preserve the mapping for subsequent serializing we have to provide alias annotations (for de)
-    // *and* our own Serialize impl 
-    // TODO - check if we can rename this - it is redundant to the 'type' property in the response JSON anyways
-    #[serde(
-        alias = "accelerometer",
-        alias = "anemometer",
-        alias = "cloudcover",
-        alias = "fire",
-        alias = "image",
-        alias = "gas",
-        alias = "gps",
-        alias = "gyroscope",
-        alias = "magnetometer",
-        alias = "orientation",
-        alias = "person",
-        alias = "power",
-        alias = "smoke",
-        alias = "thermometer",
-        alias = "valve",
-        alias = "voc"
-    )]
-    pub data: T,
+macro_rules! define_sensor_record {
+    ($( $cap:ident : $f:ident <- $data:ident as $prop:tt ),*) => {
+        #[derive(Deserialize,Debug,Clone)]
+        #[serde(bound = "T: Serialize, for<'de2> T: Deserialize<'de2>")]
+        #[serde(rename_all="camelCase")]
+        pub struct SensorRecord <T> where T: RecordDataBounds {   
+            pub id: String, 
+
+            pub time_recorded: DateTime<Utc>,
+            pub sensor_no: u32,
+            pub device_id: DeviceId,
+
+            pub evidences: Vec<RecordId>, 
+            pub claims: Vec<RecordId>,
+
+            // here is the crux - we get this as different properties ("gps" etc - it depends on T)
+            // since we need to preserve the mapping for subsequent serializing we have to provide alias annotations (for de)
+            // *and* our own Serialize impl 
+            // TODO - check if we can rename this - it is redundant to the 'type' property in the response JSON anyways
+            #[serde( $( alias = $prop ),* )]
+            pub data: T,
+        }
+    }
 }
+for_each_capability!( define_sensor_record);
 
 impl<T> Serialize for SensorRecord<T> where T: RecordDataBounds {
     fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> where S: Serializer {
@@ -142,26 +199,83 @@ pub struct RecordId {
     pub id: String,
 }
 
-/// enum to give us a single non-generic type we can use to wrap any record so that we can publish it through a single msg/callback slot
-/// note this also defined respective From<SensorRecord<..>> impls
-define_algebraic_type!{ pub SentinelUpdate =
-    SensorRecord<AccelerometerData> |
-    SensorRecord<AnemometerData> |
-    SensorRecord<CloudcoverData> |
-    SensorRecord<FireData> |
-    SensorRecord<GasData> |
-    SensorRecord<GpsData> |
-    SensorRecord<GyroscopeData> |
-    SensorRecord<ImageData> |
-    SensorRecord<MagnetometerData> |
-    SensorRecord<OrientationData> |
-    SensorRecord<PersonData> |
-    SensorRecord<PowerData> |
-    SensorRecord<SmokeData> |
-    SensorRecord<ThermometerData> |
-    SensorRecord<ValveData> |
-    SensorRecord<VocData>
+macro_rules! define_sentinel_update {
+    ($( $cap:ident : $f:ident <- $data:ident as $prop:tt ),*) => {
+        /// enum to give us a single non-generic type we can use to wrap any record so that we can publish it through a single msg/callback slot.
+        /// Serializes into the JSON of the wrapped record
+        #[derive(Serialize,Debug,Clone)]
+        #[serde(untagged)]
+        pub enum SentinelUpdate {
+            $( $cap( SensorRecord<$data> ) ),*
+        }
+
+        $(
+            impl From<SensorRecord<$data>> for SentinelUpdate {
+                fn from (rec: SensorRecord<$data>)->Self { SentinelUpdate::$cap(rec) }
+            }
+        )*
+
+        impl SentinelUpdate {
+            /// parse a generic JSON record of known capability
+            pub fn from_json (capability: SensorCapability, value: serde_json::Value)->Result<Self> {
+                match capability {
+                    $( SensorCapability::$cap => Ok( SentinelUpdate::$cap( serde_json::from_value( value)?)) ),*
+                }
+            }
+
+            pub fn capability (&self)->SensorCapability {
+                match self {
+                    $( SentinelUpdate::$cap(_) => SensorCapability::$cap ),*
+                }
+            }
+
+            pub fn device_id (&self)->&str {
+                match self {
+                    $( SentinelUpdate::$cap(rec) => rec.device_id.as_str() ),*
+                }
+            }
+
+            pub fn set_device_id (&mut self, device_id: &str) {
+                match self {
+                    $( SentinelUpdate::$cap(rec) => rec.device_id = device_id.to_string() ),*
+                }
+            }
+
+            pub fn record_id (&self)->&str {
+                match self {
+                    $( SentinelUpdate::$cap(rec) => rec.id.as_str() ),*
+                }
+            }
+
+            pub fn sensor_no (&self)->u32 {
+                match self {
+                    $( SentinelUpdate::$cap(rec) => rec.sensor_no ),*
+                }
+            }
+
+            pub fn time_recorded (&self)->DateTime<Utc> {
+                match self {
+                    $( SentinelUpdate::$cap(rec) => rec.time_recorded ),*
+                }
+            }
+
+            /// persist the wrapped record
+            pub fn store (&self, storage: &mut dyn SentinelStorage)->Result<()> {
+                match self {
+                    $( SentinelUpdate::$cap(rec) => storage.store( rec) ),*
+                }
+            }
+
+            /// sort the wrapped record into the respective record list of the given sentinel
+            pub fn sort_into (self, sentinel: &mut Sentinel) {
+                match self {
+                    $( SentinelUpdate::$cap(rec) => sort_in_record( &mut sentinel.$f, rec) ),*
+                }
+            }
+        }
+    }
 }
+for_each_capability!( define_sentinel_update);
 
 /* #endregion sensor record */
 
@@ -174,7 +288,6 @@ pub struct AccelerometerData {
     pub ay: f32,
     pub az: f32,
 }
-assoc_capability!( AccelerometerData: Accelerometer);
 
 
 #[derive(Serialize,Deserialize,Debug,PartialEq,Clone)]
@@ -183,21 +296,25 @@ pub struct AnemometerData {
     pub angle: Angle,
     pub speed: Velocity 
 }
-assoc_capability!(AnemometerData: Anemometer);
 
 #[derive(Serialize,Deserialize,Debug,PartialEq,Clone)]
 #[serde(rename_all="camelCase")]
 pub struct CloudcoverData {
     pub percent: f32,
 }
-assoc_capability!(CloudcoverData: Cloudcover);
 
 #[derive(Serialize,Deserialize,Debug,PartialEq,Clone)]
 #[serde(rename_all="camelCase")]
 pub struct FireData {
-    pub fire_prob: f64
+    pub fire_prob: f64,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub effective_prob: Option<f64>, // cloud cover adjusted fire_prob, set by the confidence module
+}
+
+impl FireData {
+    /// the probability alarm thresholds act on
+    pub fn confidence (&self)->f64 { self.effective_prob.unwrap_or( self.fire_prob) }
 }
-assoc_capability!(FireData: Fire);
 
 
 #[derive(Serialize,Deserialize,Debug,PartialEq,Clone)] // check this
@@ -207,7 +324,6 @@ pub struct ImageData {
     pub is_infrared: bool,
     pub orientation_record: Option<RecordId>, // nested orientation record?
 }
-assoc_capability!(ImageData: Image);
 
 
 #[derive(Serialize,Deserialize,Debug,PartialEq,Clone)]  
@@ -217,7 +333,6 @@ pub struct GasData {
     pub pressure: f64,
     pub altitude: f64
 }
-assoc_capability!(GasData: Gas);
 
 
 #[derive(Serialize,Deserialize,Debug,PartialEq,Clone)]  
@@ -230,7 +345,6 @@ pub struct GpsData {
     pub number_of_satellites: Option<i32>,
     #[serde(alias = "HDOP")] pub hdop: Option<f32>
 }
-assoc_capability!(GpsData: Gps);
 
 
 #[derive(Serialize,Deserialize,Debug,PartialEq,Clone)]  
@@ -240,7 +354,6 @@ pub struct GyroscopeData {
     pub gy: f64,
     pub gz: f64
 }
-assoc_capability!(GyroscopeData: Gyroscope);
 
 
 #[derive(Serialize,Deserialize,Debug,PartialEq,Clone)]  
@@ -251,7 +364,6 @@ pub struct OrientationData {
     pub qy: f64,
     pub qz: f64
 }
-assoc_capability!(OrientationData: Orientation);
 
 
 #[derive(Serialize,Deserialize,Debug,PartialEq,Clone)]  
@@ -261,7 +373,6 @@ pub struct MagnetometerData {
     pub my: f64,
     pub mz: f64
 }
-assoc_capability!(MagnetometerData: Magnetometer);
 
 
 #[derive(Serialize,Deserialize,Debug,PartialEq,Clone)]
@@ -269,7 +380,6 @@ assoc_capability!(MagnetometerData: Magnetometer);
 pub struct PersonData {
     pub person_prob: f64
 }
-assoc_capability!(PersonData: Person);
 
 
 #[derive(Serialize,Deserialize,Debug,PartialEq,Clone)]
@@ -290,15 +400,20 @@ pub struct PowerData { // can use uom here for current, volatage, temp?
     pub load_volatage_status: String,
     pub load_status: String
 }
-assoc_capability!(PowerData: Power);
 
 
 #[derive(Serialize,Deserialize,Debug,PartialEq,Clone)]
 #[serde(rename_all="camelCase")]
 pub struct SmokeData {
-    pub smoke_prob: f64
+    pub smoke_prob: f64,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub effective_prob: Option<f64>, // cloud cover adjusted smoke_prob, set by the confidence module
+}
+
+impl SmokeData {
+    /// the probability alarm thresholds act on
+    pub fn confidence (&self)->f64 { self.effective_prob.unwrap_or( self.smoke_prob) }
 }
-assoc_capability!(SmokeData: Smoke);
 
 
 #[derive(Serialize,Deserialize,Debug,PartialEq,Clone)]
@@ -306,7 +421,6 @@ assoc_capability!(SmokeData: Smoke);
 pub struct ThermometerData {
     pub temperature: ThermodynamicTemperature
 }
-assoc_capability!(ThermometerData: Thermometer);
 
 
 #[derive(Serialize,Deserialize,Debug,PartialEq,Clone)]
@@ -316,7 +430,6 @@ pub struct ValveData {
     pub external_light_on: bool,
     pub internal_light_on: bool,
 }
-assoc_capability!(ValveData: Valve);
 
 
 #[derive(Serialize,Deserialize,Debug,PartialEq,Clone)] 
@@ -324,49 +437,52 @@ pub struct VocData {
    #[serde(alias = "TVOC")] pub tvoc: i32,
    #[serde(alias = "eCO2")] pub e_co2: i32,
 }
-assoc_capability!(VocData: Voc);
 
-#[derive(Serialize,Deserialize,Debug,PartialEq,Copy,Clone)] 
-#[serde(rename_all="lowercase")]
-pub enum SensorCapability {
-    Accelerometer,
-    Anemometer,
-    Cloudcover,
-    Fire,
-    Gas,
-    Gps,
-    Gyroscope,
-    Image,
-    Magnetometer,
-    Orientation,
-    Person,
-    Power,
-    Smoke,
-    Thermometer,
-    Valve,
-    Voc
+macro_rules! define_sensor_capability {
+    ($( $cap:ident : $f:ident <- $data:ident as $prop:tt ),*) => {
+        #[derive(Serialize,Deserialize,Debug,PartialEq,Copy,Clone,strum::EnumIter)] 
+        pub enum SensorCapability {
+            $( #[serde(rename = $prop)] $cap ),*
+        }
+
+        impl SensorCapability {
+            pub(crate) fn property_name (&self)->&'static str {
+                match *self {
+                    $( SensorCapability::$cap => $prop ),*
+                }
+            }
+        }
+
+        $(
+            impl CapabilityProvider for $data {
+                fn capability()->SensorCapability { SensorCapability::$cap }
+                fn records (sentinel: &Sentinel)->&VecDeque<SensorRecord<Self>> { &sentinel.$f }
+                fn records_mut (sentinel: &mut Sentinel)->&mut VecDeque<SensorRecord<Self>> { &mut sentinel.$f }
+            }
+        )*
+    }
 }
+for_each_capability!( define_sensor_capability);
+
 impl SensorCapability {
-    fn property_name (&self)->&'static str {
-        use SensorCapability::*;
-        match *self {
-            Accelerometer => "accelerometer",
-            Anemometer => "anemometer",
-            Cloudcover => "cloudcover",
-            Fire => "fire",
-            Gas => "gas",
-            Gps => "gps",
-            Gyroscope => "gyroscope",
-            Image => "image",
-            Magnetometer => "magnetometer",
-            Orientation => "orientation",
-            Person => "person",
-            Power => "power",
-            Smoke => "smoke",
-            Thermometer => "thermometer",
-            Valve => "valve",
-            Voc => "voc"  
-        }
+    /// the capability of a generic JSON record, which is determined by its payload property name
+    pub fn of_record (value: &serde_json::Value)->Option<SensorCapability> {
+        use strum::IntoEnumIterator;
+        SensorCapability::iter().find( |c| value.get( c.property_name()).is_some())
+    }
+
+    /// the capability with the given (lowercase) record property name, e.g. "fire"
+    pub fn from_name (name: &str)->Option<SensorCapability> {
+        use strum::IntoEnumIterator;
+        SensorCapability::iter().find( |c| c.property_name() == name)
+    }
+
+    /// parse a comma separated list of capability names such as "fire,smoke". An empty list is returned for
+    /// an empty string, which exporters interpret as all capabilities
+    pub fn from_names (names: &str)->Result<Vec<SensorCapability>> {
+        names.split(',').map( |s| s.trim()).filter( |s| !s.is_empty()).map( |name| {
+            SensorCapability::from_name( name).ok_or_else( || OdinSentinelError::ConfigParseError( format!("unknown capability: {name}")))
+        }).collect()
     }
 }
 
@@ -418,14 +534,42 @@ pub struct RecordList<T> where T: RecordDataBounds {
 
 /* #region internal data store ************************************************************************/
 
-/// the struct that stores sentinel values and provides access to them through their device_ids
+/// the struct that stores sentinel values and provides access to them through their device_ids.
+/// If it has a SentinelStorage all records that are added through `write_through()` or `write_through_all()` are also persisted
 #[derive(Debug)]
 pub struct SentinelStore {
-    sentinels: HashMap<DeviceId,Sentinel>
+    sentinels: HashMap<DeviceId,Sentinel>,
+    storage: Option<Box<dyn SentinelStorage>>,
 }
 impl SentinelStore {
     pub fn new ()->Self {
-        SentinelStore { sentinels: HashMap::new() }
+        SentinelStore { sentinels: HashMap::new(), storage: None }
+    }
+
+    pub fn set_storage (&mut self, storage: Box<dyn SentinelStorage>) {
+        self.storage = Some(storage)
+    }
+
+    pub fn storage (&self)->Option<&dyn SentinelStorage> {
+        self.storage.as_deref()
+    }
+
+    /// persist record if we have a storage and the record belongs to a known sentinel
+    pub fn write_through (&mut self, update: &SentinelUpdate)->Result<()> {
+        if let Some(storage) = &mut self.storage {
+            if self.sentinels.contains_key( update.device_id()) {
+                update.store( storage.as_mut())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// get the persisted records of a device (and optional sensor) within [start,end]. This requires a storage
+    pub fn query_records<T> (&self, device_id: &str, sensor_no: Option<u32>, start: DateTime<Utc>, end: DateTime<Utc>)->Result<Vec<SensorRecord<T>>> 
+        where T: RecordDataBounds
+    {
+        let storage = self.storage.as_deref().ok_or( op_failed("no storage"))?;
+        storage.query( device_id, sensor_no, start, end)
     }
     pub fn insert (&mut self, k: String, v: Sentinel)->Option<Sentinel> {
         self.sentinels.insert( k, v)
@@ -451,6 +595,89 @@ impl SentinelStore {
         self.sentinels.keys().map( |k| k.clone()).collect()
     }
 
+    /// get the native (server) device ids of all active (non-retired) sentinels that belong to the given source
+    pub fn get_source_device_ids (&self, source_id: &str)->Vec<String> {
+        self.sentinels.values().filter_map( |s| {
+            let (src,dev) = split_device_id( &s.device_id);
+            if src == source_id && s.retired.is_none() { Some(dev.to_string()) } else { None }
+        }).collect()
+    }
+
+    /// the time of the newest record for each active device of the given source, keyed by native device id. This is
+    /// where init queries resume after a warm start
+    pub fn resume_points (&self, source_id: &str)->ResumePoints {
+        self.sentinels.values().filter_map( |s| {
+            let (src,dev) = split_device_id( &s.device_id);
+            if src == source_id && s.retired.is_none() { s.last_update().map( |t| (dev.to_string(), t)) } else { None }
+        }).collect()
+    }
+
+    /// the records of all sentinels for the given capability as generic JSON values
+    pub fn json_records (&self, capability: SensorCapability)->Result<Vec<serde_json::Value>> {
+        let mut list = Vec::new();
+        for sentinel in self.sentinels.values() {
+            list.append( &mut sentinel.json_records( capability)?);
+        }
+        Ok(list)
+    }
+
+    /// insert the sentinel of a device that was reported after initialization, replacing a retired one with the same id
+    pub fn add_device (&mut self, sentinel: Sentinel)->DeviceEvent {
+        let device_id = sentinel.device_id.clone();
+        self.sentinels.insert( device_id.clone(), sentinel);
+        DeviceEvent::Added(device_id)
+    }
+
+    /// mark a device as retired. Returns None if there is no such device or it is already retired
+    pub fn retire_device (&mut self, device_id: &DeviceId, time: DateTime<Utc>)->Option<DeviceEvent> {
+        let sentinel = self.sentinels.get_mut( device_id)?;
+        if sentinel.retired.is_none() {
+            sentinel.retired = Some(time);
+            Some( DeviceEvent::Removed( device_id.clone()))
+        } else {
+            None
+        }
+    }
+
+    pub fn into_values (self)->Vec<Sentinel> {
+        self.sentinels.into_values().collect()
+    }
+
+    /// add all sentinels of another store, replacing entries with the same device id.
+    /// Note this does not change our storage - use `write_through_all()` if the records of `other` are new
+    pub fn merge (&mut self, other: SentinelStore) {
+        self.sentinels.extend( other.sentinels)
+    }
+
+    /// persist all records of the given sentinels if we have a storage
+    pub fn write_through_all (&mut self, other: &SentinelStore)->Result<()> {
+        if let Some(storage) = &mut self.storage {
+            for sentinel in other.sentinels.values() {
+                sentinel.store_records( storage.as_mut())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// persist all records of a single (new) sentinel if we have a storage
+    pub fn write_through_sentinel (&mut self, sentinel: &Sentinel)->Result<()> {
+        if let Some(storage) = &mut self.storage {
+            sentinel.store_records( storage.as_mut())?;
+        }
+        Ok(())
+    }
+
+    /// turn this store into one with device ids that are prefixed by the given source id
+    pub fn into_namespaced (self, source_id: &str)->SentinelStore {
+        if source_id.is_empty() { return self }
+
+        let sentinels = self.sentinels.into_values().map( |mut sentinel| {
+            sentinel.set_source( source_id);
+            (sentinel.device_id.clone(), sentinel)
+        }).collect();
+        SentinelStore { sentinels, storage: self.storage }
+    }
+
     pub fn to_json (&self, pretty: bool)->Result<String> {
         let list = SentinelList { sentinels: self.values() };
         if pretty {
@@ -468,6 +695,70 @@ impl SentinelStore {
             Ok(ron::to_string(&list)?)
         }
     }
+
+    pub fn from_json (json: &str)->Result<Self> {
+        let list: OwnedSentinelList = serde_json::from_str( json)?;
+        Ok(Self::from_list( list))
+    }
+
+    pub fn from_ron (s: &str)->Result<Self> {
+        let list: OwnedSentinelList = ron::from_str( s)?;
+        Ok(Self::from_list( list))
+    }
+
+    fn from_list (list: OwnedSentinelList)->Self {
+        let sentinels = list.sentinels.into_iter().map( |s| (s.device_id.clone(), s)).collect();
+        SentinelStore { sentinels, storage: None }
+    }
+
+    /// save store as (pretty) JSON. We write to a temp file first so that we don't end up
+    /// with a corrupted file if we get terminated while saving
+    pub fn save (&self, path: &Path)->Result<()> {
+        let json = self.to_json( true)?;
+        let tmp_path = path.with_extension("tmp");
+        fs::write( &tmp_path, json)?;
+        Ok(fs::rename( &tmp_path, path)?)
+    }
+
+    /// load store from a JSON file that was created by save()
+    pub fn load (path: &Path)->Result<Self> {
+        let json = fs::read_to_string( path)?;
+        Self::from_json( &json)
+    }
+
+    /// add a single record in the JSON format we use for updates. Sentinels for unknown devices are created on demand.
+    /// Returns the device id and capability of the record
+    pub fn add_json_record (&mut self, json: &str)->Result<(DeviceId,SensorCapability)> {
+        let value: serde_json::Value = serde_json::from_str( json)?;
+        let device_id = value.get("deviceId").and_then( |v| v.as_str()).ok_or( no_data("deviceId"))?.to_string();
+        let capability = SensorCapability::of_record( &value).ok_or( no_data("record capability"))?;
+
+        let sentinel = self.sentinels.entry( device_id.clone()).or_insert_with( || Sentinel::new( device_id.clone(), device_id.clone()));
+        sentinel.add_json_record( capability, value)?;
+        Ok((device_id,capability))
+    }
+
+    /// sort an update into the sentinel of its device. Like `add_json_record()` this creates the sentinel if we
+    /// don't know the device yet, which is used by clients that mirror the connector state
+    pub fn add_update (&mut self, update: SentinelUpdate)->(DeviceId,SensorCapability) {
+        let device_id = update.device_id().to_string();
+        let capability = update.capability();
+
+        let sentinel = self.sentinels.entry( device_id.clone()).or_insert_with( || Sentinel::new( device_id.clone(), device_id.clone()));
+        update.sort_into( sentinel);
+        (device_id,capability)
+    }
+
+    /// load a record archive, which is a text file with one JSON record per line (as sent to JSON update callbacks)
+    pub fn load_record_archive (path: &Path)->Result<Self> {
+        let mut store = SentinelStore::new();
+        for line in fs::read_to_string( path)?.lines() {
+            if !line.trim().is_empty() {
+                store.add_json_record( line)?;
+            }
+        }
+        Ok(store)
+    }
 }
 
 /// helper type so that we can serialize the Sentinel values as a list
@@ -476,83 +767,180 @@ struct SentinelList<'a>  {
     sentinels: Vec<&'a Sentinel>
 }
 
-/// the current sentinel state. This needs to be serializable to JSON so that we
-/// can send it to connected clients (field names have to map into what our javascript module expects)
-#[derive(Serialize,Deserialize,Debug)]
-#[serde(rename_all="camelCase")]
-pub struct Sentinel {
-    pub device_id: DeviceId,
-    pub device_name: String,
-    pub date: Option<DateTime<Utc>>, // last update
-
-    // the last N records for each capability/sensor
-    pub accel:         VecDeque< SensorRecord<AccelerometerData> >,
-    pub anemo:         VecDeque< SensorRecord<AnemometerData> >,
-    pub cloudcover:    VecDeque< SensorRecord<CloudcoverData> >,
-    pub fire:          VecDeque< SensorRecord<FireData> >,
-    pub gas:           VecDeque< SensorRecord<GasData> >,
-    pub gps:           VecDeque< SensorRecord<GpsData> >,
-    pub gyro:          VecDeque< SensorRecord<GyroscopeData> >,
-    pub image:         VecDeque< SensorRecord<ImageData> >,
-    pub mag:           VecDeque< SensorRecord<MagnetometerData> >,
-    pub orientation:   VecDeque< SensorRecord<OrientationData> >,
-    pub person:        VecDeque< SensorRecord<PersonData> >,
-    pub power:         VecDeque< SensorRecord<PowerData> >,
-    pub smoke:         VecDeque< SensorRecord<SmokeData> >,
-    pub thermo:        VecDeque< SensorRecord<ThermometerData> >,
-    pub valve:         VecDeque< SensorRecord<ValveData> >,
-    pub voc:           VecDeque< SensorRecord<VocData> >    
+/// the deserialization counterpart for SentinelList
+#[derive(Deserialize)]
+struct OwnedSentinelList {
+    sentinels: Vec<Sentinel>
 }
 
+/// device list changes that are detected after the initial query
+#[derive(Debug,Clone)]
+pub enum DeviceEvent {
+    Added(DeviceId),
+    Removed(DeviceId),
+    Relocated(position::Relocation)
+}
+
+/// coarse sentinel state we can use to style displays
+#[derive(Serialize,Deserialize,Debug,PartialEq,Copy,Clone)]
+#[serde(rename_all="lowercase")]
+pub enum SentinelHealth {
+    Ok,      // we got records within the configured max age
+    Stale,   // no records within the configured max age
+    NoData,  // we never got any records
+    Retired  // the device is no longer reported by the server
+}
+
+macro_rules! define_sentinel {
+    ($( $cap:ident : $f:ident <- $data:ident as $prop:tt ),*) => {
+        /// the current sentinel state. This needs to be serializable to JSON so that we
+        /// can send it to connected clients (field names have to map into what our javascript module expects)
+        #[derive(Serialize,Deserialize,Debug)]
+        #[serde(rename_all="camelCase")]
+        pub struct Sentinel {
+            pub device_id: DeviceId,
+            pub device_name: String,
+            pub date: Option<DateTime<Utc>>, // last update
+
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            pub retired: Option<DateTime<Utc>>, // set if the device was no longer reported by the server
+
+            #[serde(default, skip_serializing_if = "Vec::is_empty")]
+            pub sensors: Vec<SensorData>, // as reported by the server when the sentinel was initialized
+
+            // the last N records for each capability/sensor
+            $( pub $f: VecDeque< SensorRecord<$data> >, )*
+        }
+
+        impl Sentinel {
+            pub fn new (device_id: DeviceId, device_name: String)->Self {
+                Sentinel { 
+                    device_id,
+                    device_name,
+                    date: None,
+                    retired: None,
+                    sensors: Vec::new(),
+                    $( $f: VecDeque::new(), )*
+                }
+            }
+
+            /// the time of the newest record we have for this sentinel
+            pub fn last_update (&self)->Option<DateTime<Utc>> {
+                [ $( latest_time( &self.$f) ),* ].into_iter().flatten().max()
+            }
+
+            fn set_all_record_device_ids (&mut self, device_id: &str) {
+                $( set_record_device_ids( &mut self.$f, device_id); )*
+            }
+
+            /// sort in a record from its generic JSON value
+            pub fn add_json_record (&mut self, capability: SensorCapability, value: serde_json::Value)->Result<()> {
+                match capability {
+                    $( SensorCapability::$cap => sort_in_record( &mut self.$f, serde_json::from_value(value)?) ),*
+                }
+                Ok(())
+            }
+
+            /// the records for the given capability as generic JSON values (newest first)
+            pub fn json_records (&self, capability: SensorCapability)->Result<Vec<serde_json::Value>> {
+                let value = match capability {
+                    $( SensorCapability::$cap => serde_json::to_value( &self.$f)? ),*
+                };
+                match value {
+                    serde_json::Value::Array(list) => Ok(list),
+                    _ => Err( op_failed("records did not serialize into a list"))
+                }
+            }
+
+            /// persist all records we currently have for this sentinel
+            pub fn store_records (&self, storage: &mut dyn SentinelStorage)->Result<()> {
+                $( storage.store_all( &self.$f)?; )*
+                Ok(())
+            }
+
+            /// all our records as SentinelUpdates in time order
+            pub fn iter_all (&self)->impl Iterator<Item=SentinelUpdate> {
+                let mut updates: Vec<SentinelUpdate> = Vec::new();
+                $( updates.extend( self.$f.iter().cloned().map( SentinelUpdate::from)); )*
+                updates.sort_by_key( |u| u.time_recorded());
+                updates.into_iter()
+            }
+
+            /// the records of `other` (a more recent state of the same device) that we don't have yet, in time order
+            pub fn new_updates (&self, other: Sentinel)->Vec<SentinelUpdate> {
+                let mut updates: Vec<SentinelUpdate> = Vec::new();
+                $( updates.extend( new_records( &self.$f, other.$f).into_iter().map( SentinelUpdate::from)); )*
+                updates.sort_by_key( |u| u.time_recorded());
+                updates
+            }
+
+            /// retrieve and sort in the last n records of a sensor capability. If `since` is set we only retrieve records that
+            /// were recorded after it
+            pub async fn get_and_store_records( &mut self, client: &Client, base_uri: &str, access_token: &str, 
+                                                sensor_no: u32, capability: SensorCapability, n_last: usize, since: Option<DateTime<Utc>>) -> Result<()> {
+                let device_id = &self.device_id.as_str();
+                match capability {
+                    $( SensorCapability::$cap => {
+                        let recs = match since {
+                            Some(since) => get_records_since( client, base_uri, access_token, device_id, sensor_no, n_last, since).await?,
+                            None => get_records( client, base_uri, access_token, device_id, sensor_no, n_last).await?
+                        };
+                        sort_in_records( &mut self.$f, recs)
+                    } ),*
+                }
+                Ok(())
+            }
+        }
+    }
+}
+for_each_capability!( define_sentinel);
+
 impl Sentinel {
-    pub fn new (device_id: DeviceId, device_name: String)->Self {
-        Sentinel { 
-            device_id,
-            device_name,
-            date: None,
-
-            accel:         VecDeque::new(),
-            anemo:         VecDeque::new(),
-            cloudcover:    VecDeque::new(),
-            fire:          VecDeque::new(),
-            gas:           VecDeque::new(),
-            gps:           VecDeque::new(),
-            gyro:          VecDeque::new(),
-            image:         VecDeque::new(),
-            mag:           VecDeque::new(),
-            orientation:   VecDeque::new(),
-            person:        VecDeque::new(),
-            power:         VecDeque::new(),
-            smoke:         VecDeque::new(),
-            thermo:        VecDeque::new(),
-            valve:         VecDeque::new(),
-            voc:           VecDeque::new(),
+    pub fn health (&self, now: DateTime<Utc>, max_age: Duration)->SentinelHealth {
+        if self.retired.is_some() {
+            SentinelHealth::Retired
+        } else if let Some(last_update) = self.last_update() {
+            if (now - last_update).to_std().map_or( true, |age| age <= max_age) { SentinelHealth::Ok } else { SentinelHealth::Stale }
+        } else {
+            SentinelHealth::NoData
         }
     }
 
-    pub async fn get_and_store_records( &mut self, client: &Client, base_uri: &str, access_token: &str, 
-                                                 sensor_no: u32, capability: SensorCapability, n_last: usize) -> Result<()> {
-        let device_id = &self.device_id.as_str();
-        use SensorCapability::*;
-        match capability {
-            Accelerometer => sort_in_records( &mut self.accel, get_records( client, base_uri, access_token, device_id, sensor_no, n_last).await?),
-            Anemometer    => sort_in_records( &mut self.anemo, get_records( client, base_uri, access_token, device_id, sensor_no, n_last).await?),
-            Cloudcover    => sort_in_records( &mut self.cloudcover, get_records(client, base_uri, access_token, device_id, sensor_no,  n_last).await?),
-            Fire          => sort_in_records( &mut self.fire, get_records( client, base_uri, access_token, device_id, sensor_no, n_last).await?),
-            Gas           => sort_in_records( &mut self.gas, get_records( client, base_uri, access_token, device_id, sensor_no, n_last).await?),
-            Gps           => sort_in_records( &mut self.gps, get_records( client, base_uri, access_token, device_id, sensor_no, n_last).await?),
-            Gyroscope     => sort_in_records( &mut self.gyro, get_records( client, base_uri, access_token, device_id, sensor_no, n_last).await?),
-            Image         => sort_in_records( &mut self.image, get_records( client, base_uri, access_token, device_id, sensor_no, n_last).await?),
-            Magnetometer  => sort_in_records( &mut self.mag, get_records( client, base_uri, access_token, device_id, sensor_no, n_last).await?),
-            Orientation   => sort_in_records( &mut self.orientation, get_records( client, base_uri, access_token, device_id, sensor_no, n_last).await?),
-            Person        => sort_in_records( &mut self.person, get_records( client, base_uri, access_token, device_id, sensor_no, n_last).await?),
-            Power         => sort_in_records( &mut self.power, get_records( client, base_uri, access_token, device_id, sensor_no, n_last).await?),
-            Smoke         => sort_in_records( &mut self.smoke, get_records( client, base_uri, access_token, device_id, sensor_no, n_last).await?),
-            Thermometer   => sort_in_records( &mut self.thermo, get_records( client, base_uri, access_token, device_id, sensor_no, n_last).await?),
-            Valve         => sort_in_records( &mut self.valve, get_records( client, base_uri, access_token, device_id, sensor_no, n_last).await?),
-            Voc           => sort_in_records( &mut self.voc, get_records( client, base_uri, access_token, device_id, sensor_no, n_last).await?),
+    /// the records of capability T (newest first)
+    pub fn records<T> (&self)->&VecDeque<SensorRecord<T>> where T: RecordDataBounds {
+        T::records( self)
+    }
+
+    /// the newest record of capability T
+    pub fn latest<T> (&self)->Option<&SensorRecord<T>> where T: RecordDataBounds {
+        T::records( self).front()
+    }
+
+    /// the records of capability T that were recorded within the given time range (newest first)
+    pub fn records_in<T> (&self, range: impl RangeBounds<DateTime<Utc>>)->Vec<&SensorRecord<T>> where T: RecordDataBounds {
+        T::records( self).iter().filter( |r| range.contains( &r.time_recorded)).collect()
+    }
+
+    /// sort in a record of capability T
+    pub fn add_record<T> (&mut self, rec: SensorRecord<T>) where T: RecordDataBounds {
+        sort_in_record( T::records_mut( self), rec)
+    }
+
+    /// the image record with the given id (e.g. from the evidences of a fire or smoke record)
+    pub fn image_record (&self, record_id: &RecordId)->Option<&SensorRecord<ImageData>> {
+        self.records::<ImageData>().iter().find( |r| r.id == record_id.id)
+    }
+
+    /// namespace the device_id of this sentinel and all its records with the given source id
+    pub fn set_source (&mut self, source_id: &str) {
+        let device_id = namespaced_device_id( source_id, &self.device_id);
+
+        self.set_all_record_device_ids( &device_id);
+
+        for sensor in self.sensors.iter_mut() {
+            sensor.device_id = device_id.clone();
         }
-        Ok(())
+        self.device_id = device_id;
     }
 }
 
@@ -562,7 +950,49 @@ pub fn sort_in_records<T> (list: &mut VecDeque<SensorRecord<T>>, recs: Vec<Senso
     }
 }
 
+/// the `timeRecorded` of a generic JSON record
+pub(crate) fn json_record_time (rec: &serde_json::Value)->Option<DateTime<Utc>> {
+    let t = rec.get("timeRecorded")?.as_str()?;
+    DateTime::parse_from_rfc3339( t).ok().map( |t| t.with_timezone(&Utc))
+}
+
+/// flatten a JSON record payload into (column name, value) pairs. Nested objects get '_' separated column names
+pub(crate) fn flatten_json (prefix: &str, value: &serde_json::Value, columns: &mut Vec<(String,serde_json::Value)>) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (k,v) in map {
+                let name = if prefix.is_empty() { k.clone() } else { format!("{prefix}_{k}") };
+                flatten_json( &name, v, columns);
+            }
+        }
+        _ => columns.push( (prefix.to_string(), value.clone()))
+    }
+}
+
+/// record lists are sorted newest first
+fn latest_time<T> (list: &VecDeque<SensorRecord<T>>)->Option<DateTime<Utc>> where T: RecordDataBounds {
+    list.front().map( |r| r.time_recorded)
+}
+
+fn set_record_device_ids<T> (list: &mut VecDeque<SensorRecord<T>>, device_id: &str) where T: RecordDataBounds {
+    for rec in list.iter_mut() {
+        rec.device_id = device_id.to_string();
+    }
+}
+
+/// return the records of `recs` that are not already in `list` (compared by record id)
+pub fn new_records<T> (list: &VecDeque<SensorRecord<T>>, recs: VecDeque<SensorRecord<T>>)->Vec<SensorRecord<T>> where T: RecordDataBounds {
+    let known: HashSet<&str> = list.iter().map( |r| r.id.as_str()).collect();
+    recs.into_iter().filter( |r| !known.contains( r.id.as_str())).collect()
+}
+
+/// insert a record according to its time, replacing a record with the same id (e.g. a redacted image)
 pub fn sort_in_record<T> (list: &mut VecDeque<SensorRecord<T>>, rec: SensorRecord<T>) where T: RecordDataBounds {
+    if let Some(r) = list.iter_mut().find( |r| r.id == rec.id) {
+        *r = rec;
+        return
+    }
+
     let mut i=0;
     for r in list.iter() {
         if (rec.time_recorded > r.time_recorded) {
@@ -588,30 +1018,133 @@ pub struct SentinelConfig {
     pub max_age: Duration,
     pub ping_interval: Option<Duration>, // interval duration for sending Ping messages on the websocket
 
+    #[serde(default)]
+    pub device_names: HashMap<DeviceId,String>, // optional (native) device_id -> device_name map
+
+    #[serde(default)]
+    pub device_filter: DeviceFilter, // which devices and capabilities we retrieve
+
+    #[serde(default)]
+    pub device_refresh_interval: Option<Duration>, // interval for re-querying the device list to pick up new/removed devices
+
+    #[serde(default)]
+    pub checkpoint: Option<CheckpointConfig>, // optional snapshot persistence for warm starts
+
     //... and a lot more to come
+}
 
-    // TODO - add optional device_id -> device_name map 
+impl SentinelConfig {
+    /// the display name for a device - either from the configured names, the server provided device info or the device id
+    pub fn device_name (&self, device: &Device)->String {
+        if let Some(name) = self.device_names.get( &device.id) {
+            name.clone()
+        } else if let Some(info) = &device.info {
+            info.clone()
+        } else {
+            device.id.clone()
+        }
+    }
 }
 
-/* #endregion config */
+/// device and capability filter that is applied to init queries, websocket joins and records received
+/// through the websocket. Device patterns are matched against native device ids and can contain '*' wildcards
+#[derive(Deserialize,Serialize,Debug,Clone,Default)]
+#[serde(default)]
+pub struct DeviceFilter {
+    pub include: Vec<String>, // device id patterns - if empty all devices are included
+    pub exclude: Vec<String>, // device id patterns that override includes
+    pub capabilities: Vec<SensorCapability>, // capabilities to retrieve - if empty all capabilities are retrieved
+}
 
-/* #region initial query ******************************************************************************/
+impl DeviceFilter {
+    pub fn accepts_device (&self, device_id: &str)->bool {
+        (self.include.is_empty() || self.include.iter().any( |p| matches_pattern( p, device_id)))
+          && !self.exclude.iter().any( |p| matches_pattern( p, device_id))
+    }
 
-pub async fn init_sentinel_store (client: &Client, base_uri: &str, access_token: &str, n_last: usize)->Result<SentinelStore> {
-    let mut sentinel_store = SentinelStore::new();
+    pub fn accepts_capability (&self, capability: SensorCapability)->bool {
+        self.capabilities.is_empty() || self.capabilities.contains( &capability)
+    }
 
-    let device_list = get_device_list( client, base_uri, access_token).await?;
-    for device in &device_list.data {
-        let device_name = if let Some(info) = &device.info { info.clone() } else { "unknown".to_string() };
-        let mut sentinel = Sentinel::new( device.id.clone(), device_name);
+    pub fn filter_device_list (&self, device_list: DeviceList)->DeviceList {
+        DeviceList { data: device_list.data.into_iter().filter( |d| self.accepts_device( &d.id)).collect() }
+    }
+}
+
+/// escape text for XML content and attribute values (used by KML, CoT and CAP output)
+pub fn xml_escape (s: &str)->String {
+    let mut out = String::with_capacity( s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            _ => out.push(c)
+        }
+    }
+    out
+}
 
-        let sensor_list = get_sensor_list( client, base_uri, access_token, device.id.as_str()).await?;
-        for sensor_data in &sensor_list.data {
-            for capability in &sensor_data.capabilities {
-                sentinel.get_and_store_records(client, base_uri, access_token, sensor_data.no, *capability, n_last).await?;
+/// simple wildcard match where '*' in the pattern matches any (possibly empty) sequence of chars
+pub fn matches_pattern (pattern: &str, s: &str)->bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    if !s.starts_with(first) { return false }
+
+    let mut rest = &s[first.len()..];
+    let mut parts: Vec<&str> = parts.collect();
+    if let Some(last) = parts.pop() { // there was at least one '*'
+        for p in parts {
+            if let Some(i) = rest.find(p) {
+                rest = &rest[i+p.len()..];
+            } else {
+                return false
             }
         }
+        rest.len() >= last.len() && rest.ends_with(last)
+    } else {
+        rest.is_empty()
+    }
+}
+
+/// where and how often we save SentinelStore snapshots. If the file exists at startup the connector
+/// loads it and makes the contained sentinels available before querying the servers
+#[derive(Deserialize,Serialize,Debug,Clone)]
+pub struct CheckpointConfig {
+    pub path: PathBuf,
+    pub interval: Option<Duration>, // if not set we only save on termination
+}
 
+/// config for connectors that merge several Delphire backends into one SentinelStore
+#[derive(Deserialize,Serialize)]
+pub struct MultiSentinelConfig {
+    pub sources: Vec<SentinelSourceConfig>,
+
+    #[serde(default)]
+    pub checkpoint: Option<CheckpointConfig>, // the checkpoint of the merged store - source checkpoint configs are ignored
+}
+
+/// a single named backend. The id is used to namespace device ids of this backend
+#[derive(Deserialize,Serialize)]
+pub struct SentinelSourceConfig {
+    pub id: SourceId,
+    pub config: SentinelConfig
+}
+
+/* #endregion config */
+
+/* #region initial query ******************************************************************************/
+
+pub async fn init_sentinel_store (client: &Client, base_uri: &str, access_token: &str, n_last: usize, filter: &DeviceFilter,
+                                  device_name: impl Fn(&Device)->String, resume: &ResumePoints)->Result<SentinelStore> {
+    let mut sentinel_store = SentinelStore::new();
+
+    let device_list = filter.filter_device_list( get_device_list( client, base_uri, access_token).await?);
+    for device in &device_list.data {
+        let since = resume.get( &device.id).cloned();
+        let sentinel = init_sentinel( client, base_uri, access_token, n_last, filter, device, device_name(device), since).await?;
         sentinel_store.insert( sentinel.device_id.clone(), sentinel);
     }
 
@@ -619,7 +1152,37 @@ pub async fn init_sentinel_store (client: &Client, base_uri: &str, access_token:
 }
 
 pub async fn init_sentinel_store_from_config (client: &Client, config: &SentinelConfig)->Result<SentinelStore> {
-    init_sentinel_store(client, config.base_uri.as_str(), config.access_token.as_str(), config.max_history_len).await
+    resume_sentinel_store_from_config( client, config, &ResumePoints::new()).await
+}
+
+/// initialize a store for a warm start, only retrieving records of known devices that are newer than their resume points
+pub async fn resume_sentinel_store_from_config (client: &Client, config: &SentinelConfig, resume: &ResumePoints)->Result<SentinelStore> {
+    init_sentinel_store(client, config.base_uri.as_str(), config.access_token.as_str(), config.max_history_len,
+                        &config.device_filter, |device| config.device_name(device), resume).await
+}
+
+/// create a Sentinel for the given device and retrieve the last n records for all its (accepted) sensor capabilities.
+/// If `since` is set we only retrieve records that were recorded after it
+pub async fn init_sentinel (client: &Client, base_uri: &str, access_token: &str, n_last: usize, filter: &DeviceFilter,
+                            device: &Device, device_name: String, since: Option<DateTime<Utc>>)->Result<Sentinel> {
+    let mut sentinel = Sentinel::new( device.id.clone(), device_name);
+
+    let sensor_list = get_sensor_list( client, base_uri, access_token, device.id.as_str()).await?;
+    for sensor_data in &sensor_list.data {
+        for capability in &sensor_data.capabilities {
+            if filter.accepts_capability( *capability) {
+                sentinel.get_and_store_records(client, base_uri, access_token, sensor_data.no, *capability, n_last, since).await?;
+            }
+        }
+    }
+    sentinel.sensors = sensor_list.data;
+
+    Ok(sentinel)
+}
+
+pub async fn init_sentinel_from_config (client: &Client, config: &SentinelConfig, device: &Device)->Result<Sentinel> {
+    init_sentinel( client, config.base_uri.as_str(), config.access_token.as_str(), config.max_history_len,
+                   &config.device_filter, device, config.device_name(device), None).await
 }
 
 /* #endregion initial query */
@@ -633,8 +1196,10 @@ pub async fn get_device_list (client: &Client, base_uri: &str, access_token: &st
     Ok(device_list)
 }
 
+/// get the list of devices that are accepted by the configured device filter
 pub async fn get_device_list_from_config (client: &Client, config: &SentinelConfig)->Result<DeviceList> {
-    get_device_list( client, &config.base_uri, &config.access_token).await
+    let device_list = get_device_list( client, &config.base_uri, &config.access_token).await?;
+    Ok(config.device_filter.filter_device_list( device_list))
 }
 
 pub async fn get_sensor_list (client: &Client, base_uri: &str, access_token: &str, device_id: &str) -> Result<SensorList> {
@@ -655,6 +1220,38 @@ pub async fn get_records <T> (client: &Client, base_uri: &str, access_token: &st
     Ok(record_list.data)
 } 
 
+/// page size for resumed record queries. This is smaller than the usual history length since we expect to
+/// find the resume point within the first few records
+const RESUME_PAGE_SIZE: usize = 10;
+
+/// get the last n records that were recorded after `since`, newest first. We page through the server history
+/// until we reach `since` so that we don't retrieve records we already have
+pub async fn get_records_since <T> (client: &Client, base_uri: &str, access_token: &str, 
+                                    device_id: &str, sensor_no: u32, n_last: usize, since: DateTime<Utc>) -> Result<Vec<SensorRecord<T>>> 
+    where T: RecordDataBounds
+{
+    let capability = T::capability();
+    let page_size = n_last.min( RESUME_PAGE_SIZE);
+    let mut recs = Vec::new();
+    let mut page = 1;
+
+    while recs.len() < n_last {
+        let uri = format!("{base_uri}/devices/{device_id}/sensors/{sensor_no}/{capability:?}?sort=timeRecorded,DESC&limit={page_size}&page={page}");
+        let response = client.get(uri).bearer_auth(access_token).send().await?;
+        let record_list: RecordList<T> = response.json().await?;
+        let n_recs = record_list.data.len();
+
+        for rec in record_list.data {
+            if rec.time_recorded <= since || recs.len() >= n_last { return Ok(recs) }
+            recs.push( rec);
+        }
+
+        if n_recs < page_size { break }
+        page += 1;
+    }
+    Ok(recs)
+}
+
 pub async fn get_latest_record <T> (client: &Client, base_uri: &str, access_token: &str, 
                                     device_id: &str, sensor_no:u32) -> Result<SensorRecord<T>> 
     where T: RecordDataBounds
@@ -667,4 +1264,53 @@ pub async fn get_latest_record <T> (client: &Client, base_uri: &str, access_toke
     }
 }
 
+/// get the latest record of a sensor capability, wrapped into a SentinelUpdate
+pub async fn get_latest_update (client: &Client, base_uri: &str, access_token: &str, 
+                                device_id: &str, sensor_no: u32, capability: SensorCapability)->Result<SentinelUpdate> {
+    let uri = format!("{base_uri}/devices/{device_id}/sensors/{sensor_no}/{capability:?}?sort=timeRecorded,DESC&limit=1");
+    let response = client.get(uri).bearer_auth(access_token).send().await?;
+    let mut value: serde_json::Value = response.json().await?;
+    match value.get_mut("data").map( |v| v.take()) {
+        Some(serde_json::Value::Array(mut list)) if !list.is_empty() => SentinelUpdate::from_json( capability, list.remove(0)),
+        _ => Err(no_data(format!("for device: {}, sensor: {}, capability: {:?}", device_id, sensor_no, capability)))
+    }
+}
+
+/// get a page of records as generic JSON values, newest first. Pages are 1-based (as reported in server responses)
+pub async fn get_json_records (client: &Client, base_uri: &str, access_token: &str, device_id: &str, sensor_no: u32,
+                               capability: SensorCapability, limit: usize, page: usize)->Result<Vec<serde_json::Value>> {
+    let uri = format!("{base_uri}/devices/{device_id}/sensors/{sensor_no}/{capability:?}?sort=timeRecorded,DESC&limit={limit}&page={page}");
+    let response = client.get(uri).bearer_auth(access_token).send().await?;
+    let mut value: serde_json::Value = response.json().await?;
+    match value.get_mut("data").map( |v| v.take()) {
+        Some(serde_json::Value::Array(list)) => Ok(list),
+        _ => Err( no_data( format!("records for device: {}, sensor: {}, capability: {:?}", device_id, sensor_no, capability)))
+    }
+}
+
+/// page through the server history of a sensor capability and return all records within [start,end], newest first
+pub async fn get_record_history (client: &Client, base_uri: &str, access_token: &str, device_id: &str, sensor_no: u32,
+                                 capability: SensorCapability, start: DateTime<Utc>, end: DateTime<Utc>, page_size: usize)->Result<Vec<serde_json::Value>> {
+    let mut history = Vec::new();
+    let mut page = 1;
+
+    loop {
+        let recs = get_json_records( client, base_uri, access_token, device_id, sensor_no, capability, page_size, page).await?;
+        let n_recs = recs.len();
+        let mut reached_start = false;
+
+        for rec in recs {
+            match json_record_time( &rec) {
+                Some(t) if t < start => { reached_start = true; break }
+                Some(t) if t <= end => history.push( rec),
+                _ => {}
+            }
+        }
+
+        if reached_start || n_recs < page_size { break }
+        page += 1;
+    }
+    Ok(history)
+}
+
 /* #endregion basic http getters */
\ No newline at end of file
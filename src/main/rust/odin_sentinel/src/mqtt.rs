@@ -0,0 +1,217 @@
+/*
+ * Copyright (c) 2024, United States Government, as represented by the
+ * Administrator of the National Aeronautics and Space Administration.
+ * All rights reserved.
+ *
+ * The RACE - Runtime for Airspace Concept Evaluation platform is licensed
+ * under the Apache License, Version 2.0 (the "License"); you may not use
+ * this file except in compliance with the License. You may obtain a copy
+ * of the License at http://www.apache.org/licenses/LICENSE-2.0.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! MQTT bridge for SentinelConnector updates. Each JSON record is published (optionally retained) to
+//! `{topic_prefix}/{device_id}/{capability}/{sensor_no}`, and WsCmd JSON messages received on the
+//! command topic are forwarded to the connector, which routes them to the backend that owns the devices.
+//! Each time we (re-)connect to the broker we publish the newest record of each topic from a connector snapshot
+
+use std::{collections::HashMap,sync::Arc,time::Duration};
+use serde::{Deserialize,Serialize};
+use rumqttc::{AsyncClient,EventLoop,MqttOptions,QoS,Event,Packet};
+use odin_actor::prelude::*;
+use odin_actor::tokio_kanal::{Actor,ActorHandle,JoinHandle,spawn};
+use crate::*;
+use crate::ws::WsCmd;
+use crate::actor::{SentinelConnectorMsg,AddInitCallback,AddJsonUpdateCallback,TriggerJsonSnapshot,SendWsCmd,
+                   SentinelsInitialized,SentinelJsonSnapshot,SentinelJsonUpdate};
+
+#[derive(Deserialize,Serialize,Debug,Clone)]
+pub struct MqttConfig {
+    pub host: String,
+    pub port: u16,
+    pub client_id: String,
+    pub credentials: Option<(String,String)>, // (user,password)
+    pub keep_alive: Duration,
+
+    pub topic_prefix: String,      // e.g. "sentinel"
+    pub qos: u8,                   // 0,1 or 2
+    pub retain: bool,              // publish records as retained messages so that new subscribers get the latest values
+    pub cmd_topic: Option<String>, // topic for inbound WsCmd JSON messages
+}
+
+pub fn qos (level: u8)->QoS {
+    match level {
+        0 => QoS::AtMostOnce,
+        1 => QoS::AtLeastOnce,
+        _ => QoS::ExactlyOnce
+    }
+}
+
+/// the topic for a JSON record, or None if it isn't a valid record
+pub fn record_topic (topic_prefix: &str, json: &str)->Option<String> {
+    let value: serde_json::Value = serde_json::from_str( json).ok()?;
+    let device_id = value.get("deviceId")?.as_str()?;
+    let sensor_no = value.get("sensorNo")?.as_u64()?;
+    let capability = SensorCapability::of_record( &value)?;
+
+    Some( format!("{}/{}/{}/{}", topic_prefix, device_id, capability.property_name(), sensor_no))
+}
+
+/// the topic for a record update
+pub fn update_topic (topic_prefix: &str, update: &SentinelUpdate)->String {
+    format!("{}/{}/{}/{}", topic_prefix, update.device_id(), update.capability().property_name(), update.sensor_no())
+}
+
+/// the (topic,payload) messages for the newest record of each topic in a SentinelStore, which is what
+/// we publish (retained) when we connect so that subscribers get the current state
+pub fn snapshot_messages (topic_prefix: &str, sentinels: &SentinelStore)->Result<Vec<(String,String)>> {
+    let mut latest: HashMap<String,String> = HashMap::new();
+    for sentinel in sentinels.values() {
+        for update in sentinel.iter_all() { // ascending time order, i.e. newer records replace older ones
+            latest.insert( update_topic( topic_prefix, &update), serde_json::to_string( &update)?);
+        }
+    }
+    Ok( latest.into_iter().collect())
+}
+
+/// publish (topic,payload) messages with the configured QoS and retain flag. This only queues the messages,
+/// they are sent by the event loop
+pub async fn publish_messages (client: &AsyncClient, config: &MqttConfig, msgs: Vec<(String,String)>)->Result<()> {
+    for (topic, payload) in msgs {
+        client.publish( topic, qos( config.qos), config.retain, payload.into_bytes()).await?;
+    }
+    Ok(())
+}
+
+/// the task that polls the MQTT event loop (which drives the connection) and forwards commands to the connector.
+/// Since rumqttc does not restore subscriptions when it reconnects we (re-)subscribe each time we get a ConnAck,
+/// which we also report to the bridge so that it can re-publish the current state
+async fn run_event_loop (mut event_loop: EventLoop, client: AsyncClient, config: MqttConfig,
+                         hself: ActorHandle<MqttBridgeMsg>, hconn: ActorHandle<SentinelConnectorMsg>) {
+    loop {
+        match event_loop.poll().await {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                if let Some(cmd_topic) = &config.cmd_topic {
+                    // don't await here - this is the task that has to process the subscribe request
+                    if let Err(e) = client.try_subscribe( cmd_topic, qos( config.qos)) {
+                        eprintln!("@@ failed to subscribe MQTT command topic: {:?}", e);
+                    }
+                }
+                hself.send_msg( MqttConnected).await.ok();
+            }
+            Ok(Event::Incoming(Packet::Publish(p))) => {
+                if config.cmd_topic.as_ref().map_or( false, |t| *t == p.topic) {
+                    match serde_json::from_slice::<WsCmd>( &p.payload) {
+                        Ok(cmd) => { hconn.send_msg( SendWsCmd(cmd)).await.ok(); }
+                        Err(e) => eprintln!("@@ invalid MQTT command: {:?}", e)
+                    }
+                }
+            }
+            Ok(_) => {} // we don't care about other events
+            Err(e) => {
+                eprintln!("@@ MQTT connection error: {:?}", e);
+                tokio::time::sleep( Duration::from_secs(5)).await; // polling again reconnects
+            }
+        }
+    }
+}
+
+/// internal message sent by the event loop task each time we are (re-)connected to the broker
+#[derive(Debug)] pub struct MqttConnected;
+
+define_actor_msg_type! { pub MqttBridgeMsg = SentinelsInitialized | SentinelJsonSnapshot | SentinelJsonUpdate | MqttConnected }
+
+pub struct MqttBridge {
+    config: MqttConfig,
+    hconn: ActorHandle<SentinelConnectorMsg>,
+    initialized: bool, // set once the connector has data
+    client: Option<AsyncClient>,
+    event_loop_task: Option<JoinHandle<()>>,
+}
+
+impl MqttBridge {
+    pub fn new (config: MqttConfig, hconn: ActorHandle<SentinelConnectorMsg>)->Self {
+        MqttBridge { config, hconn, initialized: false, client: None, event_loop_task: None }
+    }
+
+    fn connect (&mut self, hself: ActorHandle<MqttBridgeMsg>) {
+        let mut opts = MqttOptions::new( &self.config.client_id, &self.config.host, self.config.port);
+        opts.set_keep_alive( self.config.keep_alive);
+        if let Some((user,pw)) = &self.config.credentials {
+            opts.set_credentials( user, pw);
+        }
+
+        let (client, event_loop) = AsyncClient::new( opts, 64);
+        self.event_loop_task = Some( spawn( run_event_loop( event_loop, client.clone(), self.config.clone(), hself, self.hconn.clone())));
+        self.client = Some(client);
+    }
+
+    async fn publish (&self, json: Arc<String>)->Result<()> {
+        if let Some(client) = &self.client {
+            let topic = record_topic( &self.config.topic_prefix, &json).ok_or( op_failed("not a sensor record"))?;
+            publish_messages( client, &self.config, vec![ (topic, json.to_string()) ]).await?;
+        }
+        Ok(())
+    }
+
+    async fn publish_snapshot (&self, json: &str)->Result<()> {
+        if let Some(client) = &self.client {
+            let sentinels = SentinelStore::from_json( json)?;
+            publish_messages( client, &self.config, snapshot_messages( &self.config.topic_prefix, &sentinels)?).await?;
+        }
+        Ok(())
+    }
+
+    async fn request_snapshot (&self, hself: &ActorHandle<MqttBridgeMsg>) {
+        self.hconn.send_msg( TriggerJsonSnapshot( msg_callback!( hself, |json:String| SentinelJsonSnapshot(json)))).await.ok();
+    }
+
+    fn disconnect (&mut self) {
+        if let Some(join_handle) = &self.event_loop_task {
+            join_handle.abort();
+        }
+        self.event_loop_task = None;
+        self.client = None;
+    }
+}
+
+impl_actor! { match msg for Actor<MqttBridge,MqttBridgeMsg> as
+    _Start_ => cont! {
+        let hself = self.hself.clone();
+        self.connect( hself);
+
+        let id = self.id().to_string();
+        let hself = &self.hself;
+        self.hconn.send_msg( AddInitCallback{id, action: msg_callback!(hself, SentinelsInitialized)}).await.ok();
+    }
+    SentinelsInitialized => cont! {
+        self.initialized = true;
+        let hself = &self.hself;
+        self.request_snapshot( hself).await;
+        self.hconn.send_msg( AddJsonUpdateCallback {id: self.id().to_string(), action: msg_callback!( hself, |json:Arc<String>| SentinelJsonUpdate(json))}).await.ok();
+    }
+    MqttConnected => cont! {
+        if self.initialized { // otherwise we publish the snapshot once the connector has data
+            let hself = &self.hself;
+            self.request_snapshot( hself).await;
+        }
+    }
+    SentinelJsonSnapshot => cont! {
+        if let Err(e) = self.publish_snapshot( &msg.0).await {
+            eprintln!("@@ failed to publish MQTT snapshot: {:?}", e);
+        }
+    }
+    SentinelJsonUpdate => cont! {
+        if let Err(e) = self.publish( msg.0).await {
+            eprintln!("@@ failed to publish MQTT message: {:?}", e);
+        }
+    }
+    _Terminate_ => stop! {
+        self.disconnect()
+    }
+}
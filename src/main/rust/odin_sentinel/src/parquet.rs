@@ -0,0 +1,166 @@
+/*
+ * Copyright (c) 2024, United States Government, as represented by the
+ * Administrator of the National Aeronautics and Space Administration.
+ * All rights reserved.
+ *
+ * The RACE - Runtime for Airspace Concept Evaluation platform is licensed
+ * under the Apache License, Version 2.0 (the "License"); you may not use
+ * this file except in compliance with the License. You may obtain a copy
+ * of the License at http://www.apache.org/licenses/LICENSE-2.0.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! columnar (Apache Arrow / Parquet) export of sensor records. Each capability is flattened into its own Arrow
+//! schema with fixed `id`, `device_id`, `sensor_no`, `time_recorded`, `evidences` and `claims` columns, followed by
+//! the (flattened) payload fields. Since we use the JSON representation of records, uom quantities are already
+//! SI floats (K, m/s, V, A). Parquet files are partitioned by capability, device and day:
+//!     {dir}/{capability}/device_id={device_id}/date={yyyy-mm-dd}/records.parquet
+
+use std::{collections::BTreeMap,fs::{self,File},path::{Path,PathBuf},sync::Arc};
+use chrono::{DateTime,Utc};
+use serde_json::Value;
+use ::arrow::array::{ArrayRef,BooleanArray,Float64Array,Int64Array,StringArray,TimestampMillisecondArray,UInt32Array};
+use ::arrow::datatypes::{DataType,Field,Schema,TimeUnit};
+use ::arrow::record_batch::RecordBatch;
+use ::parquet::arrow::ArrowWriter;
+use strum::IntoEnumIterator;
+use crate::*;
+
+fn record_ids (rec: &Value, key: &str)->String {
+    rec.get(key).and_then( |v| v.as_array()).map( |list| {
+        list.iter().filter_map( |r| r.get("id").and_then( |id| id.as_str())).collect::<Vec<&str>>().join(",")
+    }).unwrap_or_default()
+}
+
+/// the Arrow type of a payload column, inferred from its (non-null) values
+fn column_type (values: &[Value])->DataType {
+    let non_null: Vec<&Value> = values.iter().filter( |v| !v.is_null()).collect();
+    if non_null.is_empty() {
+        DataType::Utf8
+    } else if non_null.iter().all( |v| v.is_i64()) {
+        DataType::Int64
+    } else if non_null.iter().all( |v| v.is_number()) {
+        DataType::Float64
+    } else if non_null.iter().all( |v| v.is_boolean()) {
+        DataType::Boolean
+    } else {
+        DataType::Utf8
+    }
+}
+
+fn column_array (data_type: &DataType, values: &[Value])->ArrayRef {
+    match data_type {
+        DataType::Int64 => Arc::new( values.iter().map( |v| v.as_i64()).collect::<Int64Array>()),
+        DataType::Float64 => Arc::new( values.iter().map( |v| v.as_f64()).collect::<Float64Array>()),
+        DataType::Boolean => Arc::new( values.iter().map( |v| v.as_bool()).collect::<BooleanArray>()),
+        _ => Arc::new( values.iter().map( |v| match v {
+            Value::Null => None,
+            Value::String(s) => Some(s.clone()),
+            other => Some(other.to_string())
+        }).collect::<StringArray>())
+    }
+}
+
+/// flatten JSON records of the given capability into a single Arrow RecordBatch. Records that are not
+/// well formed are skipped
+pub fn record_batch (capability: SensorCapability, records: &[Value])->Result<RecordBatch> {
+    let records: Vec<&Value> = records.iter().filter( |r| json_record_time(r).is_some()).collect();
+
+    // payload columns in order of first appearance
+    let mut names: Vec<String> = Vec::new();
+    let mut rows: Vec<BTreeMap<String,Value>> = Vec::with_capacity( records.len());
+    for rec in &records {
+        let mut columns = Vec::new();
+        if let Some(payload) = rec.get( capability.property_name()) {
+            flatten_json( "", payload, &mut columns);
+        }
+        for (name,_) in &columns {
+            if !names.contains(name) { names.push( name.clone()) }
+        }
+        rows.push( columns.into_iter().collect());
+    }
+
+    let mut fields = vec![
+        Field::new( "id", DataType::Utf8, false),
+        Field::new( "device_id", DataType::Utf8, false),
+        Field::new( "sensor_no", DataType::UInt32, false),
+        Field::new( "time_recorded", DataType::Timestamp( TimeUnit::Millisecond, Some("UTC".into())), false),
+        Field::new( "evidences", DataType::Utf8, false),
+        Field::new( "claims", DataType::Utf8, false),
+    ];
+    let mut columns: Vec<ArrayRef> = vec![
+        Arc::new( StringArray::from( records.iter().map( |r| r.get("id").and_then( |v| v.as_str()).unwrap_or_default()).collect::<Vec<&str>>())),
+        Arc::new( StringArray::from( records.iter().map( |r| r.get("deviceId").and_then( |v| v.as_str()).unwrap_or_default()).collect::<Vec<&str>>())),
+        Arc::new( UInt32Array::from( records.iter().map( |r| r.get("sensorNo").and_then( |v| v.as_u64()).unwrap_or_default() as u32).collect::<Vec<u32>>())),
+        Arc::new( TimestampMillisecondArray::from( records.iter().filter_map( |r| json_record_time(r)).map( |t| t.timestamp_millis()).collect::<Vec<i64>>()).with_timezone("UTC")),
+        Arc::new( StringArray::from( records.iter().map( |r| record_ids( r, "evidences")).collect::<Vec<String>>())),
+        Arc::new( StringArray::from( records.iter().map( |r| record_ids( r, "claims")).collect::<Vec<String>>())),
+    ];
+
+    for name in &names {
+        let values: Vec<Value> = rows.iter().map( |row| row.get(name).cloned().unwrap_or(Value::Null)).collect();
+        let data_type = column_type( &values);
+        columns.push( column_array( &data_type, &values));
+        fields.push( Field::new( name, data_type, true));
+    }
+
+    Ok( RecordBatch::try_new( Arc::new( Schema::new( fields)), columns)? )
+}
+
+/// write records into a single Parquet file
+pub fn write_parquet_file (path: &Path, capability: SensorCapability, records: &[Value])->Result<()> {
+    let batch = record_batch( capability, records)?;
+    let file = File::create( path)?;
+    let mut writer = ArrowWriter::try_new( file, batch.schema(), None)?;
+    writer.write( &batch)?;
+    writer.close()?;
+    Ok(())
+}
+
+fn partition_name (s: &str)->String {
+    s.chars().map( |c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' { c } else { '_' }).collect()
+}
+
+/// write records of a capability partitioned by device and day, returning the paths of the written files
+pub fn write_partitioned (dir: &Path, capability: SensorCapability, records: &[Value])->Result<Vec<PathBuf>> {
+    let mut partitions: BTreeMap<(String,String),Vec<Value>> = BTreeMap::new();
+    for rec in records {
+        if let (Some(device_id), Some(t)) = (rec.get("deviceId").and_then( |v| v.as_str()), json_record_time( rec)) {
+            let key = (device_id.to_string(), t.format("%Y-%m-%d").to_string());
+            partitions.entry( key).or_default().push( rec.clone());
+        }
+    }
+
+    let mut paths = Vec::new();
+    for ((device_id,date), mut recs) in partitions {
+        recs.sort_by_key( |r| json_record_time(r)); // ascending within files
+        let part_dir = dir.join( capability.property_name())
+                          .join( format!("device_id={}", partition_name( &device_id)))
+                          .join( format!("date={date}"));
+        fs::create_dir_all( &part_dir)?;
+
+        let path = part_dir.join( "records.parquet");
+        write_parquet_file( &path, capability, &recs)?;
+        paths.push( path);
+    }
+    Ok(paths)
+}
+
+impl SentinelStore {
+    /// export the records of the given capabilities (all if empty) as partitioned Parquet files
+    pub fn export_parquet (&self, dir: &Path, capabilities: &[SensorCapability])->Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        for capability in SensorCapability::iter().filter( |c| capabilities.is_empty() || capabilities.contains(c)) {
+            let records = self.json_records( capability)?;
+            if !records.is_empty() {
+                paths.append( &mut write_partitioned( dir, capability, &records)?);
+            }
+        }
+        Ok(paths)
+    }
+}
@@ -0,0 +1,158 @@
+/*
+ * Copyright (c) 2024, United States Government, as represented by the
+ * Administrator of the National Aeronautics and Space Administration.
+ * All rights reserved.
+ *
+ * The RACE - Runtime for Airspace Concept Evaluation platform is licensed
+ * under the Apache License, Version 2.0 (the "License"); you may not use
+ * this file except in compliance with the License. You may obtain a copy
+ * of the License at http://www.apache.org/licenses/LICENSE-2.0.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! camera pointing geometry derived from orientation records. OrientationData quaternions are interpreted as the
+//! rotation from the sensor body frame (x: camera boresight, y: right, z: down) into the local North-East-Down frame,
+//! i.e. the boresight of an unrotated camera points north along the horizon
+
+use serde::Serialize;
+use chrono::{DateTime,Utc};
+use crate::*;
+use crate::position::PositionConfig;
+
+/// default length of detection rays in meters
+pub const DEFAULT_RAY_LENGTH: f64 = 5000.0;
+
+const EARTH_RADIUS: f64 = 6_371_000.0; // mean radius in meters
+
+/// Tait-Bryan angles in degrees (z-y'-x'' sequence, i.e. yaw is applied first)
+#[derive(Serialize,Debug,Clone,Copy,PartialEq)]
+pub struct EulerAngles {
+    pub roll: f64,
+    pub pitch: f64,
+    pub yaw: f64,
+}
+
+/// where the camera boresight points to
+#[derive(Serialize,Debug,Clone,Copy,PartialEq)]
+pub struct CameraPointing {
+    pub azimuth: f64,   // degrees clockwise from true north [0,360)
+    pub elevation: f64, // degrees above the horizon [-90,90]
+}
+
+fn unit_quaternion (o: &OrientationData)->(f64,f64,f64,f64) {
+    let norm = (o.w*o.w + o.qx*o.qx + o.qy*o.qy + o.qz*o.qz).sqrt();
+    if norm > 0.0 { (o.w/norm, o.qx/norm, o.qy/norm, o.qz/norm) } else { (1.0, 0.0, 0.0, 0.0) }
+}
+
+pub fn euler_angles (o: &OrientationData)->EulerAngles {
+    let (w,x,y,z) = unit_quaternion( o);
+
+    let roll = (2.0*(w*x + y*z)).atan2( 1.0 - 2.0*(x*x + y*y));
+    let sin_pitch = 2.0*(w*y - z*x);
+    let pitch = if sin_pitch.abs() >= 1.0 { std::f64::consts::FRAC_PI_2.copysign( sin_pitch) } else { sin_pitch.asin() };
+    let yaw = (2.0*(w*z + x*y)).atan2( 1.0 - 2.0*(y*y + z*z));
+
+    EulerAngles { roll: roll.to_degrees(), pitch: pitch.to_degrees(), yaw: yaw.to_degrees() }
+}
+
+pub fn camera_pointing (o: &OrientationData)->CameraPointing {
+    let (w,x,y,z) = unit_quaternion( o);
+
+    // the rotated boresight (body x-axis) in NED coordinates is the first column of the rotation matrix
+    let n = 1.0 - 2.0*(y*y + z*z);
+    let e = 2.0*(x*y + w*z);
+    let d = 2.0*(x*z - w*y);
+
+    let azimuth = e.atan2( n).to_degrees().rem_euclid( 360.0);
+    let elevation = (-d).atan2( n.hypot( e)).to_degrees();
+    CameraPointing { azimuth, elevation }
+}
+
+/// the (lat,lon) in degrees at the given distance (meters) and azimuth (degrees) from a start point (great circle)
+pub fn destination (lat: f64, lon: f64, azimuth: f64, distance: f64)->(f64,f64) {
+    let (phi1, lambda1, theta) = (lat.to_radians(), lon.to_radians(), azimuth.to_radians());
+    let delta = distance / EARTH_RADIUS;
+
+    let phi2 = (phi1.sin()*delta.cos() + phi1.cos()*delta.sin()*theta.cos()).asin();
+    let lambda2 = lambda1 + (theta.sin()*delta.sin()*phi1.cos()).atan2( delta.cos() - phi1.sin()*phi2.sin());
+
+    (phi2.to_degrees(), ((lambda2.to_degrees() + 540.0) % 360.0) - 180.0)
+}
+
+/// great circle distance in meters between two (lat,lon) points given in degrees
+pub fn distance (lat1: f64, lon1: f64, lat2: f64, lon2: f64)->f64 {
+    let (phi1, phi2) = (lat1.to_radians(), lat2.to_radians());
+    let dphi = phi2 - phi1;
+    let dlambda = (lon2 - lon1).to_radians();
+
+    let a = (dphi/2.0).sin().powi(2) + phi1.cos()*phi2.cos()*(dlambda/2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * a.sqrt().atan2( (1.0 - a).sqrt())
+}
+
+/// the orientation record referenced by an image record
+pub fn image_orientation<'a> (sentinel: &'a Sentinel, image: &SensorRecord<ImageData>)->Option<&'a SensorRecord<OrientationData>> {
+    let record_id = image.data.orientation_record.as_ref()?;
+    sentinel.records::<OrientationData>().iter().find( |r| r.id == record_id.id)
+}
+
+/// a line of sight from the sentinel position along the camera boresight
+#[derive(Serialize,Debug,Clone)]
+#[serde(rename_all="camelCase")]
+pub struct DetectionRay {
+    pub device_id: DeviceId,
+    pub record_id: String, // the detection (or image) record this ray belongs to
+    pub image_id: String,
+    pub time_recorded: DateTime<Utc>,
+    pub origin: (f64,f64), // (lat,lon) degrees
+    pub end: (f64,f64),    // (lat,lon) degrees
+    pub pointing: CameraPointing,
+}
+
+/// the ray of an image record, or None if we don't have its orientation or any position of the sentinel
+pub fn image_ray (sentinel: &Sentinel, image: &SensorRecord<ImageData>, position: &PositionConfig, length: f64)->Option<DetectionRay> {
+    let pointing = camera_pointing( &image_orientation( sentinel, image)?.data);
+
+    // use the position estimate when the image was taken, or the oldest one if there is none
+    let pos = sentinel.position_at( position, image.time_recorded)?;
+    let origin = (pos.latitude, pos.longitude);
+
+    // rays pointing above the horizon are drawn with full length, the ground intersection is not our business here
+    let end = destination( origin.0, origin.1, pointing.azimuth, length);
+
+    Some( DetectionRay {
+        device_id: sentinel.device_id.clone(),
+        record_id: image.id.clone(),
+        image_id: image.id.clone(),
+        time_recorded: image.time_recorded,
+        origin, end, pointing
+    })
+}
+
+/// the rays of all evidence images of a detection (fire or smoke) record
+pub fn detection_rays (sentinel: &Sentinel, record_id: &str, evidences: &[RecordId], position: &PositionConfig, length: f64)->Vec<DetectionRay> {
+    evidences.iter()
+        .filter_map( |e| sentinel.image_record( e))
+        .filter_map( |img| image_ray( sentinel, img, position, length))
+        .map( |ray| DetectionRay { record_id: record_id.to_string(), ..ray })
+        .collect()
+}
+
+impl Sentinel {
+    /// the rays of all fire and smoke detections we have, newest first
+    pub fn detection_rays (&self, position: &PositionConfig, length: f64)->Vec<DetectionRay> {
+        let mut rays: Vec<DetectionRay> = Vec::new();
+        for rec in self.records::<FireData>() {
+            rays.extend( detection_rays( self, &rec.id, &rec.evidences, position, length));
+        }
+        for rec in self.records::<SmokeData>() {
+            rays.extend( detection_rays( self, &rec.id, &rec.evidences, position, length));
+        }
+        rays.sort_by( |a,b| b.time_recorded.cmp( &a.time_recorded));
+        rays
+    }
+}
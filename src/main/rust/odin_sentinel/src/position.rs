@@ -0,0 +1,183 @@
+/*
+ * Copyright (c) 2024, United States Government, as represented by the
+ * Administrator of the National Aeronautics and Space Administration.
+ * All rights reserved.
+ *
+ * The RACE - Runtime for Airspace Concept Evaluation platform is licensed
+ * under the Apache License, Version 2.0 (the "License"); you may not use
+ * this file except in compliance with the License. You may obtain a copy
+ * of the License at http://www.apache.org/licenses/LICENSE-2.0.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! position estimates for stationary sentinels. Instead of the newest (jittery) GPS fix we report a quality weighted
+//! average of recent fixes that are close to the current location, with outliers removed. Fixes are weighted by their
+//! inverse HDOP square, fixes with too few satellites are only used if there are no better ones. A relocation is
+//! detected once a number of consecutive fixes agree on a location that is far from the previous estimate
+
+use std::time::Duration;
+use chrono::{DateTime,Utc};
+use serde::{Deserialize,Serialize};
+use crate::*;
+use crate::pointing::distance;
+
+const UERE: f64 = 5.0;          // assumed user equivalent range error in meters (for HDOP based accuracy)
+const DEFAULT_HDOP: f64 = 2.0;  // used for fixes that don't report HDOP
+
+#[derive(Deserialize,Serialize,Debug,Clone)]
+pub struct PositionConfig {
+    pub max_fixes: usize,          // max number of recent fixes used for an estimate
+    pub min_satellites: i32,       // fixes with fewer satellites are only used if there are no others
+    pub outlier_distance: f64,     // min distance in meters from the median position for fixes to be rejected as outliers
+    pub relocation_distance: f64,  // distance in meters the device has to move to count as relocation
+    pub relocation_fixes: usize,   // number of consecutive fixes that have to confirm a new location
+}
+
+impl Default for PositionConfig {
+    fn default()->Self {
+        PositionConfig { max_fixes: 20, min_satellites: 4, outlier_distance: 25.0, relocation_distance: 100.0, relocation_fixes: 3 }
+    }
+}
+
+#[derive(Serialize,Deserialize,Debug,Clone,PartialEq)]
+#[serde(rename_all="camelCase")]
+pub struct PositionEstimate {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: Option<f64>,
+    pub accuracy: f64, // estimated horizontal standard deviation in meters
+    pub fixes: usize,  // number of fixes the estimate is based on
+    pub time_recorded: DateTime<Utc>, // of the newest fix used
+}
+
+#[derive(Serialize,Deserialize,Debug,Clone,PartialEq)]
+#[serde(rename_all="camelCase")]
+pub struct Relocation {
+    pub device_id: DeviceId,
+    pub time_recorded: DateTime<Utc>,
+    pub from: PositionEstimate,
+    pub to: PositionEstimate,
+    pub distance: f64, // meters
+}
+
+struct Fix { lat: f64, lon: f64, alt: Option<f64>, weight: f64, good: bool, time: DateTime<Utc> }
+
+impl Fix {
+    fn new (rec: &SensorRecord<GpsData>, config: &PositionConfig)->Self {
+        let gps = &rec.data;
+        let hdop = gps.hdop.map_or( DEFAULT_HDOP, |h| (h as f64).max( 0.5));
+        Fix {
+            lat: gps.latitude.degrees(),
+            lon: gps.longitude.degrees(),
+            alt: gps.altitude,
+            weight: 1.0 / (UERE * hdop).powi(2),
+            good: gps.number_of_satellites.map_or( true, |n| n >= config.min_satellites),
+            time: rec.time_recorded,
+        }
+    }
+}
+
+fn median (mut values: Vec<f64>)->f64 {
+    values.sort_by( |a,b| a.total_cmp( b));
+    let n = values.len();
+    if n % 2 == 1 { values[n/2] } else { (values[n/2 - 1] + values[n/2]) / 2.0 }
+}
+
+fn median_position (fixes: &[&Fix])->(f64,f64) {
+    (median( fixes.iter().map( |f| f.lat).collect()), median( fixes.iter().map( |f| f.lon).collect()))
+}
+
+/// outlier rejected weighted mean of fixes that are within relocation distance of the median of the newest fixes
+fn estimate (fixes: &[Fix], config: &PositionConfig)->Option<PositionEstimate> {
+    let fixes: Vec<&Fix> = fixes.iter().collect();
+    if fixes.is_empty() { return None }
+
+    // fixes of an earlier deployment are not part of the estimate
+    let n_ref = config.relocation_fixes.clamp( 1, fixes.len());
+    let (ref_lat, ref_lon) = median_position( &fixes[..n_ref]);
+    let current: Vec<&Fix> = fixes.iter().copied()
+        .filter( |f| distance( ref_lat, ref_lon, f.lat, f.lon) <= config.relocation_distance)
+        .collect();
+    if current.is_empty() { return None }
+
+    let (med_lat, med_lon) = median_position( &current);
+    let dists: Vec<f64> = current.iter().map( |f| distance( med_lat, med_lon, f.lat, f.lon)).collect();
+    let max_dist = (3.0 * median( dists.clone())).max( config.outlier_distance);
+    let inliers: Vec<&Fix> = current.iter().zip( dists).filter( |(_,d)| *d <= max_dist).map( |(f,_)| *f).collect();
+
+    // average offsets from the newest inlier so that a single fix is reproduced exactly
+    let (lat0, lon0) = (inliers[0].lat, inliers[0].lon);
+    let sum_w: f64 = inliers.iter().map( |f| f.weight).sum();
+    let latitude = lat0 + inliers.iter().map( |f| f.weight * (f.lat - lat0)).sum::<f64>() / sum_w;
+    let longitude = lon0 + inliers.iter().map( |f| f.weight * (f.lon - lon0)).sum::<f64>() / sum_w;
+
+    let alts: Vec<(f64,f64)> = inliers.iter().filter_map( |f| f.alt.map( |a| (a, f.weight))).collect();
+    let altitude = if alts.is_empty() { None } else {
+        Some( alts.iter().map( |(a,w)| a*w).sum::<f64>() / alts.iter().map( |(_,w)| w).sum::<f64>())
+    };
+
+    Some( PositionEstimate {
+        latitude, longitude, altitude,
+        accuracy: 1.0 / sum_w.sqrt(),
+        fixes: inliers.len(),
+        time_recorded: inliers.iter().map( |f| f.time).max()?,
+    })
+}
+
+/// the newest fixes (newest first), without the ones that have too few satellites if there are better ones
+fn recent_fixes (sentinel: &Sentinel, config: &PositionConfig, skip: usize)->Vec<Fix> {
+    let fixes: Vec<Fix> = sentinel.records::<GpsData>().iter().skip( skip).take( config.max_fixes).map( |r| Fix::new( r, config)).collect();
+    if fixes.iter().any( |f| f.good) { fixes.into_iter().filter( |f| f.good).collect() } else { fixes }
+}
+
+/// the current position estimate of a sentinel, or None if it has no GPS fix yet
+pub fn estimate_position (sentinel: &Sentinel, config: &PositionConfig)->Option<PositionEstimate> {
+    estimate( &recent_fixes( sentinel, config, 0), config)
+}
+
+/// the position estimate of a sentinel at the given time, i.e. from fixes that are not newer (or the oldest fix if there
+/// is none), or None if it has no GPS fix yet
+pub fn estimate_position_at (sentinel: &Sentinel, config: &PositionConfig, t: DateTime<Utc>)->Option<PositionEstimate> {
+    let gps = sentinel.records::<GpsData>();
+    let skip = gps.iter().position( |r| r.time_recorded <= t).unwrap_or( gps.len().saturating_sub(1));
+    estimate( &recent_fixes( sentinel, config, skip), config)
+}
+
+/// check if the newest GPS fix completed a relocation, i.e. the newest `relocation_fixes` fixes agree on a location
+/// that is far from the one before. This only reports once per relocation
+pub fn detect_relocation (sentinel: &Sentinel, config: &PositionConfig)->Option<Relocation> {
+    let n = config.relocation_fixes.max( 1);
+    let newest = recent_fixes( sentinel, config, 0);
+    if newest.len() <= n { return None }
+
+    let (ref_lat, ref_lon) = median_position( &newest[..n].iter().collect::<Vec<_>>());
+    if newest[..n].iter().any( |f| distance( ref_lat, ref_lon, f.lat, f.lon) > config.relocation_distance) { return None }
+
+    // the fix before has to be elsewhere, otherwise we already reported
+    let before = &newest[n];
+    if distance( ref_lat, ref_lon, before.lat, before.lon) <= config.relocation_distance { return None }
+
+    let from = estimate( &recent_fixes( sentinel, config, n), config)?;
+    let d = distance( from.latitude, from.longitude, ref_lat, ref_lon);
+    if d <= config.relocation_distance { return None }
+
+    let to = estimate( &newest[..n], config)?;
+    Some( Relocation { device_id: sentinel.device_id.clone(), time_recorded: to.time_recorded, distance: d, from, to })
+}
+
+impl Sentinel {
+    /// our current position estimate
+    pub fn position (&self, config: &PositionConfig)->Option<PositionEstimate> {
+        estimate_position( self, config)
+    }
+
+    /// our position estimate at the given time
+    pub fn position_at (&self, config: &PositionConfig, t: DateTime<Utc>)->Option<PositionEstimate> {
+        estimate_position_at( self, config, t)
+    }
+}
@@ -0,0 +1,237 @@
+/*
+ * Copyright (c) 2024, United States Government, as represented by the
+ * Administrator of the National Aeronautics and Space Administration.
+ * All rights reserved.
+ *
+ * The RACE - Runtime for Airspace Concept Evaluation platform is licensed
+ * under the Apache License, Version 2.0 (the "License"); you may not use
+ * this file except in compliance with the License. You may obtain a copy
+ * of the License at http://www.apache.org/licenses/LICENSE-2.0.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! solar power budget and battery runtime forecasts from PowerData history. Energies are integrated from
+//! consecutive records (holding each power value until the next record, gaps longer than `max_gap` are not counted)
+//! and attributed to the local date of the interval start (see `day_offset()`). Forecasts project the remaining
+//! battery energy at the current load and warn if it will not last until solar charging resumes, which is estimated
+//! from the charging window (first/last time of day with solar power) of the most recent day we have data for
+
+use std::{collections::{BTreeMap,HashMap},sync::Arc,time::Duration};
+use chrono::{DateTime,NaiveDate,NaiveTime,Timelike,Utc};
+use serde::{Deserialize,Serialize};
+use uom::si::{electric_potential::volt,electric_current::ampere};
+use odin_actor::prelude::*;
+use odin_actor::tokio_kanal::{Actor,ActorHandle};
+use crate::*;
+use crate::actor::{SentinelConnectorMsg,AddInitCallback,SentinelsInitialized,SentinelJsonSnapshot,SentinelRecordUpdate,
+                   subscribe_mirror};
+
+const SECS_PER_DAY: i64 = 86400;
+
+#[derive(Deserialize,Serialize,Debug,Clone)]
+pub struct PowerConfig {
+    pub battery_capacity: f64, // usable battery capacity in Wh (between 100% and 0% SOC)
+    pub min_soc: f64,          // state of charge (percent) at which the unit shuts down
+    pub load_window: Duration, // window for averaging the current load power
+    pub min_solar_power: f64,  // solar power in W above which we consider the panel to be charging
+    pub max_gap: Duration,     // max time between records that is integrated
+    #[serde(default)]
+    pub utc_offset: Option<i32>, // seconds east of UTC that define our days, solar time of the GPS longitude if None
+}
+
+impl Default for PowerConfig {
+    fn default()->Self {
+        PowerConfig {
+            battery_capacity: 1200.0,
+            min_soc: 10.0,
+            load_window: Duration::from_secs(3600),
+            min_solar_power: 5.0,
+            max_gap: Duration::from_secs(3600),
+            utc_offset: None,
+        }
+    }
+}
+
+fn solar_power (p: &PowerData)->f64 { p.solar_voltage.get::<volt>() * p.solar_current.get::<ampere>() }
+fn load_power (p: &PowerData)->f64 { p.load_voltage.get::<volt>() * p.load_current.get::<ampere>() }
+
+#[derive(Serialize,Deserialize,Debug,Clone,PartialEq)]
+#[serde(rename_all="camelCase")]
+pub struct DailyEnergy {
+    pub date: NaiveDate, // local date (see day_offset())
+    pub energy_in: f64,  // solar energy in Wh
+    pub energy_out: f64, // load energy in Wh
+    pub balance: f64,    // energy_in - energy_out
+}
+
+/// the offset of the days we attribute energies to. A UTC date would split the solar day of sentinels that are far
+/// from Greenwich, hence we fall back to the mean solar time of the last known position (15deg per hour)
+pub fn day_offset (sentinel: &Sentinel, config: &PowerConfig)->chrono::Duration {
+    let secs = config.utc_offset.map( |secs| secs as i64)
+        .or_else( || sentinel.latest::<GpsData>().map( |gps| (gps.data.longitude.degrees() * 240.0).round() as i64))
+        .unwrap_or(0);
+    chrono::Duration::seconds( secs)
+}
+
+/// daily solar and load energies of a sentinel in ascending date order
+pub fn daily_energy (sentinel: &Sentinel, config: &PowerConfig)->Vec<DailyEnergy> {
+    let max_gap = config.max_gap.as_secs_f64();
+    let offset = day_offset( sentinel, config);
+    let mut days: BTreeMap<NaiveDate,(f64,f64)> = BTreeMap::new();
+
+    let recs: Vec<&SensorRecord<PowerData>> = sentinel.records::<PowerData>().iter().rev().collect(); // oldest first
+    for w in recs.windows(2) {
+        let dt = (w[1].time_recorded - w[0].time_recorded).num_milliseconds() as f64 / 1000.0;
+        if dt <= 0.0 || dt > max_gap { continue }
+
+        let e = days.entry( (w[0].time_recorded + offset).date_naive()).or_default();
+        e.0 += solar_power( &w[0].data) * dt / 3600.0;
+        e.1 += load_power( &w[0].data) * dt / 3600.0;
+    }
+
+    days.into_iter().map( |(date,(energy_in,energy_out))| DailyEnergy { date, energy_in, energy_out, balance: energy_in - energy_out }).collect()
+}
+
+/// the (start,end) time of day (UTC) of the most recent completed charging period, i.e. the last sequence of records
+/// with solar power above threshold that is not the ongoing one. Charging periods can span UTC midnight
+pub fn charging_window (sentinel: &Sentinel, config: &PowerConfig)->Option<(NaiveTime,NaiveTime)> {
+    let charging = |r: &&SensorRecord<PowerData>| solar_power( &r.data) > config.min_solar_power;
+    let mut recs = sentinel.records::<PowerData>().iter() // newest first
+        .skip_while( charging)
+        .skip_while( |r| !charging( r))
+        .take_while( charging);
+
+    let end = recs.next()?.time_recorded.time();
+    let start = recs.last().map_or( end, |r| r.time_recorded.time());
+    Some( (start, end))
+}
+
+#[derive(Serialize,Deserialize,Debug,Clone,PartialEq)]
+#[serde(rename_all="camelCase")]
+pub struct RuntimeForecast {
+    pub device_id: DeviceId,
+    pub time_recorded: DateTime<Utc>,      // of the newest power record
+    pub soc: f64,
+    pub remaining_energy: f64,             // Wh until shutdown
+    pub load_power: f64,                   // average W within load window
+    pub solar_power: f64,                  // current W
+    pub time_to_empty: Option<Duration>,   // at current load without charging, None if there is no load
+    pub charging_resumes: Option<DateTime<Utc>>, // next estimated start of solar charging if we are not charging
+    pub overnight_shutdown: bool,          // projected to shut down before charging resumes
+}
+
+fn secs_of_day (t: NaiveTime)->i64 { t.num_seconds_from_midnight() as i64 }
+
+/// seconds from time of day `from` until the next occurrence of time of day `to`
+fn secs_until (from: NaiveTime, to: NaiveTime)->i64 {
+    (secs_of_day( to) - secs_of_day( from)).rem_euclid( SECS_PER_DAY)
+}
+
+pub fn runtime_forecast (sentinel: &Sentinel, config: &PowerConfig)->Option<RuntimeForecast> {
+    let latest = sentinel.latest::<PowerData>()?;
+    let t = latest.time_recorded;
+
+    let window_start = t - chrono::Duration::from_std( config.load_window).ok()?;
+    let loads: Vec<f64> = sentinel.records_in::<PowerData>( window_start..=t).iter().map( |r| load_power( &r.data)).collect();
+    let load = loads.iter().sum::<f64>() / loads.len() as f64;
+    let solar = solar_power( &latest.data);
+
+    let soc = latest.data.soc;
+    let remaining_energy = (config.battery_capacity * (soc - config.min_soc) / 100.0).max( 0.0);
+    let time_to_empty = if load > 0.0 { Some( Duration::from_secs_f64( remaining_energy / load * 3600.0)) } else { None };
+
+    let window = charging_window( sentinel, config);
+    let (charging_resumes, overnight_shutdown) = match (window, load > 0.0) {
+        (Some((start,end)), true) => {
+            let now = t.time();
+            let day = secs_until( start, end);
+            if solar > config.min_solar_power {
+                // energy we have left at the end of the charging window has to last through the night
+                let elapsed = secs_until( start, now);
+                let day_left = if elapsed <= day { (day - elapsed) as f64 } else { 0.0 };
+                let at_sunset = (remaining_energy + (solar - load) * day_left / 3600.0)
+                    .clamp( 0.0, config.battery_capacity * (100.0 - config.min_soc) / 100.0);
+                let night = (SECS_PER_DAY - day) as f64;
+                (None, at_sunset < load * night / 3600.0)
+            } else {
+                let until_charging = secs_until( now, start);
+                (Some( t + chrono::Duration::seconds( until_charging)), remaining_energy < load * until_charging as f64 / 3600.0)
+            }
+        }
+        (None, true) => (None, false), // no solar history we can use for a projection
+        (_, false) => (None, false)
+    };
+
+    Some( RuntimeForecast {
+        device_id: sentinel.device_id.clone(),
+        time_recorded: t,
+        soc, remaining_energy,
+        load_power: load,
+        solar_power: solar,
+        time_to_empty, charging_resumes, overnight_shutdown
+    })
+}
+
+/* #region power monitor actor *******************************************************************/
+
+#[derive(Debug)] pub struct AddPowerWarningCallback { pub id: String, pub action: Callback<Arc<RuntimeForecast>> }
+
+define_actor_msg_type! { pub PowerMonitorMsg = AddPowerWarningCallback | SentinelsInitialized | SentinelJsonSnapshot | SentinelRecordUpdate }
+
+/// publishes a RuntimeForecast whenever a device gets on a trajectory to shut down overnight
+pub struct PowerMonitor {
+    config: PowerConfig,
+    hconn: ActorHandle<SentinelConnectorMsg>,
+    sentinels: SentinelStore, // our mirror of the connector state
+    warned: HashMap<DeviceId,bool>, // so that we only report state changes
+    warning_callbacks: CallbackList<Arc<RuntimeForecast>>,
+}
+
+impl PowerMonitor {
+    pub fn new (config: PowerConfig, hconn: ActorHandle<SentinelConnectorMsg>)->Self {
+        PowerMonitor { config, hconn, sentinels: SentinelStore::new(), warned: HashMap::new(), warning_callbacks: CallbackList::new() }
+    }
+
+    async fn update (&mut self, update: &SentinelUpdate)->Result<()> {
+        let (device_id, capability) = self.sentinels.add_update( update.clone());
+        if capability != SensorCapability::Power { return Ok(()) }
+
+        let sentinel = self.sentinels.get( &device_id).ok_or( OdinSentinelError::NoSuchDeviceError( device_id.clone()))?;
+        if let Some(forecast) = runtime_forecast( sentinel, &self.config) {
+            let was_warned = self.warned.insert( device_id, forecast.overnight_shutdown).unwrap_or( false);
+            if forecast.overnight_shutdown && !was_warned {
+                self.warning_callbacks.trigger( Arc::new(forecast)).await;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl_actor! { match msg for Actor<PowerMonitor,PowerMonitorMsg> as
+    _Start_ => cont! {
+        let id = self.id().to_string();
+        let hself = &self.hself;
+        self.hconn.send_msg( AddInitCallback{id, action: msg_callback!(hself, SentinelsInitialized)}).await.ok();
+    }
+    AddPowerWarningCallback => cont! {
+        self.warning_callbacks.add( msg.id, msg.action)
+    }
+    SentinelsInitialized => cont! {
+        subscribe_mirror!( self.hconn, &self.hself, self.id().to_string());
+    }
+    SentinelJsonSnapshot => cont! {
+        if let Some(sentinels) = msg.sentinels() { self.sentinels = sentinels }
+    }
+    SentinelRecordUpdate => cont! {
+        if let Err(e) = self.update( &msg.0).await {
+            eprintln!("@@ failed to process sentinel update: {:?}", e);
+        }
+    }
+}
+
+/* #endregion power monitor actor */
@@ -0,0 +1,185 @@
+/*
+ * Copyright (c) 2024, United States Government, as represented by the
+ * Administrator of the National Aeronautics and Space Administration.
+ * All rights reserved.
+ *
+ * The RACE - Runtime for Airspace Concept Evaluation platform is licensed
+ * under the Apache License, Version 2.0 (the "License"); you may not use
+ * this file except in compliance with the License. You may obtain a copy
+ * of the License at http://www.apache.org/licenses/LICENSE-2.0.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! privacy filter for person detections. Person records and the images they reference as evidences can show
+//! members of the public. The SentinelConnector applies a PrivacyFilter after records are written to its storage
+//! (which serves as the restricted archive of full data) and before they are added to the in-memory SentinelStore and
+//! sent to update callbacks, i.e. before they reach client actors, exporters and servers.
+//!
+//! Images that were already published before the person record that references them arrived cannot be retracted.
+//! For those we publish redacted versions (same record id) that replace the originals in the store and in mirrors
+
+use std::collections::HashMap;
+use chrono::{DateTime,Utc};
+use serde::{Deserialize,Serialize};
+use serde_json::Value;
+use crate::*;
+
+/// filename we use for redacted image records
+pub const REDACTED_FILENAME: &str = "redacted";
+
+/// how long after a person record we still filter the images it references (images normally arrive within seconds)
+const PRIVATE_IMAGE_RETENTION: i64 = 86400; // secs
+
+#[derive(Deserialize,Serialize,Debug,Clone,Copy,PartialEq)]
+pub enum PersonPolicy {
+    Pass,     // no filtering
+    Redact,   // keep person records but redact the filenames of their evidence images
+    Suppress, // drop person records and their evidence images
+}
+
+#[derive(Deserialize,Serialize,Debug,Clone)]
+pub struct PrivacyPolicy {
+    pub persons: PersonPolicy,
+    pub min_person_prob: f64, // person records with lower probability are not considered to show persons
+}
+
+impl Default for PrivacyPolicy {
+    fn default()->Self {
+        PrivacyPolicy { persons: PersonPolicy::Pass, min_person_prob: 0.0 }
+    }
+}
+
+impl PrivacyPolicy {
+    /// the policy for tools that export data without a configured policy
+    pub fn redact_all()->Self {
+        PrivacyPolicy { persons: PersonPolicy::Redact, min_person_prob: 0.0 }
+    }
+}
+
+#[derive(Debug,Default)]
+pub struct PrivacyFilter {
+    policy: PrivacyPolicy,
+    private_images: HashMap<String,DateTime<Utc>>, // ids of images referenced by person records -> time of person record
+}
+
+impl PrivacyFilter {
+    pub fn new (policy: PrivacyPolicy)->Self {
+        PrivacyFilter { policy, private_images: HashMap::new() }
+    }
+
+    pub fn policy (&self)->&PrivacyPolicy { &self.policy }
+
+    fn is_person (&self, rec: &SensorRecord<PersonData>)->bool {
+        self.policy.persons != PersonPolicy::Pass && rec.data.person_prob >= self.policy.min_person_prob
+    }
+
+    fn filter_image (&self, rec: &mut SensorRecord<ImageData>)->bool {
+        if self.private_images.contains_key( &rec.id) {
+            match self.policy.persons {
+                PersonPolicy::Suppress => return false,
+                PersonPolicy::Redact => rec.data.filename = REDACTED_FILENAME.to_string(),
+                PersonPolicy::Pass => {}
+            }
+        }
+        true
+    }
+
+    /// mark the evidence images of a person record as private
+    fn add_private_images (&mut self, rec: &SensorRecord<PersonData>) {
+        self.private_images.extend( rec.evidences.iter().map( |e| (e.id.clone(), rec.time_recorded)));
+    }
+
+    /// forget the images of person records that are too old to still get their images
+    fn prune_private_images (&mut self, now: DateTime<Utc>) {
+        let cutoff = now - chrono::Duration::seconds( PRIVATE_IMAGE_RETENTION);
+        self.private_images.retain( |_,t| *t >= cutoff);
+    }
+
+    /// redacted versions of the evidence images of a person record that the sentinel already has (and hence were published)
+    fn redactions (&self, rec: &SensorRecord<PersonData>, sentinel: &Sentinel)->Vec<SentinelUpdate> {
+        sentinel.image.iter()
+            .filter( |img| img.data.filename != REDACTED_FILENAME && rec.evidences.iter().any( |e| e.id == img.id))
+            .map( |img| {
+                let mut img = img.clone();
+                img.data.filename = REDACTED_FILENAME.to_string();
+                SentinelUpdate::Image(img)
+            })
+            .collect()
+    }
+
+    /// filter a new update for the given sentinel, returning the updates that should be published instead (if any).
+    /// Person records can cause redactions of previously published images, which precede the person record
+    pub fn filter_update (&mut self, update: SentinelUpdate, sentinel: &Sentinel)->Vec<SentinelUpdate> {
+        match update {
+            SentinelUpdate::Person(rec) if self.is_person( &rec) => {
+                self.prune_private_images( rec.time_recorded);
+                self.add_private_images( &rec);
+                let mut updates = self.redactions( &rec, sentinel);
+                if self.policy.persons != PersonPolicy::Suppress { updates.push( SentinelUpdate::Person(rec)) }
+                updates
+            }
+            SentinelUpdate::Image(mut rec) => {
+                if self.filter_image( &mut rec) { vec![ SentinelUpdate::Image(rec)] } else { Vec::new() }
+            }
+            update => vec![update]
+        }
+    }
+
+    /// apply the policy to all records of a sentinel that was not published yet
+    pub fn filter_sentinel (&mut self, sentinel: &mut Sentinel) {
+        let persons = std::mem::take( &mut sentinel.person);
+        let mut kept = VecDeque::with_capacity( persons.len());
+        for rec in persons {
+            if self.is_person( &rec) {
+                self.add_private_images( &rec);
+                if self.policy.persons == PersonPolicy::Suppress { continue }
+            }
+            kept.push_back( rec);
+        }
+        sentinel.person = kept;
+
+        let mut images = std::mem::take( &mut sentinel.image);
+        images.retain_mut( |img| self.filter_image( img));
+        sentinel.image = images;
+    }
+
+    pub fn filter_store (&mut self, store: &mut SentinelStore) {
+        for sentinel in store.sentinels.values_mut() {
+            self.filter_sentinel( sentinel);
+        }
+    }
+
+    /// apply the policy to generic JSON records (e.g. retrieved record history) of the given capabilities
+    pub fn filter_json_records (&mut self, capability_records: &mut [(SensorCapability,Vec<Value>)]) {
+        for (_,records) in capability_records.iter_mut().filter( |(c,_)| *c == SensorCapability::Person) {
+            records.retain( |v| {
+                match serde_json::from_value::<SensorRecord<PersonData>>( v.clone()) {
+                    Ok(rec) if self.is_person( &rec) => {
+                        self.add_private_images( &rec);
+                        self.policy.persons != PersonPolicy::Suppress
+                    }
+                    _ => true
+                }
+            });
+        }
+
+        for (_,records) in capability_records.iter_mut().filter( |(c,_)| *c == SensorCapability::Image) {
+            records.retain_mut( |v| {
+                let is_private = v.get("id").and_then( |id| id.as_str()).map_or( false, |id| self.private_images.contains_key( id));
+                match self.policy.persons {
+                    PersonPolicy::Suppress if is_private => false,
+                    PersonPolicy::Redact if is_private => {
+                        v["image"]["filename"] = Value::from( REDACTED_FILENAME);
+                        true
+                    }
+                    _ => true
+                }
+            });
+        }
+    }
+}
@@ -0,0 +1,508 @@
+/*
+ * Copyright (c) 2024, United States Government, as represented by the
+ * Administrator of the National Aeronautics and Space Administration.
+ * All rights reserved.
+ *
+ * The RACE - Runtime for Airspace Concept Evaluation platform is licensed
+ * under the Apache License, Version 2.0 (the "License"); you may not use
+ * this file except in compliance with the License. You may obtain a copy
+ * of the License at http://www.apache.org/licenses/LICENSE-2.0.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! read-only OGC SensorThings API (v1.1) projection of a SentinelStore. The mapping is
+//!   - Things: sentinel devices
+//!   - Locations: latest GPS position of a device
+//!   - Sensors: sensor boards of a device (as reported by the server, identified by `{device_id}.{sensor_no}`)
+//!   - Datastreams: capability x sensor_no of a device (identified by `{device_id}.{sensor_no}.{capability}`)
+//!   - Observations: sensor records (identified by record id)
+//! Supported query options are `$top`, `$skip`, `$count` and - for Observations - `$orderby` and `$filter` on
+//! `phenomenonTime` (or `resultTime`). The HTTP server is in the `server` sub-module (requires the "sensorthings" feature)
+
+use chrono::{DateTime,Utc};
+use serde::{Deserialize,Serialize};
+use serde_json::{json,Value};
+use strum::IntoEnumIterator;
+use crate::*;
+use crate::timeline::iso_interval;
+use crate::position::PositionConfig;
+
+#[cfg(feature="sensorthings")] mod server;
+#[cfg(feature="sensorthings")] pub use server::*;
+
+/* #region query options **************************************************************************************/
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum TimeOp { Eq, Gt, Ge, Lt, Le }
+
+#[derive(Debug,Clone,PartialEq)]
+pub struct TimeCondition {
+    pub op: TimeOp,
+    pub time: DateTime<Utc>
+}
+impl TimeCondition {
+    pub fn matches (&self, t: DateTime<Utc>)->bool {
+        match self.op {
+            TimeOp::Eq => t == self.time,
+            TimeOp::Gt => t > self.time,
+            TimeOp::Ge => t >= self.time,
+            TimeOp::Lt => t < self.time,
+            TimeOp::Le => t <= self.time,
+        }
+    }
+}
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum SortOrder { Asc, Desc }
+
+#[derive(Debug,Clone,Default)]
+pub struct Query {
+    pub filter: Vec<TimeCondition>, // conjunction
+    pub order: Option<SortOrder>,
+    pub top: Option<usize>,
+    pub skip: usize,
+    pub count: bool,
+
+    // the original expressions, which we need to construct next links
+    filter_expr: Option<String>,
+    orderby_expr: Option<String>,
+}
+
+impl Query {
+    /// parse a (percent encoded) URL query string such as `$top=10&$filter=phenomenonTime gt 2023-01-29T19:00:00Z`
+    pub fn parse (query: &str)->Result<Query> {
+        let mut q = Query::default();
+
+        for (k,v) in url::form_urlencoded::parse( query.as_bytes()) {
+            match k.as_ref() {
+                "$top" => q.top = Some( v.parse().map_err( |_| op_failed( format!("invalid $top: {v}")))?),
+                "$skip" => q.skip = v.parse().map_err( |_| op_failed( format!("invalid $skip: {v}")))?,
+                "$count" => q.count = v == "true",
+                "$orderby" => {
+                    q.order = Some( parse_orderby( &v)?);
+                    q.orderby_expr = Some( v.to_string());
+                }
+                "$filter" => {
+                    q.filter = parse_filter( &v)?;
+                    q.filter_expr = Some( v.to_string());
+                }
+                _ => return Err( op_failed( format!("unsupported query option: {k}")))
+            }
+        }
+        Ok(q)
+    }
+
+    pub fn has_time_options (&self)->bool {
+        !self.filter.is_empty() || self.order.is_some()
+    }
+
+    pub fn accepts (&self, t: DateTime<Utc>)->bool {
+        self.filter.iter().all( |c| c.matches( t))
+    }
+
+    fn to_query_string (&self, skip: usize)->String {
+        let mut s = url::form_urlencoded::Serializer::new( String::new());
+        if let Some(top) = self.top { s.append_pair( "$top", &top.to_string()); }
+        s.append_pair( "$skip", &skip.to_string());
+        if self.count { s.append_pair( "$count", "true"); }
+        if let Some(expr) = &self.orderby_expr { s.append_pair( "$orderby", expr); }
+        if let Some(expr) = &self.filter_expr { s.append_pair( "$filter", expr); }
+        s.finish()
+    }
+}
+
+fn is_time_property (p: &str)->bool {
+    p == "phenomenonTime" || p == "resultTime"
+}
+
+fn parse_orderby (expr: &str)->Result<SortOrder> {
+    let mut it = expr.split_whitespace();
+    match (it.next(), it.next(), it.next()) {
+        (Some(p), None, None) if is_time_property(p) => Ok(SortOrder::Asc),
+        (Some(p), Some("asc"), None) if is_time_property(p) => Ok(SortOrder::Asc),
+        (Some(p), Some("desc"), None) if is_time_property(p) => Ok(SortOrder::Desc),
+        _ => Err( op_failed( format!("unsupported $orderby: {expr}")))
+    }
+}
+
+fn parse_filter (expr: &str)->Result<Vec<TimeCondition>> {
+    let mut conds = Vec::new();
+    let tokens: Vec<&str> = expr.split_whitespace().collect();
+
+    for (i,clause) in tokens.split( |t| *t == "and").enumerate() {
+        match clause {
+            [p, op, t] if is_time_property(p) => {
+                let op = match *op {
+                    "eq" => TimeOp::Eq,
+                    "gt" => TimeOp::Gt,
+                    "ge" => TimeOp::Ge,
+                    "lt" => TimeOp::Lt,
+                    "le" => TimeOp::Le,
+                    _ => return Err( op_failed( format!("unsupported $filter operator: {op}")))
+                };
+                let t = t.trim_matches('\'');
+                let time = DateTime::parse_from_rfc3339( t).map_err( |_| op_failed( format!("invalid $filter time: {t}")))?;
+                conds.push( TimeCondition{ op, time: time.with_timezone(&Utc) });
+            }
+            _ => return Err( op_failed( format!("unsupported $filter: {expr}")))
+        }
+    }
+    Ok(conds)
+}
+
+/* #endregion query options */
+
+/* #region entities *******************************************************************************************/
+
+#[derive(Deserialize,Serialize,Debug,Clone)]
+pub struct SensorThingsOpts {
+    pub base_url: String, // the public URL of the service root, e.g. "http://localhost:8080/v1.1"
+    pub max_top: usize,   // max number of entities per response (larger collections get a @iot.nextLink)
+    #[serde(default)]
+    pub position: PositionConfig, // how we estimate Location positions from GPS fixes
+}
+impl Default for SensorThingsOpts {
+    fn default()->Self {
+        SensorThingsOpts { base_url: "http://localhost:8080/v1.1".to_string(), max_top: 100, position: PositionConfig::default() }
+    }
+}
+
+const ENTITY_SETS: [&str;5] = ["Things", "Locations", "Sensors", "Datastreams", "Observations"];
+
+fn self_link (opts: &SensorThingsOpts, entity_set: &str, id: &str)->String {
+    format!("{}/{}('{}')", opts.base_url, entity_set, id)
+}
+
+pub fn sensor_id (device_id: &str, sensor_no: u32)->String {
+    format!("{device_id}.{sensor_no}")
+}
+
+pub fn datastream_id (device_id: &str, sensor_no: u32, capability: SensorCapability)->String {
+    format!("{device_id}.{sensor_no}.{}", capability.property_name())
+}
+
+fn parse_sensor_id (id: &str)->Option<(&str,u32)> {
+    let (device_id, sensor_no) = id.rsplit_once('.')?;
+    Some( (device_id, sensor_no.parse().ok()?) )
+}
+
+fn parse_datastream_id (id: &str)->Option<(&str,u32,SensorCapability)> {
+    let (sensor_id, cap) = id.rsplit_once('.')?;
+    let (device_id, sensor_no) = parse_sensor_id( sensor_id)?;
+    let capability = SensorCapability::from_name( cap)?;
+    Some( (device_id, sensor_no, capability) )
+}
+
+fn record_sensor_no (rec: &Value)->Option<u32> {
+    rec.get("sensorNo")?.as_u64().map( |n| n as u32)
+}
+
+/// all (sensor_no,capability) pairs of a sentinel, from its reported sensors and the records we have
+fn datastream_keys (sentinel: &Sentinel)->Vec<(u32,SensorCapability)> {
+    let mut keys: Vec<(u32,SensorCapability)> = Vec::new();
+    for sensor in &sentinel.sensors {
+        for capability in &sensor.capabilities {
+            if !keys.contains( &(sensor.no, *capability)) { keys.push( (sensor.no, *capability)) }
+        }
+    }
+    for capability in SensorCapability::iter() {
+        for rec in sentinel.json_records( capability).unwrap_or_default() {
+            if let Some(sensor_no) = record_sensor_no( &rec) {
+                if !keys.contains( &(sensor_no, capability)) { keys.push( (sensor_no, capability)) }
+            }
+        }
+    }
+    keys.sort_by_key( |(n,c)| (*n, c.property_name()));
+    keys
+}
+
+/// the records of a datastream (newest first)
+fn datastream_records (sentinel: &Sentinel, sensor_no: u32, capability: SensorCapability)->Vec<Value> {
+    sentinel.json_records( capability).unwrap_or_default().into_iter()
+        .filter( |rec| record_sensor_no( rec) == Some(sensor_no))
+        .collect()
+}
+
+pub fn thing (opts: &SensorThingsOpts, sentinel: &Sentinel)->Value {
+    let link = self_link( opts, "Things", &sentinel.device_id);
+    json!({
+        "@iot.id": sentinel.device_id,
+        "@iot.selfLink": link,
+        "name": sentinel.device_name,
+        "description": format!("Sentinel device {}", sentinel.device_id),
+        "properties": {
+            "lastUpdate": sentinel.last_update(),
+            "retired": sentinel.retired
+        },
+        "Locations@iot.navigationLink": format!("{link}/Locations"),
+        "Datastreams@iot.navigationLink": format!("{link}/Datastreams")
+    })
+}
+
+/// the latest GPS position of a sentinel, or None if we don't have any GPS record yet
+pub fn location (opts: &SensorThingsOpts, sentinel: &Sentinel)->Option<Value> {
+    let pos = sentinel.position( &opts.position)?;
+
+    Some( json!({
+        "@iot.id": sentinel.device_id,
+        "@iot.selfLink": self_link( opts, "Locations", &sentinel.device_id),
+        "name": format!("{} position", sentinel.device_name),
+        "description": "estimated position from recent GPS fixes",
+        "encodingType": "application/geo+json",
+        "location": { "type": "Point", "coordinates": [pos.longitude, pos.latitude] },
+        "Things@iot.navigationLink": format!("{}/Things", self_link( opts, "Locations", &sentinel.device_id))
+    }))
+}
+
+pub fn sensors (opts: &SensorThingsOpts, sentinel: &Sentinel)->Vec<Value> {
+    let mut sensor_nos: Vec<u32> = datastream_keys( sentinel).into_iter().map( |(n,_)| n).collect();
+    sensor_nos.dedup();
+
+    sensor_nos.into_iter().map( |sensor_no| {
+        let id = sensor_id( &sentinel.device_id, sensor_no);
+        let link = self_link( opts, "Sensors", &id);
+        let part_no = sentinel.sensors.iter().find( |s| s.no == sensor_no).and_then( |s| s.part_no.clone());
+        json!({
+            "@iot.id": id,
+            "@iot.selfLink": link,
+            "name": part_no.clone().unwrap_or_else( || format!("sensor {sensor_no}")),
+            "description": format!("sensor {} of device {}", sensor_no, sentinel.device_id),
+            "encodingType": "text/plain",
+            "metadata": part_no,
+            "Datastreams@iot.navigationLink": format!("{link}/Datastreams")
+        })
+    }).collect()
+}
+
+pub fn datastream (opts: &SensorThingsOpts, sentinel: &Sentinel, sensor_no: u32, capability: SensorCapability)->Value {
+    let id = datastream_id( &sentinel.device_id, sensor_no, capability);
+    let link = self_link( opts, "Datastreams", &id);
+
+    let times: Vec<DateTime<Utc>> = datastream_records( sentinel, sensor_no, capability).iter().filter_map( json_record_time).collect();
+    let phenomenon_time = match (times.last(), times.first()) { // newest first
+        (Some(start), Some(end)) => Some( iso_interval( *start, *end)),
+        _ => None
+    };
+
+    // records are JSON objects with capability specific fields, hence we don't have a single unit of measurement
+    json!({
+        "@iot.id": id,
+        "@iot.selfLink": link,
+        "name": format!("{} {} {}", sentinel.device_name, capability.property_name(), sensor_no),
+        "description": format!("{} records of sensor {} of device {}", capability.property_name(), sensor_no, sentinel.device_id),
+        "observationType": "http://www.opengis.net/def/observationType/OGC-OM/2.0/OM_Observation",
+        "unitOfMeasurement": { "name": null, "symbol": null, "definition": null },
+        "phenomenonTime": phenomenon_time,
+        "Thing@iot.navigationLink": format!("{link}/Thing"),
+        "Sensor@iot.navigationLink": format!("{link}/Sensor"),
+        "Observations@iot.navigationLink": format!("{link}/Observations")
+    })
+}
+
+pub fn datastreams (opts: &SensorThingsOpts, sentinel: &Sentinel)->Vec<Value> {
+    datastream_keys( sentinel).into_iter().map( |(n,c)| datastream( opts, sentinel, n, c)).collect()
+}
+
+pub fn observation (opts: &SensorThingsOpts, rec: &Value, capability: SensorCapability)->Option<Value> {
+    let id = rec.get("id")?.as_str()?;
+    let device_id = rec.get("deviceId")?.as_str()?;
+    let sensor_no = record_sensor_no( rec)?;
+    let link = self_link( opts, "Observations", id);
+
+    Some( json!({
+        "@iot.id": id,
+        "@iot.selfLink": link,
+        "phenomenonTime": rec.get("timeRecorded"),
+        "resultTime": rec.get("timeRecorded"),
+        "result": rec.get( capability.property_name()),
+        "Datastream@iot.navigationLink": format!("{}/Datastreams('{}')", opts.base_url, datastream_id( device_id, sensor_no, capability))
+    }))
+}
+
+/* #endregion entities */
+
+/* #region request resolution *********************************************************************************/
+
+/// the entities we navigate through while resolving a path
+enum Node<'a> {
+    Root,
+    Sentinels(Vec<&'a Sentinel>),
+    Thing(&'a Sentinel),
+    Location(&'a Sentinel),
+    Sensor(&'a Sentinel, u32),
+    Datastream(&'a Sentinel, u32, SensorCapability),
+    Observations(Vec<(Value,SensorCapability)>), // records newest first
+    Observation(Value, SensorCapability),
+    Collection(Vec<Value>),
+}
+
+fn sorted_sentinels (store: &SentinelStore)->Vec<&Sentinel> {
+    let mut list = store.values();
+    list.sort_by( |a,b| a.device_id.cmp( &b.device_id));
+    list
+}
+
+fn all_observations (sentinels: &[&Sentinel])->Vec<(Value,SensorCapability)> {
+    let mut list = Vec::new();
+    for sentinel in sentinels {
+        for capability in SensorCapability::iter() {
+            for rec in sentinel.json_records( capability).unwrap_or_default() {
+                list.push( (rec,capability));
+            }
+        }
+    }
+    list.sort_by( |a,b| json_record_time( &b.0).cmp( &json_record_time( &a.0)));
+    list
+}
+
+/// parse a path segment such as `Things('roo7gd1dldn3')` into its name and optional id
+fn parse_segment (seg: &str)->Result<(&str,Option<&str>)> {
+    if let Some(i) = seg.find('(') {
+        let id = seg[i+1..].strip_suffix(')').ok_or( op_failed( format!("invalid path segment: {seg}")))?;
+        Ok( (&seg[..i], Some( id.trim_matches('\''))) )
+    } else {
+        Ok( (seg, None) )
+    }
+}
+
+fn percent_decode (s: &str)->String {
+    let bytes = s.as_bytes();
+    let mut out: Vec<u8> = Vec::with_capacity( bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i+2 < bytes.len() {
+            if let Some(b) = std::str::from_utf8( &bytes[i+1..i+3]).ok().and_then( |h| u8::from_str_radix( h, 16).ok()) {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push( bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy( &out).to_string()
+}
+
+fn next_node<'a> (opts: &SensorThingsOpts, store: &'a SentinelStore, node: Node<'a>, name: &str, id: Option<&str>)->Option<Node<'a>> {
+    let node = match (node, name) {
+        (Node::Root, "Things") => Node::Sentinels( sorted_sentinels( store)),
+        (Node::Root, "Locations") => Node::Collection( sorted_sentinels( store).into_iter().filter_map( |s| location( opts, s)).collect()),
+        (Node::Root, "Sensors") => Node::Collection( sorted_sentinels( store).into_iter().flat_map( |s| sensors( opts, s)).collect()),
+        (Node::Root, "Datastreams") => Node::Collection( sorted_sentinels( store).into_iter().flat_map( |s| datastreams( opts, s)).collect()),
+        (Node::Root, "Observations") => Node::Observations( all_observations( &sorted_sentinels( store))),
+
+        (Node::Thing(s), "Locations") => Node::Collection( location( opts, s).into_iter().collect()),
+        (Node::Thing(s), "Datastreams") => Node::Collection( datastreams( opts, s)),
+        (Node::Location(s), "Things") => Node::Collection( vec![thing( opts, s)]),
+
+        (Node::Sensor(s,n), "Datastreams") => Node::Collection( datastream_keys( s).into_iter()
+                                                   .filter( |(sn,_)| *sn == n)
+                                                   .map( |(sn,c)| datastream( opts, s, sn, c)).collect()),
+
+        (Node::Datastream(s,_,_), "Thing") => Node::Thing(s),
+        (Node::Datastream(s,n,_), "Sensor") => Node::Sensor(s,n),
+        (Node::Datastream(s,n,c), "Observations") => Node::Observations( datastream_records( s, n, c).into_iter().map( |r| (r,c)).collect()),
+
+        (Node::Observation(rec,c), "Datastream") => {
+            let device_id = rec.get("deviceId")?.as_str()?.to_string();
+            let sensor_no = record_sensor_no( &rec)?;
+            Node::Datastream( store.get( &device_id)?, sensor_no, c)
+        }
+        _ => return None
+    };
+
+    if let Some(id) = id { // select element of collection
+        match (node, name) {
+            (Node::Sentinels(list), _) => list.into_iter().find( |s| s.device_id == id).map( Node::Thing),
+            (Node::Collection(_), "Locations") => store.get( &id.to_string()).filter( |s| location( opts, s).is_some()).map( Node::Location),
+            (Node::Collection(_), "Sensors") => {
+                let (device_id, sensor_no) = parse_sensor_id( id)?;
+                let s = store.get( &device_id.to_string())?;
+                datastream_keys( s).iter().any( |(n,_)| *n == sensor_no).then( || Node::Sensor(s, sensor_no))
+            }
+            (Node::Collection(_), "Datastreams") => {
+                let (device_id, sensor_no, capability) = parse_datastream_id( id)?;
+                let s = store.get( &device_id.to_string())?;
+                datastream_keys( s).contains( &(sensor_no,capability)).then( || Node::Datastream(s, sensor_no, capability))
+            }
+            (Node::Observations(list), _) => list.into_iter()
+                .find( |(rec,_)| rec.get("id").and_then( |v| v.as_str()) == Some(id))
+                .map( |(rec,c)| Node::Observation(rec,c)),
+            _ => None
+        }
+    } else {
+        Some(node)
+    }
+}
+
+fn collection_response (opts: &SensorThingsOpts, path: &str, query: &Query, entities: Vec<Value>)->Value {
+    let count = entities.len();
+    let top = query.top.unwrap_or( opts.max_top).min( opts.max_top);
+    let page: Vec<Value> = entities.into_iter().skip( query.skip).take( top).collect();
+
+    let mut response = serde_json::Map::new();
+    if query.count {
+        response.insert( "@iot.count".to_string(), json!(count));
+    }
+    if query.skip + page.len() < count {
+        let next_link = format!("{}/{}?{}", opts.base_url, path, query.to_query_string( query.skip + page.len()));
+        response.insert( "@iot.nextLink".to_string(), json!(next_link));
+    }
+    response.insert( "value".to_string(), Value::Array(page));
+    Value::Object(response)
+}
+
+/// resolve a (percent encoded) request path relative to the service root, e.g. `Datastreams('roo7gd1dldn3.7.fire')/Observations`.
+/// Returns Ok(None) if the path does not refer to a known entity, and Err if the path or query is not supported
+pub fn resolve (store: &SentinelStore, opts: &SensorThingsOpts, path: &str, query: &Query)->Result<Option<Value>> {
+    let path = percent_decode( path.trim_matches('/'));
+    let mut node = Node::Root;
+
+    if !path.is_empty() {
+        for seg in path.split('/') {
+            let (name, id) = parse_segment( seg)?;
+            match next_node( opts, store, node, name, id) {
+                Some(next) => node = next,
+                None => return Ok(None)
+            }
+        }
+    }
+
+    if query.has_time_options() && !matches!( node, Node::Observations(_)) {
+        return Err( op_failed("$filter and $orderby are only supported for Observations"))
+    }
+
+    let response = match node {
+        Node::Root => json!({
+            "value": ENTITY_SETS.iter().map( |name| json!({ "name": name, "url": format!("{}/{}", opts.base_url, name) })).collect::<Vec<Value>>()
+        }),
+        Node::Thing(s) => thing( opts, s),
+        Node::Location(s) => location( opts, s).unwrap_or( Value::Null),
+        Node::Sensor(s,n) => {
+            let id = sensor_id( &s.device_id, n);
+            sensors( opts, s).into_iter().find( |v| v["@iot.id"] == id).unwrap_or( Value::Null)
+        }
+        Node::Datastream(s,n,c) => datastream( opts, s, n, c),
+        Node::Observation(rec,c) => observation( opts, &rec, c).unwrap_or( Value::Null),
+
+        Node::Sentinels(list) => collection_response( opts, &path, query, list.into_iter().map( |s| thing( opts, s)).collect()),
+        Node::Collection(list) => collection_response( opts, &path, query, list),
+        Node::Observations(mut list) => {
+            list.retain( |(rec,_)| json_record_time( rec).map_or( false, |t| query.accepts( t)));
+            if query.order == Some(SortOrder::Asc) {
+                list.reverse();
+            }
+            let entities = list.iter().filter_map( |(rec,c)| observation( opts, rec, *c)).collect();
+            collection_response( opts, &path, query, entities)
+        }
+    };
+
+    Ok(Some(response))
+}
+
+/* #endregion request resolution */
@@ -0,0 +1,165 @@
+/*
+ * Copyright (c) 2024, United States Government, as represented by the
+ * Administrator of the National Aeronautics and Space Administration.
+ * All rights reserved.
+ *
+ * The RACE - Runtime for Airspace Concept Evaluation platform is licensed
+ * under the Apache License, Version 2.0 (the "License"); you may not use
+ * this file except in compliance with the License. You may obtain a copy
+ * of the License at http://www.apache.org/licenses/LICENSE-2.0.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! HTTP server for the SensorThings projection. The SensorThingsServer actor mirrors the SentinelConnector
+//! state (through a JSON snapshot and record updates) and serves read-only requests from it
+
+use std::{collections::HashMap,sync::{Arc,Mutex}};
+use serde::{Deserialize,Serialize};
+use tokio::sync::RwLock;
+use axum::{Router,extract::State,http::{StatusCode,Uri,header},response::{IntoResponse,Response}};
+use odin_actor::prelude::*;
+use odin_actor::tokio_kanal::{Actor,ActorHandle,JoinHandle,spawn};
+use crate::*;
+use crate::actor::{SentinelConnectorMsg,AddInitCallback,SentinelsInitialized,SentinelJsonSnapshot,SentinelRecordUpdate,
+                   subscribe_mirror};
+use super::{Query,SensorThingsOpts,resolve};
+
+#[derive(Deserialize,Serialize,Debug,Clone)]
+pub struct SensorThingsConfig {
+    pub addr: String,      // socket address to bind to, e.g. "0.0.0.0:8080"
+    pub base_path: String, // path of the service root, e.g. "/v1.1"
+    pub opts: SensorThingsOpts,
+}
+
+/// max number of serialized responses we keep between store changes
+const MAX_CACHED_RESPONSES: usize = 256;
+
+/// the store we serve from, together with the serialized responses (keyed by request URI) for its current state.
+/// Responses are only resolved (under the read lock) once per store change, the cache is cleared by the actor
+/// while it holds the write lock
+struct ServedStore {
+    sentinels: SentinelStore,
+    responses: Mutex<HashMap<String,Arc<String>>>,
+}
+
+impl ServedStore {
+    fn new ()->Self {
+        ServedStore { sentinels: SentinelStore::new(), responses: Mutex::new( HashMap::new()) }
+    }
+
+    fn changed (&mut self) {
+        if let Ok(mut responses) = self.responses.lock() { responses.clear() }
+    }
+}
+
+struct ServerState {
+    base_path: String,
+    opts: SensorThingsOpts,
+    store: Arc<RwLock<ServedStore>>,
+}
+
+fn json_response (json: Arc<String>)->Response {
+    ([(header::CONTENT_TYPE, "application/json")], json.to_string()).into_response()
+}
+
+async fn handle_request (State(state): State<Arc<ServerState>>, uri: Uri)->Response {
+    let Some(path) = uri.path().strip_prefix( state.base_path.as_str()) else {
+        return StatusCode::NOT_FOUND.into_response()
+    };
+    let query = match Query::parse( uri.query().unwrap_or("")) {
+        Ok(query) => query,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response()
+    };
+
+    let key = uri.to_string();
+    let store = state.store.read().await;
+    if let Some(json) = store.responses.lock().ok().and_then( |responses| responses.get( &key).cloned()) {
+        return json_response( json)
+    }
+
+    match resolve( &store.sentinels, &state.opts, path, &query) {
+        Ok(Some(value)) => {
+            let json = Arc::new( value.to_string());
+            if let Ok(mut responses) = store.responses.lock() { // still under the read lock, i.e. this can't be stale
+                if responses.len() >= MAX_CACHED_RESPONSES { responses.clear() }
+                responses.insert( key, json.clone());
+            }
+            json_response( json)
+        }
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response()
+    }
+}
+
+async fn run_server (config: SensorThingsConfig, store: Arc<RwLock<ServedStore>>)->Result<()> {
+    let state = Arc::new( ServerState { base_path: config.base_path.clone(), opts: config.opts.clone(), store });
+    let app = Router::new().fallback( handle_request).with_state( state);
+
+    let listener = tokio::net::TcpListener::bind( &config.addr).await?;
+    axum::serve( listener, app).await?;
+    Ok(())
+}
+
+define_actor_msg_type! { pub SensorThingsServerMsg = SentinelsInitialized | SentinelJsonSnapshot | SentinelRecordUpdate }
+
+pub struct SensorThingsServer {
+    config: SensorThingsConfig,
+    hconn: ActorHandle<SentinelConnectorMsg>,
+    store: Arc<RwLock<ServedStore>>,
+    server_task: Option<JoinHandle<()>>,
+}
+
+impl SensorThingsServer {
+    pub fn new (config: SensorThingsConfig, hconn: ActorHandle<SentinelConnectorMsg>)->Self {
+        SensorThingsServer { config, hconn, store: Arc::new( RwLock::new( ServedStore::new())), server_task: None }
+    }
+
+    fn start_server (&mut self) {
+        let config = self.config.clone();
+        let store = self.store.clone();
+        self.server_task = Some( spawn( async move {
+            if let Err(e) = run_server( config, store).await {
+                eprintln!("@@ SensorThings server terminated: {:?}", e);
+            }
+        }));
+    }
+
+    fn stop_server (&mut self) {
+        if let Some(join_handle) = &self.server_task {
+            join_handle.abort();
+        }
+        self.server_task = None;
+    }
+}
+
+impl_actor! { match msg for Actor<SensorThingsServer,SensorThingsServerMsg> as
+    _Start_ => cont! {
+        self.start_server();
+        let id = self.id().to_string();
+        let hself = &self.hself;
+        self.hconn.send_msg( AddInitCallback{id, action: msg_callback!(hself, SentinelsInitialized)}).await.ok();
+    }
+    SentinelsInitialized => cont! {
+        subscribe_mirror!( self.hconn, &self.hself, self.id().to_string());
+    }
+    SentinelJsonSnapshot => cont! {
+        if let Some(sentinels) = msg.sentinels() {
+            let mut store = self.store.write().await;
+            store.sentinels = sentinels;
+            store.changed();
+        }
+    }
+    SentinelRecordUpdate => cont! {
+        let mut store = self.store.write().await;
+        store.sentinels.add_update( msg.0.as_ref().clone());
+        store.changed();
+    }
+    _Terminate_ => stop! {
+        self.stop_server()
+    }
+}
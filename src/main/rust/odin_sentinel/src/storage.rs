@@ -0,0 +1,67 @@
+/*
+ * Copyright (c) 2024, United States Government, as represented by the
+ * Administrator of the National Aeronautics and Space Administration.
+ * All rights reserved.
+ *
+ * The RACE - Runtime for Airspace Concept Evaluation platform is licensed
+ * under the Apache License, Version 2.0 (the "License"); you may not use
+ * this file except in compliance with the License. You may obtain a copy
+ * of the License at http://www.apache.org/licenses/LICENSE-2.0.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! persistent storage of sensor record history. The in-memory SentinelStore only keeps the last N records
+//! per capability, a SentinelStorage keeps everything within its retention period and can be queried by
+//! device, sensor and time range
+
+use std::fmt::Debug;
+use chrono::{DateTime,Utc};
+use crate::*;
+
+#[cfg(feature="sqlite")] mod sqlite;
+#[cfg(feature="sqlite")] pub use sqlite::*;
+
+/// the non-generic (object safe) storage interface. Records are passed in as their JSON representation, which
+/// is the same we send to clients. Use the generic `store()` and `query()` methods of `dyn SentinelStorage` to
+/// get typed access
+pub trait SentinelStorage: Debug + Send {
+    /// store a record. Storing a record with an already known id is not an error
+    fn store_record (&mut self, capability: SensorCapability, record_id: &str, device_id: &str, sensor_no: u32,
+                     time_recorded: DateTime<Utc>, json: &str)->Result<()>;
+
+    /// get the JSON records for a device (and optional sensor) within [start,end], sorted in ascending time
+    fn query_records (&self, capability: SensorCapability, device_id: &str, sensor_no: Option<u32>,
+                      start: DateTime<Utc>, end: DateTime<Utc>)->Result<Vec<String>>;
+
+    /// remove all records that were recorded before the given time and return how many were removed
+    fn remove_older_than (&mut self, cutoff: DateTime<Utc>)->Result<usize>;
+}
+
+impl<'a> dyn SentinelStorage + 'a {
+    pub fn store<T> (&mut self, rec: &SensorRecord<T>)->Result<()> where T: RecordDataBounds {
+        let json = serde_json::to_string(rec)?;
+        self.store_record( T::capability(), &rec.id, &rec.device_id, rec.sensor_no, rec.time_recorded, &json)
+    }
+
+    pub fn store_all<T> (&mut self, recs: &VecDeque<SensorRecord<T>>)->Result<()> where T: RecordDataBounds {
+        for rec in recs {
+            self.store( rec)?;
+        }
+        Ok(())
+    }
+
+    pub fn query<T> (&self, device_id: &str, sensor_no: Option<u32>, start: DateTime<Utc>, end: DateTime<Utc>)->Result<Vec<SensorRecord<T>>> 
+        where T: RecordDataBounds
+    {
+        let mut recs = Vec::new();
+        for json in self.query_records( T::capability(), device_id, sensor_no, start, end)? {
+            recs.push( serde_json::from_str( &json)?);
+        }
+        Ok(recs)
+    }
+}
@@ -0,0 +1,379 @@
+/*
+ * Copyright (c) 2024, United States Government, as represented by the
+ * Administrator of the National Aeronautics and Space Administration.
+ * All rights reserved.
+ *
+ * The RACE - Runtime for Airspace Concept Evaluation platform is licensed
+ * under the Apache License, Version 2.0 (the "License"); you may not use
+ * this file except in compliance with the License. You may obtain a copy
+ * of the License at http://www.apache.org/licenses/LICENSE-2.0.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{collections::HashMap,path::PathBuf,time::Duration,thread,sync::{Arc,Mutex,mpsc}};
+use chrono::{DateTime,TimeZone,Utc};
+use rusqlite::{Connection,params,params_from_iter,types::{Value as SqlValue,ValueRef}};
+use serde::{Deserialize,Serialize};
+use serde_json::Value;
+use strum::IntoEnumIterator;
+use crate::*;
+use super::SentinelStorage;
+
+#[derive(Deserialize,Serialize,Debug,Clone)]
+pub struct SqliteStorageConfig {
+    pub path: PathBuf,               // the database file
+    pub retention: Option<Duration>, // how long we keep records. If not set we keep everything
+    pub purge_interval: Duration,    // how often we check for records that exceed the retention period
+}
+
+/// the record columns that are the same for all capability tables
+const ENVELOPE_NAMES: [&str;6] = ["id", "device_id", "sensor_no", "time_recorded", "evidences", "claims"];
+const ENVELOPE_COLUMNS: &str = "id TEXT PRIMARY KEY, device_id TEXT NOT NULL, sensor_no INTEGER NOT NULL, \
+                                time_recorded INTEGER NOT NULL, evidences JSON NOT NULL, claims JSON NOT NULL";
+
+/// separator of nested payload field names (e.g. `orientationRecord.id`). Field names can contain '_'
+const FIELD_SEPARATOR: char = '.';
+
+/// the declared SQLite type of a payload column, which is what we use to restore the JSON type of its values
+#[derive(Debug,Clone,Copy,PartialEq)]
+enum ColumnType { Integer, Real, Boolean, Text, Json }
+
+impl ColumnType {
+    fn of (v: &Value)->Option<Self> {
+        match v {
+            Value::Null => None,
+            Value::Bool(_) => Some(ColumnType::Boolean),
+            Value::Number(n) => Some( if n.is_f64() { ColumnType::Real } else { ColumnType::Integer }),
+            Value::String(_) => Some(ColumnType::Text),
+            _ => Some(ColumnType::Json)
+        }
+    }
+
+    fn from_decl (decl: &str)->Self {
+        match decl {
+            "INTEGER" => ColumnType::Integer,
+            "REAL" => ColumnType::Real,
+            "BOOLEAN" => ColumnType::Boolean,
+            "JSON" => ColumnType::Json,
+            _ => ColumnType::Text
+        }
+    }
+
+    fn decl (&self)->&'static str {
+        match self {
+            ColumnType::Integer => "INTEGER",
+            ColumnType::Real => "REAL",
+            ColumnType::Boolean => "BOOLEAN",
+            ColumnType::Text => "TEXT",
+            ColumnType::Json => "JSON",
+        }
+    }
+
+    fn to_sql (&self, v: &Value)->SqlValue {
+        match (self, v) {
+            (_, Value::Null) => SqlValue::Null,
+            (ColumnType::Integer, Value::Number(n)) => n.as_i64().map_or( SqlValue::Null, SqlValue::Integer),
+            (ColumnType::Real, Value::Number(n)) => n.as_f64().map_or( SqlValue::Null, SqlValue::Real),
+            (ColumnType::Boolean, Value::Bool(b)) => SqlValue::Integer( *b as i64),
+            (ColumnType::Text, Value::String(s)) => SqlValue::Text( s.clone()),
+            (_, v) => SqlValue::Text( v.to_string()) // type changed - keep the JSON
+        }
+    }
+
+    fn to_json (&self, v: ValueRef)->Value {
+        match (self, v) {
+            (_, ValueRef::Null) => Value::Null,
+            (ColumnType::Boolean, ValueRef::Integer(i)) => Value::Bool( i != 0),
+            (_, ValueRef::Integer(i)) => Value::from(i),
+            (_, ValueRef::Real(r)) => Value::from(r),
+            (ColumnType::Json, ValueRef::Text(t)) => serde_json::from_slice( t).unwrap_or( Value::Null),
+            (_, ValueRef::Text(t)) => Value::from( String::from_utf8_lossy( t).to_string()),
+            (_, ValueRef::Blob(_)) => Value::Null
+        }
+    }
+}
+
+/// a record row as it is passed to the writer thread
+#[derive(Debug)]
+struct RecordRow {
+    id: String,
+    capability: SensorCapability,
+    device_id: String,
+    sensor_no: u32,
+    time_recorded: i64, // epoch millis
+    json: String,
+}
+
+#[derive(Debug)]
+enum WriterCmd {
+    Store(RecordRow),
+    Purge(DateTime<Utc>, mpsc::Sender<Result<usize>>),
+    Flush(mpsc::Sender<()>),
+    Stop,
+}
+
+/// an embedded SQLite database with one table per capability (named by its record property, e.g. `thermometer`).
+/// Tables have typed id, device_id, sensor_no, time_recorded (epoch millis), evidences and claims columns plus one typed
+/// column per (flattened) payload field, i.e. the archive can be queried with plain SQL. Payload columns are added when
+/// the first record with a (non-null) value for the field is stored. Inserts and purges are done by a dedicated writer
+/// thread so that storing records does not block the (async) SentinelConnector
+#[derive(Debug)]
+pub struct SqliteStorage {
+    conn: Arc<Mutex<Connection>>,
+    tx: mpsc::Sender<WriterCmd>,
+    writer: Option<thread::JoinHandle<()>>,
+}
+
+impl SqliteStorage {
+    pub fn open (config: SqliteStorageConfig)->Result<Self> {
+        let conn = Connection::open( &config.path)?;
+        Self::init( conn, config)
+    }
+
+    /// create a non-persistent instance (mostly for testing)
+    pub fn open_in_memory (config: SqliteStorageConfig)->Result<Self> {
+        let conn = Connection::open_in_memory()?;
+        Self::init( conn, config)
+    }
+
+    fn init (conn: Connection, config: SqliteStorageConfig)->Result<Self> {
+        for capability in SensorCapability::iter() {
+            let table = capability.property_name();
+            conn.execute_batch( &format!(
+                "CREATE TABLE IF NOT EXISTS \"{table}\" ({ENVELOPE_COLUMNS});
+                 CREATE INDEX IF NOT EXISTS \"{table}_device_idx\" ON \"{table}\" (device_id, sensor_no, time_recorded);
+                 CREATE INDEX IF NOT EXISTS \"{table}_time_idx\" ON \"{table}\" (time_recorded);"
+            ))?;
+        }
+
+        let conn = Arc::new( Mutex::new( conn));
+        let (tx, rx) = mpsc::channel();
+        let writer_conn = conn.clone();
+        let writer = thread::Builder::new().name( "sqlite-writer".to_string()).spawn( move || run_writer( writer_conn, config, rx))?;
+
+        Ok( SqliteStorage { conn, tx, writer: Some(writer) } )
+    }
+
+    fn send (&self, cmd: WriterCmd)->Result<()> {
+        self.tx.send( cmd).map_err( |_| op_failed("sqlite writer terminated"))
+    }
+
+    /// wait until the writer thread has processed all records that were stored before
+    pub fn flush (&self)->Result<()> {
+        let (tx, rx) = mpsc::channel();
+        self.send( WriterCmd::Flush(tx))?;
+        rx.recv().map_err( |_| op_failed("sqlite writer terminated"))
+    }
+}
+
+impl Drop for SqliteStorage {
+    fn drop (&mut self) {
+        self.tx.send( WriterCmd::Stop).ok();
+        if let Some(writer) = self.writer.take() {
+            writer.join().ok();
+        }
+    }
+}
+
+/// the writer loop wakes up at least every purge interval so that records also expire if nothing is stored
+fn run_writer (conn: Arc<Mutex<Connection>>, config: SqliteStorageConfig, rx: mpsc::Receiver<WriterCmd>) {
+    let mut columns: HashMap<&'static str,HashMap<String,ColumnType>> = HashMap::new();
+    let purge_interval = config.purge_interval.max( Duration::from_secs(1));
+    let mut last_purge = Utc::now();
+
+    loop {
+        match rx.recv_timeout( purge_interval) {
+            Ok(WriterCmd::Store(row)) => {
+                if let Err(e) = insert_row( &conn, &mut columns, &row) {
+                    eprintln!("@@ failed to store record {}: {:?}", row.id, e);
+                }
+            }
+            Ok(WriterCmd::Purge(cutoff, reply)) => { reply.send( delete_older_than( &conn, cutoff)).ok(); }
+            Ok(WriterCmd::Flush(reply)) => { reply.send(()).ok(); }
+            Ok(WriterCmd::Stop) | Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+        }
+
+        if let Some(retention) = config.retention {
+            let now = Utc::now();
+            if (now - last_purge).to_std().map_or( false, |dt| dt >= purge_interval) {
+                last_purge = now;
+                let cutoff = chrono::Duration::from_std( retention).ok().and_then( |r| now.checked_sub_signed( r));
+                if let Some(cutoff) = cutoff { // retention periods that exceed the time range are never reached
+                    if let Err(e) = delete_older_than( &conn, cutoff) {
+                        eprintln!("@@ failed to purge records: {:?}", e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn lock (conn: &Mutex<Connection>)->Result<std::sync::MutexGuard<'_,Connection>> {
+    conn.lock().map_err( |_| op_failed("sqlite connection poisoned"))
+}
+
+/// the declared types of the payload columns of a capability table
+fn table_columns (conn: &Connection, capability: SensorCapability)->Result<HashMap<String,ColumnType>> {
+    let mut stmt = conn.prepare( &format!("PRAGMA table_info(\"{}\")", capability.property_name()))?;
+    let rows = stmt.query_map( [], |row| Ok( (row.get::<_,String>(1)?, row.get::<_,String>(2)?)))?;
+
+    let mut columns = HashMap::new();
+    for row in rows {
+        let (name, decl) = row?;
+        if !is_envelope_column( &name) {
+            columns.insert( name, ColumnType::from_decl( &decl));
+        }
+    }
+    Ok(columns)
+}
+
+fn is_envelope_column (name: &str)->bool {
+    ENVELOPE_NAMES.contains( &name)
+}
+
+fn flatten_payload (prefix: &str, value: &Value, fields: &mut Vec<(String,Value)>) {
+    match value {
+        Value::Object(map) => {
+            for (k,v) in map {
+                let name = if prefix.is_empty() { k.clone() } else { format!("{prefix}{FIELD_SEPARATOR}{k}") };
+                flatten_payload( &name, v, fields);
+            }
+        }
+        _ => fields.push( (prefix.to_string(), value.clone()))
+    }
+}
+
+fn insert_row (conn: &Mutex<Connection>, columns: &mut HashMap<&'static str,HashMap<String,ColumnType>>, row: &RecordRow)->Result<()> {
+    let conn = lock( conn)?;
+    let table = row.capability.property_name();
+    let rec: Value = serde_json::from_str( &row.json)?;
+
+    let mut fields = Vec::new();
+    if let Some(payload) = rec.get( table) {
+        flatten_payload( "", payload, &mut fields);
+    }
+
+    let known = match columns.entry( table) {
+        std::collections::hash_map::Entry::Occupied(e) => e.into_mut(),
+        std::collections::hash_map::Entry::Vacant(e) => e.insert( table_columns( &conn, row.capability)?)
+    };
+
+    let mut names: Vec<String> = ENVELOPE_NAMES.iter().map( |n| format!("\"{n}\"")).collect();
+    let mut values = vec![
+        SqlValue::Text( row.id.clone()),
+        SqlValue::Text( row.device_id.clone()),
+        SqlValue::Integer( row.sensor_no as i64),
+        SqlValue::Integer( row.time_recorded),
+        SqlValue::Text( rec.get("evidences").map_or( "[]".to_string(), |v| v.to_string())),
+        SqlValue::Text( rec.get("claims").map_or( "[]".to_string(), |v| v.to_string())),
+    ];
+
+    for (name, value) in &fields {
+        let column_type = match known.get( name) {
+            Some(column_type) => *column_type,
+            None => {
+                let Some(column_type) = ColumnType::of( value) else { continue }; // nothing to store yet
+                conn.execute_batch( &format!("ALTER TABLE \"{table}\" ADD COLUMN \"{name}\" {}", column_type.decl()))?;
+                known.insert( name.clone(), column_type);
+                column_type
+            }
+        };
+        names.push( format!("\"{name}\""));
+        values.push( column_type.to_sql( value));
+    }
+
+    let placeholders: Vec<String> = (1..=values.len()).map( |i| format!("?{i}")).collect();
+    conn.execute(
+        &format!("INSERT OR IGNORE INTO \"{table}\" ({}) VALUES ({})", names.join(", "), placeholders.join(", ")),
+        params_from_iter( values)
+    )?;
+    Ok(())
+}
+
+fn delete_older_than (conn: &Mutex<Connection>, cutoff: DateTime<Utc>)->Result<usize> {
+    let conn = lock( conn)?;
+    let mut n = 0;
+    for capability in SensorCapability::iter() {
+        n += conn.execute( &format!("DELETE FROM \"{}\" WHERE time_recorded < ?1", capability.property_name()), params![ cutoff.timestamp_millis()])?;
+    }
+    Ok(n)
+}
+
+/// set a (possibly nested) payload field. Null values are omitted, which deserializes optional fields as None
+fn set_field (payload: &mut serde_json::Map<String,Value>, name: &str, value: Value) {
+    match name.split_once( FIELD_SEPARATOR) {
+        Some((head, tail)) => {
+            let entry = payload.entry( head.to_string()).or_insert_with( || Value::Object( serde_json::Map::new()));
+            if let Value::Object(map) = entry { set_field( map, tail, value) }
+        }
+        None => { payload.insert( name.to_string(), value); }
+    }
+}
+
+impl SentinelStorage for SqliteStorage {
+    fn store_record (&mut self, capability: SensorCapability, record_id: &str, device_id: &str, sensor_no: u32,
+                     time_recorded: DateTime<Utc>, json: &str)->Result<()> {
+        self.send( WriterCmd::Store( RecordRow {
+            id: record_id.to_string(),
+            capability,
+            device_id: device_id.to_string(),
+            sensor_no,
+            time_recorded: time_recorded.timestamp_millis(),
+            json: json.to_string(),
+        }))
+    }
+
+    fn query_records (&self, capability: SensorCapability, device_id: &str, sensor_no: Option<u32>,
+                      start: DateTime<Utc>, end: DateTime<Utc>)->Result<Vec<String>> {
+        self.flush()?; // make sure we see all records that were stored before
+
+        let conn = lock( &self.conn)?;
+        let columns = table_columns( &conn, capability)?;
+        let table = capability.property_name();
+        let mut stmt = conn.prepare( &format!(
+            "SELECT * FROM \"{table}\" WHERE device_id = ?1 AND (?2 IS NULL OR sensor_no = ?2)
+             AND time_recorded >= ?3 AND time_recorded <= ?4 ORDER BY time_recorded ASC"
+        ))?;
+        let names: Vec<String> = stmt.column_names().iter().map( |n| n.to_string()).collect();
+
+        let mut rows = stmt.query( params![ device_id, sensor_no, start.timestamp_millis(), end.timestamp_millis()])?;
+        let mut list = Vec::new();
+        while let Some(row) = rows.next()? {
+            let mut rec = serde_json::Map::new();
+            let mut payload = serde_json::Map::new();
+
+            for (i,name) in names.iter().enumerate() {
+                let v = row.get_ref(i)?;
+                match name.as_str() {
+                    "id" => { rec.insert( "id".to_string(), ColumnType::Text.to_json(v)); }
+                    "device_id" => { rec.insert( "deviceId".to_string(), ColumnType::Text.to_json(v)); }
+                    "sensor_no" => { rec.insert( "sensorNo".to_string(), ColumnType::Integer.to_json(v)); }
+                    "time_recorded" => {
+                        let t = v.as_i64().ok().and_then( |ms| Utc.timestamp_millis_opt( ms).single()).ok_or( no_data("time_recorded"))?;
+                        rec.insert( "timeRecorded".to_string(), serde_json::to_value( t)?);
+                    }
+                    "evidences" | "claims" => { rec.insert( name.clone(), ColumnType::Json.to_json(v)); }
+                    _ => {
+                        let value = columns.get( name).copied().unwrap_or( ColumnType::Text).to_json(v);
+                        if !value.is_null() { set_field( &mut payload, name, value) }
+                    }
+                }
+            }
+            rec.insert( table.to_string(), Value::Object(payload));
+            list.push( Value::Object(rec).to_string());
+        }
+        Ok(list)
+    }
+
+    fn remove_older_than (&mut self, cutoff: DateTime<Utc>)->Result<usize> {
+        let (tx, rx) = mpsc::channel();
+        self.send( WriterCmd::Purge( cutoff, tx))?;
+        rx.recv().map_err( |_| op_failed("sqlite writer terminated"))?
+    }
+}
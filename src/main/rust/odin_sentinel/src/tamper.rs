@@ -0,0 +1,275 @@
+/*
+ * Copyright (c) 2024, United States Government, as represented by the
+ * Administrator of the National Aeronautics and Space Administration.
+ * All rights reserved.
+ *
+ * The RACE - Runtime for Airspace Concept Evaluation platform is licensed
+ * under the Apache License, Version 2.0 (the "License"); you may not use
+ * this file except in compliance with the License. You may obtain a copy
+ * of the License at http://www.apache.org/licenses/LICENSE-2.0.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! tamper and tip-over detection from the 9-axis motion tracking sensors. For each device and sensor the
+//! TamperDetector learns the resting gravity vector (accelerometer) and heading (magnetometer, tilt compensated
+//! with the resting gravity) and reports tilt beyond a threshold, shocks, fast rotations and heading changes.
+//! Accelerometer values are only interpreted relative to the learned gravity magnitude so we don't depend on
+//! their unit, gyroscope rates are assumed to be in deg/s
+
+use std::{collections::HashMap,sync::Arc};
+use chrono::{DateTime,Utc};
+use serde::{Deserialize,Serialize};
+use odin_actor::prelude::*;
+use odin_actor::tokio_kanal::{Actor,ActorHandle};
+use crate::*;
+use crate::actor::{SentinelConnectorMsg,AddInitCallback,SentinelsInitialized,SentinelJsonSnapshot,SentinelRecordUpdate,
+                   subscribe_mirror};
+
+#[derive(Deserialize,Serialize,Debug,Clone)]
+pub struct TamperConfig {
+    pub min_samples: usize,      // number of resting samples before we report anything
+    pub learn_rate: f64,         // weight of new resting samples for the baseline (exponential moving average)
+    pub tilt_threshold: f64,     // degrees between resting and current gravity vector
+    pub shock_threshold: f64,    // acceleration change relative to resting gravity (i.e. in g)
+    pub rotation_threshold: f64, // gyroscope rate in deg/s
+    pub heading_threshold: f64,  // heading change in degrees
+}
+
+impl Default for TamperConfig {
+    fn default()->Self {
+        TamperConfig { min_samples: 5, learn_rate: 0.05, tilt_threshold: 20.0, shock_threshold: 0.5, rotation_threshold: 90.0, heading_threshold: 15.0 }
+    }
+}
+
+#[derive(Serialize,Deserialize,Debug,Clone,PartialEq)]
+#[serde(tag="type", rename_all="camelCase")]
+pub enum TamperKind {
+    Tilt { angle: f64 },
+    Upright { angle: f64 },  // tilt back within threshold
+    Shock { magnitude: f64 },
+    Rotation { rate: f64 },
+    HeadingChange { delta: f64 },
+}
+
+#[derive(Serialize,Deserialize,Debug,Clone)]
+#[serde(rename_all="camelCase")]
+pub struct TamperEvent {
+    pub device_id: DeviceId,
+    pub sensor_no: u32,
+    pub record_id: String,
+    pub time_recorded: DateTime<Utc>,
+    pub kind: TamperKind,
+}
+
+type Vec3 = [f64;3];
+
+fn dot (a: &Vec3, b: &Vec3)->f64 { a[0]*b[0] + a[1]*b[1] + a[2]*b[2] }
+fn cross (a: &Vec3, b: &Vec3)->Vec3 { [a[1]*b[2] - a[2]*b[1], a[2]*b[0] - a[0]*b[2], a[0]*b[1] - a[1]*b[0]] }
+fn norm (a: &Vec3)->f64 { dot( a, a).sqrt() }
+fn sub (a: &Vec3, b: &Vec3)->Vec3 { [a[0]-b[0], a[1]-b[1], a[2]-b[2]] }
+fn scale (a: &Vec3, s: f64)->Vec3 { [a[0]*s, a[1]*s, a[2]*s] }
+
+fn angle_between (a: &Vec3, b: &Vec3)->f64 {
+    let n = norm( a) * norm( b);
+    if n == 0.0 { 0.0 } else { (dot( a, b) / n).clamp( -1.0, 1.0).acos().to_degrees() }
+}
+
+/// signed heading of the magnetic field around the gravity axis, relative to the sensor x-axis (degrees)
+fn heading (gravity: &Vec3, mag: &Vec3)->Option<f64> {
+    let g = scale( gravity, 1.0 / norm( gravity));
+    let m = sub( mag, &scale( &g, dot( mag, &g)));
+    let x = sub( &[1.0, 0.0, 0.0], &scale( &g, g[0]));
+    if norm( &m) < 1e-9 || norm( &x) < 1e-9 { return None }
+
+    Some( dot( &g, &cross( &x, &m)).atan2( dot( &x, &m)).to_degrees())
+}
+
+fn heading_diff (a: f64, b: f64)->f64 {
+    (a - b + 540.0).rem_euclid( 360.0) - 180.0
+}
+
+/// what we learned about a motion tracking sensor
+#[derive(Debug,Clone,Default)]
+struct MotionState {
+    gravity: Option<Vec3>,
+    samples: usize,
+    tilted: bool,
+    heading: Option<f64>,
+    heading_samples: usize,
+    last_heading_event: Option<f64>,
+}
+
+#[derive(Debug,Default)]
+pub struct TamperDetector {
+    config: TamperConfig,
+    states: HashMap<(DeviceId,u32),MotionState>,
+}
+
+impl TamperDetector {
+    pub fn new (config: TamperConfig)->Self {
+        TamperDetector { config, states: HashMap::new() }
+    }
+
+    /// learn from all motion records of a store (oldest first) without reporting events
+    pub fn learn (&mut self, store: &SentinelStore) {
+        for sentinel in store.values() {
+            let mut updates: Vec<SentinelUpdate> = Vec::new();
+            updates.extend( sentinel.records::<AccelerometerData>().iter().map( |r| SentinelUpdate::from( r.clone())));
+            updates.extend( sentinel.records::<MagnetometerData>().iter().map( |r| SentinelUpdate::from( r.clone())));
+            updates.sort_by_key( |u| u.time_recorded());
+            for u in &updates { self.process( u); }
+        }
+    }
+
+    /// process a new record, returning the events it caused
+    pub fn process (&mut self, update: &SentinelUpdate)->Vec<TamperEvent> {
+        match update {
+            SentinelUpdate::Accelerometer(rec) => self.accelerometer( rec),
+            SentinelUpdate::Gyroscope(rec) => self.gyroscope( rec),
+            SentinelUpdate::Magnetometer(rec) => self.magnetometer( rec),
+            _ => Vec::new()
+        }
+    }
+
+    fn event<T> (rec: &SensorRecord<T>, kind: TamperKind)->TamperEvent where T: RecordDataBounds {
+        TamperEvent { device_id: rec.device_id.clone(), sensor_no: rec.sensor_no, record_id: rec.id.clone(), time_recorded: rec.time_recorded, kind }
+    }
+
+    fn state (&mut self, device_id: &DeviceId, sensor_no: u32)->&mut MotionState {
+        self.states.entry( (device_id.clone(), sensor_no)).or_default()
+    }
+
+    fn accelerometer (&mut self, rec: &SensorRecord<AccelerometerData>)->Vec<TamperEvent> {
+        let config = self.config.clone();
+        let a: Vec3 = [rec.data.ax as f64, rec.data.ay as f64, rec.data.az as f64];
+        let state = self.state( &rec.device_id, rec.sensor_no);
+        let mut events = Vec::new();
+
+        let Some(gravity) = state.gravity else {
+            if norm( &a) > 0.0 { state.gravity = Some(a); state.samples = 1; }
+            return events
+        };
+        let g = norm( &gravity);
+        let learned = state.samples >= config.min_samples;
+
+        let shock = norm( &sub( &a, &gravity)) / g;
+        let tilt = angle_between( &gravity, &a);
+        let magnitude_ok = (norm( &a) / g - 1.0).abs() < config.shock_threshold;
+
+        if learned && shock > config.shock_threshold && !magnitude_ok {
+            events.push( Self::event( rec, TamperKind::Shock{ magnitude: shock }));
+        }
+
+        if magnitude_ok { // otherwise the tilt angle is not meaningful
+            if tilt > config.tilt_threshold {
+                if learned && !state.tilted { events.push( Self::event( rec, TamperKind::Tilt{ angle: tilt })); }
+                state.tilted = learned;
+            } else {
+                if state.tilted { events.push( Self::event( rec, TamperKind::Upright{ angle: tilt })); }
+                state.tilted = false;
+
+                // only resting samples update the baseline
+                let w = if learned { config.learn_rate } else { 1.0 / (state.samples + 1) as f64 };
+                state.gravity = Some( [0,1,2].map( |i| gravity[i] + w * (a[i] - gravity[i])));
+                state.samples += 1;
+            }
+        }
+        events
+    }
+
+    fn gyroscope (&mut self, rec: &SensorRecord<GyroscopeData>)->Vec<TamperEvent> {
+        let rate = norm( &[rec.data.gx, rec.data.gy, rec.data.gz]);
+        let learned = self.state( &rec.device_id, rec.sensor_no).samples >= self.config.min_samples;
+
+        if learned && rate > self.config.rotation_threshold {
+            vec![ Self::event( rec, TamperKind::Rotation{ rate }) ]
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn magnetometer (&mut self, rec: &SensorRecord<MagnetometerData>)->Vec<TamperEvent> {
+        let config = self.config.clone();
+        let state = self.state( &rec.device_id, rec.sensor_no);
+        let mut events = Vec::new();
+
+        let (Some(gravity), false) = (state.gravity, state.tilted) else { return events };
+        let Some(h) = heading( &gravity, &[rec.data.mx, rec.data.my, rec.data.mz]) else { return events };
+
+        match state.heading {
+            None => { state.heading = Some(h); state.heading_samples = 1; }
+            Some(h0) => {
+                let delta = heading_diff( h, h0);
+                if delta.abs() > config.heading_threshold {
+                    // report once per new heading, not for every record
+                    let reported = state.last_heading_event.map_or( false, |last| heading_diff( h, last).abs() <= config.heading_threshold);
+                    if state.heading_samples >= config.min_samples && !reported {
+                        events.push( Self::event( rec, TamperKind::HeadingChange{ delta }));
+                        state.last_heading_event = Some(h);
+                    }
+                } else {
+                    let w = if state.heading_samples >= config.min_samples { config.learn_rate } else { 1.0 / (state.heading_samples + 1) as f64 };
+                    state.heading = Some( (h0 + w * delta + 540.0).rem_euclid( 360.0) - 180.0);
+                    state.heading_samples += 1;
+                    state.last_heading_event = None;
+                }
+            }
+        }
+        events
+    }
+}
+
+/* #region tamper monitor actor ******************************************************************/
+
+#[derive(Debug)] pub struct AddTamperCallback { pub id: String, pub action: Callback<Arc<TamperEvent>> }
+
+define_actor_msg_type! { pub TamperMonitorMsg = AddTamperCallback | SentinelsInitialized | SentinelJsonSnapshot | SentinelRecordUpdate }
+
+pub struct TamperMonitor {
+    hconn: ActorHandle<SentinelConnectorMsg>,
+    detector: TamperDetector,
+    tamper_callbacks: CallbackList<Arc<TamperEvent>>,
+}
+
+impl TamperMonitor {
+    pub fn new (config: TamperConfig, hconn: ActorHandle<SentinelConnectorMsg>)->Self {
+        TamperMonitor { hconn, detector: TamperDetector::new( config), tamper_callbacks: CallbackList::new() }
+    }
+
+    async fn update (&mut self, update: &SentinelUpdate) {
+        if matches!( update.capability(), SensorCapability::Accelerometer | SensorCapability::Gyroscope | SensorCapability::Magnetometer) {
+            for event in self.detector.process( update) {
+                self.tamper_callbacks.trigger( Arc::new(event)).await;
+            }
+        }
+    }
+}
+
+impl_actor! { match msg for Actor<TamperMonitor,TamperMonitorMsg> as
+    _Start_ => cont! {
+        let id = self.id().to_string();
+        let hself = &self.hself;
+        self.hconn.send_msg( AddInitCallback{id, action: msg_callback!(hself, SentinelsInitialized)}).await.ok();
+    }
+    AddTamperCallback => cont! {
+        self.tamper_callbacks.add( msg.id, msg.action)
+    }
+    SentinelsInitialized => cont! {
+        subscribe_mirror!( self.hconn, &self.hself, self.id().to_string());
+    }
+    SentinelJsonSnapshot => cont! {
+        if let Some(sentinels) = msg.sentinels() {
+            self.detector.learn( &sentinels) // history is only used to learn resting baselines
+        }
+    }
+    SentinelRecordUpdate => cont! {
+        self.update( &msg.0).await
+    }
+}
+
+/* #endregion tamper monitor actor */
@@ -0,0 +1,163 @@
+/*
+ * Copyright (c) 2024, United States Government, as represented by the
+ * Administrator of the National Aeronautics and Space Administration.
+ * All rights reserved.
+ *
+ * The RACE - Runtime for Airspace Concept Evaluation platform is licensed
+ * under the Apache License, Version 2.0 (the "License"); you may not use
+ * this file except in compliance with the License. You may obtain a copy
+ * of the License at http://www.apache.org/licenses/LICENSE-2.0.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! time window based views of sentinel history (positions and alert states) that are shared by
+//! time-dynamic exporters such as CZML and KML
+
+use chrono::{DateTime,Utc};
+use serde::{Deserialize,Serialize};
+use crate::*;
+use crate::position::{PositionConfig,PositionEstimate};
+
+#[derive(Serialize,Deserialize,Debug,PartialEq,Eq,PartialOrd,Ord,Copy,Clone)]
+#[serde(rename_all="lowercase")]
+pub enum AlertState {
+    Normal,
+    Warning,
+    Alert
+}
+
+/// fire/smoke probability thresholds for alert states
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct AlertThresholds {
+    pub warning: f64,
+    pub alert: f64,
+}
+
+impl Default for AlertThresholds {
+    fn default()->Self {
+        AlertThresholds { warning: 0.5, alert: 0.8 }
+    }
+}
+
+impl AlertThresholds {
+    pub fn state (&self, prob: f64)->AlertState {
+        if prob >= self.alert { AlertState::Alert } 
+        else if prob >= self.warning { AlertState::Warning } 
+        else { AlertState::Normal }
+    }
+
+    /// the state for optional fire and smoke probabilities - the higher one wins
+    pub fn combined_state (&self, fire_prob: Option<f64>, smoke_prob: Option<f64>)->AlertState {
+        let prob = fire_prob.unwrap_or(0.0).max( smoke_prob.unwrap_or(0.0));
+        self.state( prob)
+    }
+}
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct TimeWindow {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+impl TimeWindow {
+    pub fn new (start: DateTime<Utc>, end: DateTime<Utc>)->Self {
+        TimeWindow { start, end }
+    }
+
+    pub fn contains (&self, t: DateTime<Utc>)->bool {
+        t >= self.start && t <= self.end
+    }
+
+    /// the window that covers all position and fire/smoke records of the store, or None if there are none
+    pub fn covering (store: &SentinelStore)->Option<Self> {
+        let mut start: Option<DateTime<Utc>> = None;
+        let mut end: Option<DateTime<Utc>> = None;
+
+        for s in store.values() {
+            // record lists are sorted newest first
+            let firsts = [ s.gps.back().map(|r| r.time_recorded), s.fire.back().map(|r| r.time_recorded), s.smoke.back().map(|r| r.time_recorded) ];
+            let lasts = [ s.gps.front().map(|r| r.time_recorded), s.fire.front().map(|r| r.time_recorded), s.smoke.front().map(|r| r.time_recorded) ];
+
+            for t in firsts.into_iter().flatten() { if start.map_or( true, |t0| t < t0) { start = Some(t) } }
+            for t in lasts.into_iter().flatten() { if end.map_or( true, |t1| t > t1) { end = Some(t) } }
+        }
+
+        Some( TimeWindow { start: start?, end: end? })
+    }
+
+    /// ISO 8601 interval string as used by CZML
+    pub fn to_iso_interval (&self)->String {
+        iso_interval( self.start, self.end)
+    }
+}
+
+pub fn iso_interval (start: DateTime<Utc>, end: DateTime<Utc>)->String {
+    format!("{}/{}", start.to_rfc3339(), end.to_rfc3339())
+}
+
+/// the alert state of a sentinel between two fire/smoke records
+#[derive(Debug,Clone)]
+pub struct AlertInterval {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub state: AlertState,
+    pub fire_prob: Option<f64>,  // effective (cloud cover adjusted) probabilities if available
+    pub smoke_prob: Option<f64>,
+}
+
+/// the position of a sentinel at the given time, which is the latest GPS fix that is not newer than t
+pub fn position_at (sentinel: &Sentinel, t: DateTime<Utc>)->Option<&GpsData> {
+    sentinel.gps.iter().find( |r| r.time_recorded <= t).map( |r| &r.data)
+}
+
+/// time-tagged position estimates for the GPS fixes within the window in ascending time order. If there is an
+/// earlier fix the estimate at the window start is added so that the sentinel is visible for the whole window
+pub fn positions (sentinel: &Sentinel, config: &PositionConfig, window: &TimeWindow)->Vec<(DateTime<Utc>,PositionEstimate)> {
+    let mut list: Vec<(DateTime<Utc>,PositionEstimate)> = sentinel.gps.iter().rev()
+        .filter( |r| window.contains( r.time_recorded))
+        .filter_map( |r| sentinel.position_at( config, r.time_recorded).map( |pos| (r.time_recorded, pos)))
+        .collect();
+
+    if list.first().map_or( true, |(t,_)| *t > window.start) && position_at( sentinel, window.start).is_some() {
+        if let Some(pos) = sentinel.position_at( config, window.start) {
+            list.insert( 0, (window.start, pos));
+        }
+    }
+    list
+}
+
+/// the sequence of alert intervals that covers the window. A new interval starts with each fire or smoke record
+pub fn alert_intervals (sentinel: &Sentinel, window: &TimeWindow, thresholds: &AlertThresholds)->Vec<AlertInterval> {
+    // the probabilities that were current at window start
+    let mut fire_prob = sentinel.fire.iter().find( |r| r.time_recorded <= window.start).map( |r| r.data.confidence());
+    let mut smoke_prob = sentinel.smoke.iter().find( |r| r.time_recorded <= window.start).map( |r| r.data.confidence());
+
+    let mut events: Vec<(DateTime<Utc>,Option<f64>,Option<f64>)> = Vec::new();
+    for r in sentinel.fire.iter().filter( |r| r.time_recorded > window.start && r.time_recorded <= window.end) {
+        events.push( (r.time_recorded, Some(r.data.confidence()), None));
+    }
+    for r in sentinel.smoke.iter().filter( |r| r.time_recorded > window.start && r.time_recorded <= window.end) {
+        events.push( (r.time_recorded, None, Some(r.data.confidence())));
+    }
+    events.sort_by_key( |e| e.0);
+
+    let mut list = Vec::new();
+    let mut start = window.start;
+    for (t, fp, sp) in events {
+        if t > start {
+            list.push( AlertInterval { start, end: t, state: thresholds.combined_state( fire_prob, smoke_prob), fire_prob, smoke_prob });
+            start = t;
+        }
+        if fp.is_some() { fire_prob = fp }
+        if sp.is_some() { smoke_prob = sp }
+    }
+    // always add the state after the last record, which is a zero length interval if that record is at the window end
+    list.push( AlertInterval { start, end: window.end, state: thresholds.combined_state( fire_prob, smoke_prob), fire_prob, smoke_prob });
+
+    list
+}
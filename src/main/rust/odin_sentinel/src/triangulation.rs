@@ -0,0 +1,290 @@
+/*
+ * Copyright (c) 2024, United States Government, as represented by the
+ * Administrator of the National Aeronautics and Space Administration.
+ * All rights reserved.
+ *
+ * The RACE - Runtime for Airspace Concept Evaluation platform is licensed
+ * under the Apache License, Version 2.0 (the "License"); you may not use
+ * this file except in compliance with the License. You may obtain a copy
+ * of the License at http://www.apache.org/licenses/LICENSE-2.0.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! fire location estimates from intersecting the camera bearing rays of fire detections by two or more sentinels.
+//! The FireTriangulator actor mirrors the SentinelConnector state and publishes a FireLocation update whenever
+//! a new fire record can be combined with recent detections of other sentinels. Fire records can arrive before
+//! their evidence images (or the image orientations), in which case we keep them and retry once those arrive
+
+use std::{sync::Arc,time::Duration};
+use chrono::{DateTime,Utc};
+use serde::{Deserialize,Serialize};
+use odin_actor::prelude::*;
+use odin_actor::tokio_kanal::{Actor,ActorHandle};
+use crate::*;
+use crate::actor::{SentinelConnectorMsg,AddInitCallback,SentinelsInitialized,SentinelJsonSnapshot,SentinelRecordUpdate,
+                   subscribe_mirror};
+use crate::pointing::{DetectionRay,detection_rays};
+use crate::position::PositionConfig;
+
+const EARTH_RADIUS: f64 = 6_371_000.0; // mean radius in meters
+const CHI2_95: f64 = 5.991; // chi-square quantile for 95% confidence with 2 degrees of freedom
+const MIN_DET: f64 = 1e-9;  // below this the (normalized) bearings are considered parallel
+
+#[derive(Deserialize,Serialize,Debug,Clone)]
+pub struct TriangulationConfig {
+    pub time_window: Duration, // max age difference of detections that are combined
+    pub min_fire_prob: f64,    // fire records below this (effective) probability are ignored
+    pub bearing_error: f64,    // standard deviation of camera azimuths in degrees
+    pub max_range: f64,        // max distance in meters between a sentinel and the estimated fire location
+    #[serde(default)]
+    pub position: PositionConfig, // how we estimate sentinel positions (ray origins) from GPS fixes
+}
+
+impl Default for TriangulationConfig {
+    fn default()->Self {
+        TriangulationConfig { time_window: Duration::from_secs(300), min_fire_prob: 0.5, bearing_error: 2.0, max_range: 20_000.0,
+                              position: PositionConfig::default() }
+    }
+}
+
+/// 95% confidence ellipse of a location estimate
+#[derive(Serialize,Deserialize,Debug,Clone,Copy,PartialEq)]
+#[serde(rename_all="camelCase")]
+pub struct ErrorEllipse {
+    pub semi_major: f64,  // meters
+    pub semi_minor: f64,  // meters
+    pub orientation: f64, // azimuth of the major axis in degrees [0,180)
+}
+
+/// the new update type we publish
+#[derive(Serialize,Deserialize,Debug,Clone)]
+#[serde(rename_all="camelCase")]
+pub struct FireLocation {
+    pub id: String,
+    pub time_recorded: DateTime<Utc>, // of the newest fire record we used
+    pub latitude: f64,
+    pub longitude: f64,
+    pub error_ellipse: ErrorEllipse,
+    pub device_ids: Vec<DeviceId>,
+    pub detections: Vec<String>, // ids of the fire records we used
+}
+
+/* #region geometry ******************************************************************************/
+
+/// local tangent plane (east,north) in meters around a reference point. This is accurate enough for sentinel ranges
+pub(crate) struct LocalFrame { lat0: f64, lon0: f64, cos_lat0: f64 }
+
+impl LocalFrame {
+    pub(crate) fn new (lat0: f64, lon0: f64)->Self { LocalFrame { lat0, lon0, cos_lat0: lat0.to_radians().cos() } }
+
+    pub(crate) fn to_local (&self, lat: f64, lon: f64)->(f64,f64) {
+        ((lon - self.lon0).to_radians() * EARTH_RADIUS * self.cos_lat0, (lat - self.lat0).to_radians() * EARTH_RADIUS)
+    }
+
+    pub(crate) fn to_geo (&self, x: f64, y: f64)->(f64,f64) {
+        (self.lat0 + (y / EARTH_RADIUS).to_degrees(), self.lon0 + (x / (EARTH_RADIUS * self.cos_lat0)).to_degrees())
+    }
+}
+
+/// weighted least squares intersection of bearing lines. Each line is given by its origin and a unit direction vector.
+/// Returns the solution and its (unscaled) information matrix (a, b, c) as in [[a,b],[b,c]]
+pub(crate) fn intersect_lines (lines: &[((f64,f64),(f64,f64))], weights: &[f64])->Option<((f64,f64),(f64,f64,f64))> {
+    let (mut a, mut b, mut c, mut bx, mut by) = (0.0, 0.0, 0.0, 0.0, 0.0);
+    for (((px,py),(dx,dy)),w) in lines.iter().zip( weights) {
+        let (nx, ny) = (*dy, -*dx); // line normal
+        let np = nx*px + ny*py;
+        a += w*nx*nx; b += w*nx*ny; c += w*ny*ny;
+        bx += w*nx*np; by += w*ny*np;
+    }
+
+    let det = a*c - b*b;
+    let scale = (a*a + 2.0*b*b + c*c).sqrt();
+    if scale == 0.0 || det / (scale*scale) < MIN_DET { return None }
+
+    let x = (c*bx - b*by) / det;
+    let y = (a*by - b*bx) / det;
+    Some( ((x,y), (a,b,c)) )
+}
+
+/// confidence ellipse for the covariance matrix that is the inverse of the information matrix (a,b,c)
+pub(crate) fn error_ellipse (a: f64, b: f64, c: f64)->ErrorEllipse {
+    let det = a*c - b*b;
+    let (sxx, sxy, syy) = (c/det, -b/det, a/det); // covariance
+
+    let mean = (sxx + syy) / 2.0;
+    let d = (((sxx - syy) / 2.0).powi(2) + sxy*sxy).sqrt();
+    let (l1, l2) = (mean + d, (mean - d).max(0.0));
+
+    let theta = 0.5 * (2.0*sxy).atan2( sxx - syy); // angle of major axis from east, counter clockwise
+    let orientation = (90.0 - theta.to_degrees()).rem_euclid( 180.0);
+
+    ErrorEllipse { semi_major: (l1 * CHI2_95).sqrt(), semi_minor: (l2 * CHI2_95).sqrt(), orientation }
+}
+
+/// estimate the intersection of rays (which should all come from different sentinels). Returns the (lat,lon) of the
+/// intersection and its error ellipse, or None if the rays are (nearly) parallel, the intersection is behind one of
+/// the cameras or too far away
+pub fn intersect_rays (rays: &[DetectionRay], bearing_error: f64, max_range: f64)->Option<(f64,f64,ErrorEllipse)> {
+    if rays.len() < 2 { return None }
+
+    let frame = LocalFrame::new( rays[0].origin.0, rays[0].origin.1);
+    let lines: Vec<((f64,f64),(f64,f64))> = rays.iter().map( |r| {
+        let az = r.pointing.azimuth.to_radians();
+        (frame.to_local( r.origin.0, r.origin.1), (az.sin(), az.cos()))
+    }).collect();
+
+    // first pass is unweighted, then we weight by the inverse cross-range variance at the estimated distance
+    let (p, _) = intersect_lines( &lines, &vec![1.0; lines.len()])?;
+    let sigma = bearing_error.max( 0.01).to_radians();
+    let weights: Vec<f64> = lines.iter().map( |((px,py),_)| {
+        let r = (p.0 - px).hypot( p.1 - py).max( 1.0);
+        1.0 / (sigma * r).powi(2)
+    }).collect();
+    let ((x,y), (a,b,c)) = intersect_lines( &lines, &weights)?;
+
+    for ((px,py),(dx,dy)) in &lines {
+        let (vx, vy) = (x - px, y - py);
+        if vx*dx + vy*dy <= 0.0 || vx.hypot( vy) > max_range { return None }
+    }
+
+    let (lat, lon) = frame.to_geo( x, y);
+    Some( (lat, lon, error_ellipse( a, b, c)) )
+}
+
+/* #endregion geometry */
+
+/// estimate the location of the fire reported by the given (newest) fire record, combining it with the newest
+/// qualifying fire detection of each other sentinel within the configured time window
+pub fn triangulate (config: &TriangulationConfig, store: &SentinelStore, device_id: &DeviceId, record_id: &str)->Option<FireLocation> {
+    let sentinel = store.get( device_id)?;
+    let fire = sentinel.records::<FireData>().iter().find( |r| r.id == record_id)?;
+    if fire.data.confidence() < config.min_fire_prob { return None }
+
+    let window = chrono::Duration::from_std( config.time_window).unwrap_or( chrono::Duration::minutes(5));
+    let mut rays: Vec<DetectionRay> = detection_rays( sentinel, &fire.id, &fire.evidences, &config.position, config.max_range).into_iter().take(1).collect();
+    if rays.is_empty() { return None }
+
+    // ray times are image times, the location time is the newest fire record time
+    let mut time_recorded = fire.time_recorded;
+    for other in store.values() {
+        if other.device_id == *device_id { continue }
+
+        let detection = other.records::<FireData>().iter()
+            .filter( |r| r.data.confidence() >= config.min_fire_prob && (r.time_recorded - fire.time_recorded).abs() <= window)
+            .find_map( |r| detection_rays( other, &r.id, &r.evidences, &config.position, config.max_range).into_iter().next().map( |ray| (r.time_recorded, ray)));
+
+        if let Some((t, ray)) = detection {
+            if t > time_recorded { time_recorded = t }
+            rays.push( ray);
+        }
+    }
+
+    let (latitude, longitude, error_ellipse) = intersect_rays( &rays, config.bearing_error, config.max_range)?;
+    Some( FireLocation {
+        id: format!("loc-{}", fire.id),
+        time_recorded,
+        latitude, longitude, error_ellipse,
+        device_ids: rays.iter().map( |r| r.device_id.clone()).collect(),
+        detections: rays.iter().map( |r| r.record_id.clone()).collect(),
+    })
+}
+
+/* #region triangulator actor ********************************************************************/
+
+#[derive(Debug)] pub struct AddFireLocationCallback { pub id: String, pub action: Callback<Arc<FireLocation>> }
+
+define_actor_msg_type! { pub FireTriangulatorMsg = AddFireLocationCallback | SentinelsInitialized | SentinelJsonSnapshot | SentinelRecordUpdate }
+
+/// a qualifying fire record that has no ray yet
+struct PendingFire { device_id: DeviceId, record_id: String, time_recorded: DateTime<Utc> }
+
+pub struct FireTriangulator {
+    config: TriangulationConfig,
+    hconn: ActorHandle<SentinelConnectorMsg>,
+    sentinels: SentinelStore, // our mirror of the connector state
+    pending: Vec<PendingFire>, // fire records that wait for their evidence images or image orientations
+    location_callbacks: CallbackList<Arc<FireLocation>>,
+}
+
+impl FireTriangulator {
+    pub fn new (config: TriangulationConfig, hconn: ActorHandle<SentinelConnectorMsg>)->Self {
+        FireTriangulator { config, hconn, sentinels: SentinelStore::new(), pending: Vec::new(), location_callbacks: CallbackList::new() }
+    }
+
+    /// triangulate a fire record, or remember it for later if it does not have a ray yet. Returns true if the record
+    /// is still pending
+    async fn locate (&mut self, device_id: &DeviceId, record_id: &str)->bool {
+        let Some(sentinel) = self.sentinels.get( device_id) else { return false };
+        let Some(fire) = sentinel.records::<FireData>().iter().find( |r| r.id == record_id) else { return false };
+        if fire.data.confidence() < self.config.min_fire_prob { return false }
+
+        if detection_rays( sentinel, &fire.id, &fire.evidences, &self.config.position, self.config.max_range).is_empty() {
+            return true
+        }
+        if let Some(location) = triangulate( &self.config, &self.sentinels, device_id, record_id) {
+            self.location_callbacks.trigger( Arc::new(location)).await;
+        }
+        false
+    }
+
+    async fn update (&mut self, update: &SentinelUpdate)->Result<()> {
+        let (device_id, capability) = self.sentinels.add_update( update.clone());
+
+        match capability {
+            // the update is not necessarily the newest fire record of its device (e.g. when merging after a reconnect)
+            SensorCapability::Fire => {
+                if self.locate( &device_id, update.record_id()).await {
+                    let record_id = update.record_id().to_string();
+                    self.pending.push( PendingFire { device_id, record_id, time_recorded: update.time_recorded() });
+                }
+            }
+            // this might complete the rays of pending fire records of the device. Records that are older than the
+            // time window are dropped since they could not be combined with new detections anymore
+            SensorCapability::Image | SensorCapability::Orientation => {
+                let window = chrono::Duration::from_std( self.config.time_window).unwrap_or( chrono::Duration::minutes(5));
+                let cutoff = update.time_recorded() - window;
+                let (retry, pending): (Vec<PendingFire>,Vec<PendingFire>) = std::mem::take( &mut self.pending).into_iter()
+                    .filter( |p| p.device_id != device_id || p.time_recorded >= cutoff)
+                    .partition( |p| p.device_id == device_id);
+                self.pending = pending;
+
+                for p in retry {
+                    if self.locate( &p.device_id, &p.record_id).await {
+                        self.pending.push( p);
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+impl_actor! { match msg for Actor<FireTriangulator,FireTriangulatorMsg> as
+    _Start_ => cont! {
+        let id = self.id().to_string();
+        let hself = &self.hself;
+        self.hconn.send_msg( AddInitCallback{id, action: msg_callback!(hself, SentinelsInitialized)}).await.ok();
+    }
+    AddFireLocationCallback => cont! {
+        self.location_callbacks.add( msg.id, msg.action)
+    }
+    SentinelsInitialized => cont! {
+        subscribe_mirror!( self.hconn, &self.hself, self.id().to_string());
+    }
+    SentinelJsonSnapshot => cont! {
+        if let Some(sentinels) = msg.sentinels() { self.sentinels = sentinels }
+    }
+    SentinelRecordUpdate => cont! {
+        if let Err(e) = self.update( &msg.0).await {
+            eprintln!("@@ failed to process sentinel update: {:?}", e);
+        }
+    }
+}
+
+/* #endregion triangulator actor */
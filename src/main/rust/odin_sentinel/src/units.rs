@@ -0,0 +1,152 @@
+/*
+ * Copyright (c) 2024, United States Government, as represented by the
+ * Administrator of the National Aeronautics and Space Administration.
+ * All rights reserved.
+ *
+ * The RACE - Runtime for Airspace Concept Evaluation platform is licensed
+ * under the Apache License, Version 2.0 (the "License"); you may not use
+ * this file except in compliance with the License. You may obtain a copy
+ * of the License at http://www.apache.org/licenses/LICENSE-2.0.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! user selectable units for the uom quantities of sensor records. Record JSON holds SI values, which are converted
+//! for CSV export and aggregation. This module also knows which payload fields are quantities or directions
+
+use strum::EnumString;
+use uom::si::f64::{Velocity,ThermodynamicTemperature,ElectricCurrent,ElectricPotential};
+use uom::si::{thermodynamic_temperature,velocity,electric_potential,electric_current};
+use crate::SensorCapability;
+
+#[derive(Debug,Clone,Copy,PartialEq,EnumString)]
+pub enum TemperatureUnit {
+    #[strum(serialize="K", serialize="kelvin")] Kelvin,
+    #[strum(serialize="C", serialize="celsius")] Celsius,
+    #[strum(serialize="F", serialize="fahrenheit")] Fahrenheit,
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,EnumString)]
+pub enum SpeedUnit {
+    #[strum(serialize="m/s", serialize="mps")] MetersPerSecond,
+    #[strum(serialize="km/h", serialize="kmh")] KilometersPerHour,
+    #[strum(serialize="mph")] MilesPerHour,
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,EnumString)]
+pub enum PotentialUnit {
+    #[strum(serialize="V")] Volt,
+    #[strum(serialize="mV")] Millivolt,
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,EnumString)]
+pub enum CurrentUnit {
+    #[strum(serialize="A")] Ampere,
+    #[strum(serialize="mA")] Milliampere,
+}
+
+/// the units to use for quantity values of record fields
+#[derive(Debug,Clone)]
+pub struct Units {
+    pub temperature: TemperatureUnit,
+    pub speed: SpeedUnit,
+    pub potential: PotentialUnit,
+    pub current: CurrentUnit,
+}
+impl Default for Units {
+    fn default()->Self {
+        Units {
+            temperature: TemperatureUnit::Celsius,
+            speed: SpeedUnit::MetersPerSecond,
+            potential: PotentialUnit::Volt,
+            current: CurrentUnit::Ampere
+        }
+    }
+}
+
+/// the kinds of uom quantities we have in sensor records. Their JSON values are SI floats
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub(crate) enum QuantityKind { Temperature, Speed, Potential, Current }
+
+/// the quantity kind of a record payload field (see the uom fields of the respective *Data structs)
+pub(crate) fn quantity_kind (capability: SensorCapability, field: &str)->Option<QuantityKind> {
+    use SensorCapability::*;
+    match capability {
+        Thermometer if field == "temperature" => Some(QuantityKind::Temperature),
+        Anemometer if field == "speed" => Some(QuantityKind::Speed),
+        Power if field.ends_with("Temp") => Some(QuantityKind::Temperature),
+        Power if field.ends_with("Voltage") => Some(QuantityKind::Potential),
+        Power if field.ends_with("Current") => Some(QuantityKind::Current),
+        _ => None
+    }
+}
+
+/// is this record payload field a compass direction in degrees, i.e. a value that has to be averaged on the circle
+pub(crate) fn is_direction (capability: SensorCapability, field: &str)->bool {
+    capability == SensorCapability::Anemometer && field == "angle"
+}
+
+impl Units {
+    pub(crate) fn symbol (&self, kind: QuantityKind)->&'static str {
+        match kind {
+            QuantityKind::Temperature => match self.temperature {
+                TemperatureUnit::Kelvin => "K",
+                TemperatureUnit::Celsius => "°C",
+                TemperatureUnit::Fahrenheit => "°F",
+            }
+            QuantityKind::Speed => match self.speed {
+                SpeedUnit::MetersPerSecond => "m/s",
+                SpeedUnit::KilometersPerHour => "km/h",
+                SpeedUnit::MilesPerHour => "mph",
+            }
+            QuantityKind::Potential => match self.potential {
+                PotentialUnit::Volt => "V",
+                PotentialUnit::Millivolt => "mV",
+            }
+            QuantityKind::Current => match self.current {
+                CurrentUnit::Ampere => "A",
+                CurrentUnit::Milliampere => "mA",
+            }
+        }
+    }
+
+    /// convert a SI value into our selected unit
+    pub(crate) fn convert (&self, kind: QuantityKind, v: f64)->f64 {
+        match kind {
+            QuantityKind::Temperature => {
+                let t = ThermodynamicTemperature::new::<thermodynamic_temperature::kelvin>(v);
+                match self.temperature {
+                    TemperatureUnit::Kelvin => t.get::<thermodynamic_temperature::kelvin>(),
+                    TemperatureUnit::Celsius => t.get::<thermodynamic_temperature::degree_celsius>(),
+                    TemperatureUnit::Fahrenheit => t.get::<thermodynamic_temperature::degree_fahrenheit>(),
+                }
+            }
+            QuantityKind::Speed => {
+                let s = Velocity::new::<velocity::meter_per_second>(v);
+                match self.speed {
+                    SpeedUnit::MetersPerSecond => s.get::<velocity::meter_per_second>(),
+                    SpeedUnit::KilometersPerHour => s.get::<velocity::kilometer_per_hour>(),
+                    SpeedUnit::MilesPerHour => s.get::<velocity::mile_per_hour>(),
+                }
+            }
+            QuantityKind::Potential => {
+                let p = ElectricPotential::new::<electric_potential::volt>(v);
+                match self.potential {
+                    PotentialUnit::Volt => p.get::<electric_potential::volt>(),
+                    PotentialUnit::Millivolt => p.get::<electric_potential::millivolt>(),
+                }
+            }
+            QuantityKind::Current => {
+                let c = ElectricCurrent::new::<electric_current::ampere>(v);
+                match self.current {
+                    CurrentUnit::Ampere => c.get::<electric_current::ampere>(),
+                    CurrentUnit::Milliampere => c.get::<electric_current::milliampere>(),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,334 @@
+/*
+ * Copyright (c) 2024, United States Government, as represented by the
+ * Administrator of the National Aeronautics and Space Administration.
+ * All rights reserved.
+ *
+ * The RACE - Runtime for Airspace Concept Evaluation platform is licensed
+ * under the Apache License, Version 2.0 (the "License"); you may not use
+ * this file except in compliance with the License. You may obtain a copy
+ * of the License at http://www.apache.org/licenses/LICENSE-2.0.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! rolling wind statistics from anemometer records and smoke source sector estimation. Anemometer angles are
+//! interpreted in meteorological convention, i.e. as the direction (clockwise from true north) the wind is coming
+//! from. Smoke drifts downwind, hence the source of smoke detected by a sentinel is in its upwind sector. If several
+//! sentinels detect smoke at the same time we intersect their back-projected upwind bearings to locate the source.
+//! The WindAnalyzer actor mirrors the SentinelConnector state and publishes WindStats for each new anemometer record
+//! and a SmokeSource estimate for each new smoke record
+
+use std::{ops::Bound,sync::Arc,time::Duration};
+use chrono::{DateTime,Utc};
+use serde::{Deserialize,Serialize};
+use uom::si::velocity::meter_per_second;
+use odin_actor::prelude::*;
+use odin_actor::tokio_kanal::{Actor,ActorHandle};
+use crate::*;
+use crate::actor::{SentinelConnectorMsg,AddInitCallback,SentinelsInitialized,SentinelJsonSnapshot,SentinelRecordUpdate,
+                   subscribe_mirror};
+use crate::pointing::{distance,detection_rays};
+use crate::position::PositionConfig;
+use crate::triangulation::{ErrorEllipse,LocalFrame,intersect_lines,error_ellipse};
+
+#[derive(Deserialize,Serialize,Debug,Clone)]
+pub struct WindConfig {
+    pub windows: Vec<Duration>,  // the windows we compute rolling statistics for
+    pub source_window: Duration, // the window used for smoke source estimation
+    pub min_smoke_prob: f64,     // smoke records below this (effective) probability are ignored
+    pub min_sector_width: f64,   // minimum width of source sectors in degrees
+    pub max_wind_distance: f64,  // max distance in meters of another sentinel whose wind we use if the detector has none
+    #[serde(default)]
+    pub position: PositionConfig, // how we estimate sentinel positions from GPS fixes
+}
+
+impl Default for WindConfig {
+    fn default()->Self {
+        WindConfig {
+            windows: vec![ Duration::from_secs(600), Duration::from_secs(3600) ],
+            source_window: Duration::from_secs(600),
+            min_smoke_prob: 0.5,
+            min_sector_width: 30.0,
+            max_wind_distance: 10_000.0,
+            position: PositionConfig::default(),
+        }
+    }
+}
+
+#[derive(Serialize,Deserialize,Debug,Clone,PartialEq)]
+#[serde(rename_all="camelCase")]
+pub struct WindStats {
+    pub device_id: DeviceId,
+    pub window: Duration,
+    pub time_recorded: DateTime<Utc>, // of the newest sample
+    pub samples: usize,
+    pub direction: f64,         // vector (speed weighted) averaged direction in degrees [0,360)
+    pub mean_speed: f64,        // scalar mean speed in m/s
+    pub vector_mean_speed: f64, // magnitude of the mean wind vector in m/s
+    pub gust_speed: f64,        // max speed in m/s
+    pub direction_std: f64,     // circular standard deviation of directions in degrees
+}
+
+/// compute wind statistics for all anemometer records of a sentinel within `window` before (and including) `end`
+pub fn wind_stats (sentinel: &Sentinel, end: DateTime<Utc>, window: Duration)->Option<WindStats> {
+    let start = end - chrono::Duration::from_std( window).ok()?;
+    let samples = sentinel.records_in::<AnemometerData>( (Bound::Excluded(start), Bound::Included(end)));
+    if samples.is_empty() { return None }
+
+    let n = samples.len() as f64;
+    let (mut u, mut v, mut su, mut sv, mut sum_speed, mut gust_speed) = (0.0, 0.0, 0.0, 0.0, 0.0, 0.0f64);
+    for rec in &samples {
+        let theta = rec.data.angle.degrees().to_radians();
+        let speed = rec.data.speed.get::<meter_per_second>();
+        u += speed * theta.sin();  v += speed * theta.cos();
+        su += theta.sin();  sv += theta.cos();
+        sum_speed += speed;
+        gust_speed = gust_speed.max( speed);
+    }
+
+    // fall back to the unweighted direction if it was calm
+    let direction = if u == 0.0 && v == 0.0 { su.atan2( sv) } else { u.atan2( v) }.to_degrees().rem_euclid( 360.0);
+    let resultant = (su.hypot( sv) / n).clamp( f64::MIN_POSITIVE, 1.0);
+    let direction_std = (-2.0 * resultant.ln()).sqrt().to_degrees();
+
+    Some( WindStats {
+        device_id: sentinel.device_id.clone(),
+        window,
+        time_recorded: samples.iter().map( |r| r.time_recorded).max()?,
+        samples: samples.len(),
+        direction,
+        mean_speed: sum_speed / n,
+        vector_mean_speed: u.hypot( v) / n,
+        gust_speed,
+        direction_std,
+    })
+}
+
+impl Sentinel {
+    /// wind statistics for the given window up to our newest anemometer record
+    pub fn wind_stats (&self, window: Duration)->Option<WindStats> {
+        wind_stats( self, self.latest::<AnemometerData>()?.time_recorded, window)
+    }
+}
+
+/// a bearing sector in degrees, the center is clockwise from true north
+#[derive(Serialize,Deserialize,Debug,Clone,Copy,PartialEq)]
+#[serde(rename_all="camelCase")]
+pub struct BearingSector {
+    pub center: f64,
+    pub half_width: f64,
+}
+
+impl BearingSector {
+    pub fn contains (&self, bearing: f64)->bool {
+        let d = (bearing - self.center + 540.0).rem_euclid( 360.0) - 180.0;
+        d.abs() <= self.half_width
+    }
+}
+
+/// the upwind sector of a smoke detection, back-projected from the position of the detecting sentinel
+#[derive(Serialize,Deserialize,Debug,Clone)]
+#[serde(rename_all="camelCase")]
+pub struct SmokeBearing {
+    pub device_id: DeviceId,
+    pub detection_id: String,
+    pub smoke_prob: f64,       // effective (cloud cover adjusted) probability
+    pub origin: (f64,f64),     // (lat,lon) of the detecting sentinel
+    pub sector: BearingSector,
+}
+
+/// the analysis update we publish for smoke detections
+#[derive(Serialize,Deserialize,Debug,Clone)]
+#[serde(rename_all="camelCase")]
+pub struct SmokeSource {
+    pub id: String,
+    pub device_id: DeviceId,
+    pub detection_id: String,
+    pub time_recorded: DateTime<Utc>,
+    pub smoke_prob: f64,                // effective (cloud cover adjusted) probability
+    pub origin: Option<(f64,f64)>,      // (lat,lon) of the detecting sentinel if known
+    pub sector: BearingSector,          // upwind sector the smoke is estimated to come from
+    pub camera_azimuth: Option<f64>,    // bearing of the first evidence image if we know its orientation
+    pub wind: WindStats,                // the wind statistics the sector is based on (might be from another sentinel)
+    pub bearings: Vec<SmokeBearing>,    // bearings of all sentinels with a known position that detected smoke within the source window
+    pub location: Option<(f64,f64)>,    // (lat,lon) of the weighted bearing intersection if at least two sentinels detected smoke
+    pub error_ellipse: Option<ErrorEllipse>, // of the location
+}
+
+/// the wind we use for a sentinel - its own, or that of the nearest sentinel with anemometer data within max_wind_distance
+fn wind_for (config: &WindConfig, store: &SentinelStore, sentinel: &Sentinel, t: DateTime<Utc>)->Option<WindStats> {
+    if let Some(stats) = wind_stats( sentinel, t, config.source_window) { return Some(stats) }
+
+    let pos = sentinel.position_at( &config.position, t)?;
+    let (lat, lon) = (pos.latitude, pos.longitude);
+
+    store.values().into_iter()
+        .filter( |other| other.device_id != sentinel.device_id)
+        .filter_map( |other| {
+            let p = other.position_at( &config.position, t)?;
+            let dist = distance( lat, lon, p.latitude, p.longitude);
+            if dist > config.max_wind_distance { return None }
+            wind_stats( other, t, config.source_window).map( |stats| (dist, stats))
+        })
+        .min_by( |a,b| a.0.total_cmp( &b.0))
+        .map( |(_,stats)| stats)
+}
+
+fn upwind_sector (config: &WindConfig, wind: &WindStats)->BearingSector {
+    let half_width = (2.0 * wind.direction_std).max( config.min_sector_width / 2.0).min( 180.0);
+    BearingSector { center: wind.direction, half_width }
+}
+
+/// the bearing of the newest smoke detection of another sentinel within the source window around `t`
+fn other_bearing (config: &WindConfig, store: &SentinelStore, sentinel: &Sentinel, t: DateTime<Utc>)->Option<SmokeBearing> {
+    let window = chrono::Duration::from_std( config.source_window).ok()?;
+    let smoke = sentinel.records::<SmokeData>().iter()
+        .find( |r| r.data.confidence() >= config.min_smoke_prob && (r.time_recorded - t).abs() <= window)?;
+
+    let pos = sentinel.position_at( &config.position, smoke.time_recorded)?;
+    let wind = wind_for( config, store, sentinel, smoke.time_recorded)?;
+    Some( SmokeBearing {
+        device_id: sentinel.device_id.clone(),
+        detection_id: smoke.id.clone(),
+        smoke_prob: smoke.data.confidence(),
+        origin: (pos.latitude, pos.longitude),
+        sector: upwind_sector( config, &wind),
+    })
+}
+
+/// weighted least squares intersection of back-projected bearings. Weights are the smoke probabilities over the cross-range
+/// variance (from the sector widths) at the estimated distance. Returns None if the bearings are (nearly) parallel or the
+/// intersection is downwind of one of the sentinels
+fn intersect_bearings (bearings: &[SmokeBearing])->Option<((f64,f64),ErrorEllipse)> {
+    if bearings.len() < 2 { return None }
+
+    let frame = LocalFrame::new( bearings[0].origin.0, bearings[0].origin.1);
+    let lines: Vec<((f64,f64),(f64,f64))> = bearings.iter().map( |b| {
+        let az = b.sector.center.to_radians();
+        (frame.to_local( b.origin.0, b.origin.1), (az.sin(), az.cos()))
+    }).collect();
+
+    let (p, _) = intersect_lines( &lines, &vec![1.0; lines.len()])?;
+    let weights: Vec<f64> = lines.iter().zip( bearings).map( |(((px,py),_),b)| {
+        let r = (p.0 - px).hypot( p.1 - py).max( 1.0);
+        let sigma = b.sector.half_width.max( 1.0).to_radians();
+        b.smoke_prob / (sigma * r).powi(2)
+    }).collect();
+    let ((x,y), (a,b,c)) = intersect_lines( &lines, &weights)?;
+
+    for ((px,py),(dx,dy)) in &lines {
+        if (x - px)*dx + (y - py)*dy <= 0.0 { return None }
+    }
+    Some( (frame.to_geo( x, y), error_ellipse( a, b, c)) )
+}
+
+/// estimate the upwind source sector of a smoke record, or None if it is below threshold or we have no suitable wind data.
+/// Smoke detections of other sentinels within the source window are combined into a source location estimate
+pub fn smoke_source (config: &WindConfig, store: &SentinelStore, device_id: &DeviceId, record_id: &str)->Option<SmokeSource> {
+    let sentinel = store.get( device_id)?;
+    let smoke = sentinel.records::<SmokeData>().iter().find( |r| r.id == record_id)?;
+    if smoke.data.confidence() < config.min_smoke_prob { return None }
+
+    let wind = wind_for( config, store, sentinel, smoke.time_recorded)?;
+    let sector = upwind_sector( config, &wind);
+
+    let origin = sentinel.position_at( &config.position, smoke.time_recorded).map( |p| (p.latitude, p.longitude));
+    let camera_azimuth = detection_rays( sentinel, &smoke.id, &smoke.evidences, &config.position, 0.0).first().map( |r| r.pointing.azimuth);
+
+    let mut bearings: Vec<SmokeBearing> = origin.map( |origin| SmokeBearing {
+        device_id: device_id.clone(), detection_id: smoke.id.clone(), smoke_prob: smoke.data.confidence(), origin, sector
+    }).into_iter().collect();
+    for other in store.values() {
+        if other.device_id != *device_id {
+            bearings.extend( other_bearing( config, store, other, smoke.time_recorded));
+        }
+    }
+    let (location, error_ellipse) = match intersect_bearings( &bearings) {
+        Some((location,ellipse)) => (Some(location), Some(ellipse)),
+        None => (None, None)
+    };
+
+    Some( SmokeSource {
+        id: format!("src-{}", smoke.id),
+        device_id: device_id.clone(),
+        detection_id: smoke.id.clone(),
+        time_recorded: smoke.time_recorded,
+        smoke_prob: smoke.data.confidence(),
+        origin, sector, camera_azimuth, wind,
+        bearings, location, error_ellipse
+    })
+}
+
+/* #region wind analyzer actor *******************************************************************/
+
+#[derive(Debug)] pub struct AddWindStatsCallback { pub id: String, pub action: Callback<Arc<WindStats>> }
+
+#[derive(Debug)] pub struct AddSmokeSourceCallback { pub id: String, pub action: Callback<Arc<SmokeSource>> }
+
+define_actor_msg_type! { pub WindAnalyzerMsg = AddWindStatsCallback | AddSmokeSourceCallback | SentinelsInitialized | SentinelJsonSnapshot | SentinelRecordUpdate }
+
+pub struct WindAnalyzer {
+    config: WindConfig,
+    hconn: ActorHandle<SentinelConnectorMsg>,
+    sentinels: SentinelStore, // our mirror of the connector state
+    wind_callbacks: CallbackList<Arc<WindStats>>,
+    source_callbacks: CallbackList<Arc<SmokeSource>>,
+}
+
+impl WindAnalyzer {
+    pub fn new (config: WindConfig, hconn: ActorHandle<SentinelConnectorMsg>)->Self {
+        WindAnalyzer { config, hconn, sentinels: SentinelStore::new(), wind_callbacks: CallbackList::new(), source_callbacks: CallbackList::new() }
+    }
+
+    async fn update (&mut self, update: &SentinelUpdate)->Result<()> {
+        let (device_id, capability) = self.sentinels.add_update( update.clone());
+        let sentinel = self.sentinels.get( &device_id).ok_or( OdinSentinelError::NoSuchDeviceError( device_id.clone()))?;
+
+        match capability {
+            SensorCapability::Anemometer => {
+                let stats: Vec<WindStats> = self.config.windows.iter().filter_map( |w| sentinel.wind_stats( *w)).collect();
+                for s in stats {
+                    self.wind_callbacks.trigger( Arc::new(s)).await;
+                }
+            }
+            SensorCapability::Smoke => {
+                if let Some(source) = smoke_source( &self.config, &self.sentinels, &device_id, update.record_id()) {
+                    self.source_callbacks.trigger( Arc::new(source)).await;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+impl_actor! { match msg for Actor<WindAnalyzer,WindAnalyzerMsg> as
+    _Start_ => cont! {
+        let id = self.id().to_string();
+        let hself = &self.hself;
+        self.hconn.send_msg( AddInitCallback{id, action: msg_callback!(hself, SentinelsInitialized)}).await.ok();
+    }
+    AddWindStatsCallback => cont! {
+        self.wind_callbacks.add( msg.id, msg.action)
+    }
+    AddSmokeSourceCallback => cont! {
+        self.source_callbacks.add( msg.id, msg.action)
+    }
+    SentinelsInitialized => cont! {
+        subscribe_mirror!( self.hconn, &self.hself, self.id().to_string());
+    }
+    SentinelJsonSnapshot => cont! {
+        if let Some(sentinels) = msg.sentinels() { self.sentinels = sentinels }
+    }
+    SentinelRecordUpdate => cont! {
+        if let Err(e) = self.update( &msg.0).await {
+            eprintln!("@@ failed to process sentinel update: {:?}", e);
+        }
+    }
+}
+
+/* #endregion wind analyzer actor */
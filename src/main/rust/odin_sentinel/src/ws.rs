@@ -50,7 +50,7 @@ pub async fn init_websocket (config: Arc<SentinelConfig>, device_ids: Vec<String
     Ok(ws_stream)
 }
 
-pub async fn run_websocket (hself: ActorHandle<SentinelConnectorMsg>, config: Arc<SentinelConfig>, mut ws_read: SplitStream<WsStream>)->Result<()> {
+pub async fn run_websocket (hself: ActorHandle<SentinelConnectorMsg>, source_id: SourceId, config: Arc<SentinelConfig>, mut ws_read: SplitStream<WsStream>)->Result<()> {
     let http_client = reqwest::Client::new();
     loop {
         match ws_read.next().await {
@@ -61,7 +61,10 @@ pub async fn run_websocket (hself: ActorHandle<SentinelConnectorMsg>, config: Ar
                             Ok(msg) => {
                                 match msg {
                                     WsMsg::Record { device_id, sensor_no, rec_type } => {
-                                        get_and_send_record( &hself, &http_client, config.base_uri.as_str(), config.access_token.as_str(), 
+                                        if !config.device_filter.accepts_device( &device_id) || !config.device_filter.accepts_capability( rec_type) {
+                                            continue // the server might send records we did not ask for
+                                        }
+                                        get_and_send_record( &hself, &http_client, source_id.as_str(), config.base_uri.as_str(), config.access_token.as_str(), 
                                                             device_id.as_str(), sensor_no, rec_type).await?;
                                     }
                                     WsMsg::Pong { request_time, response_time, message_id } => {}
@@ -86,7 +89,7 @@ pub async fn run_websocket (hself: ActorHandle<SentinelConnectorMsg>, config: Ar
                 }
             }
             None => { // stream closed by server
-                hself.send_msg( OdinSentinelError::WsClosedError{}).await;
+                hself.send_msg( SourceClosed(source_id)).await;
                 return Err(OdinSentinelError::WsClosedError{})
             }
         }
@@ -137,6 +140,20 @@ pub async fn request_join (ws: &mut WsStream, device_ids: Vec<String>, message_i
     Ok(ws.send( Message::Text(json)).await?)
 }
 
+/// join additional devices on an already open websocket. The response is received by the websocket task
+pub async fn send_join (tx: &mut SplitSink<WsStream,Message>, device_ids: Vec<String>, message_id: String)->Result<()> {
+    let msg = WsMsg::Join{device_ids, message_id};
+    let json = serde_json::to_string(&msg)?;
+    send_ws_text_msg( tx, json).await
+}
+
+/// stop receiving records for devices on an open websocket (e.g. because they were retired)
+pub async fn send_leave (tx: &mut SplitSink<WsStream,Message>, device_ids: Vec<String>, message_id: String)->Result<()> {
+    let msg = WsMsg::Leave{device_ids, message_id};
+    let json = serde_json::to_string(&msg)?;
+    send_ws_text_msg( tx, json).await
+}
+
 pub async fn expect_join_response (ws: &mut WsStream)->Result<()> {
     let resp = read_next_ws_msg(ws).await?;
     if let WsMsg::Join{device_ids,message_id} = resp  {
@@ -160,34 +177,21 @@ pub async fn read_next_ws_msg (ws: &mut WsStream)->Result<WsMsg> {
     Ok(msg)
 }
 
-pub async fn get_and_send_record (hself: &ActorHandle<SentinelConnectorMsg>, client: &Client, base_uri: &str, access_token: &str, 
+/// get the latest record from the server and send it with a namespaced device_id to the connector
+pub async fn get_and_send_record (hself: &ActorHandle<SentinelConnectorMsg>, client: &Client, source_id: &str, base_uri: &str, access_token: &str, 
                                   device_id: &str, sensor_no: u32, capability: SensorCapability) -> Result<()> 
 {
-    use SensorCapability::*;
-    match capability {
-        Accelerometer => Ok(hself.send_msg( get_latest_record::<AccelerometerData>(client, base_uri, access_token, device_id, sensor_no).await?).await?),
-        Anemometer    => Ok(hself.send_msg( get_latest_record::<AnemometerData>(client, base_uri, access_token, device_id, sensor_no).await?).await?),
-        Cloudcover    => Ok(hself.send_msg( get_latest_record::<CloudcoverData>(client, base_uri, access_token, device_id, sensor_no).await?).await?),
-        Fire          => Ok(hself.send_msg( get_latest_record::<FireData>(client, base_uri, access_token, device_id, sensor_no).await?).await?),
-        Gas           => Ok(hself.send_msg( get_latest_record::<GasData>(client, base_uri, access_token, device_id, sensor_no).await?).await?),
-        Gps           => Ok(hself.send_msg( get_latest_record::<GpsData>(client, base_uri, access_token, device_id, sensor_no).await?).await?),
-        Gyroscope     => Ok(hself.send_msg( get_latest_record::<GyroscopeData>(client, base_uri, access_token, device_id, sensor_no).await?).await?),
-        Image         => Ok(hself.send_msg( get_latest_record::<ImageData>(client, base_uri, access_token, device_id, sensor_no).await?).await?),
-        Magnetometer  => Ok(hself.send_msg( get_latest_record::<MagnetometerData>(client, base_uri, access_token, device_id, sensor_no).await?).await?),
-        Orientation   => Ok(hself.send_msg( get_latest_record::<OrientationData>(client, base_uri, access_token, device_id, sensor_no).await?).await?),
-        Person        => Ok(hself.send_msg( get_latest_record::<PersonData>(client, base_uri, access_token, device_id, sensor_no).await?).await?),
-        Power         => Ok(hself.send_msg( get_latest_record::<PowerData>(client, base_uri, access_token, device_id, sensor_no).await?).await?),
-        Smoke         => Ok(hself.send_msg( get_latest_record::<SmokeData>(client, base_uri, access_token, device_id, sensor_no).await?).await?),
-        Thermometer   => Ok(hself.send_msg( get_latest_record::<ThermometerData>(client, base_uri, access_token, device_id, sensor_no).await?).await?),
-        Valve         => Ok(hself.send_msg( get_latest_record::<ValveData>(client, base_uri, access_token, device_id, sensor_no).await?).await?),
-        Voc           => Ok(hself.send_msg( get_latest_record::<VocData>(client, base_uri, access_token, device_id, sensor_no).await?).await?),
-    }
+    let mut update = get_latest_update( client, base_uri, access_token, device_id, sensor_no, capability).await?;
+    let device_id = namespaced_device_id( source_id, update.device_id());
+    update.set_device_id( &device_id);
+    Ok(hself.send_msg( update).await?)
 }
 
 /* #region websocket messages ***********************************************************************/
 
 // in:      {"event":"connected","data": {"message": "connected"}}
 // out+in:  {"event":"join", "data":{ "deviceIds":["roo7gd1dldn3"], "messageId":"test-1"}}
+// out+in:  {"event":"leave", "data":{ "deviceIds":["roo7gd1dldn3"], "messageId":"test-2"}}
 // in:      {"event":"record","data":{"deviceId":"roo7gd1dldn3","sensorNo":37,"type":"image"}}
 
 /// the notifications we get from the Delphire server through the websocket
@@ -200,6 +204,9 @@ pub enum WsMsg {
     #[serde(rename_all="camelCase")]
     Join { device_ids: Vec<String>, message_id: String },
 
+    #[serde(rename_all="camelCase")]
+    Leave { device_ids: Vec<String>, message_id: String },
+
     #[serde(rename_all="camelCase")]
     Record { device_id: String, sensor_no: u32, #[serde(alias="type")] rec_type: SensorCapability },
 
@@ -214,7 +221,7 @@ pub enum WsMsg {
 }
 
 /// outgoing websocket messages
-#[derive(Serialize,Deserialize,Debug,PartialEq)]
+#[derive(Serialize,Deserialize,Debug,PartialEq,Clone)]
 #[serde(tag="event", content="data", rename_all="lowercase")]
 pub enum WsCmd {
     #[serde(rename_all="camelCase")]
@@ -234,6 +241,28 @@ impl WsCmd {
     pub fn new_ping (msg_id: impl ToString)-> WsCmd {
         WsCmd::Ping { request_time: Utc::now().timestamp_millis() as u64, message_id: msg_id.to_string() }
     }
+
+    /// the devices this command is addressed to, or None if it is not device specific
+    pub fn device_ids (&self)->Option<&Vec<String>> {
+        match self {
+            WsCmd::Ping {..} => None,
+            WsCmd::TriggerAlert { device_ids, .. } => Some(device_ids),
+            WsCmd::SwitchLights { device_ids, .. } => Some(device_ids),
+            WsCmd::SwitchValve { device_ids, .. } => Some(device_ids),
+        }
+    }
+
+    /// a copy of this command that is addressed to a different set of devices
+    pub fn with_device_ids (&self, ids: Vec<String>)->WsCmd {
+        let mut cmd = self.clone();
+        match &mut cmd {
+            WsCmd::Ping {..} => {}
+            WsCmd::TriggerAlert { device_ids, .. } => *device_ids = ids,
+            WsCmd::SwitchLights { device_ids, .. } => *device_ids = ids,
+            WsCmd::SwitchValve { device_ids, .. } => *device_ids = ids,
+        }
+        cmd
+    }
 }
 
 /* #endregion websocket messages */
\ No newline at end of file
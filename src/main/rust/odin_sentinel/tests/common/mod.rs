@@ -0,0 +1,91 @@
+//! shared helpers for odin_sentinel integration tests
+
+#![allow(dead_code)] // not every test crate uses all helpers
+
+use std::sync::{Arc,Mutex};
+use chrono::{DateTime,Utc};
+use odin_sentinel::{Result,SensorCapability};
+use odin_sentinel::storage::SentinelStorage;
+
+/// a canonical payload for each capability. This match is exhaustive, i.e. new capabilities have to be added here
+pub fn sample_payload (capability: SensorCapability)->&'static str {
+    use SensorCapability::*;
+    match capability {
+        Accelerometer => r#"{"ax":0.1,"ay":0.2,"az":9.8}"#,
+        Anemometer    => r#"{"angle":90.0,"speed":3.5}"#,
+        Cloudcover    => r#"{"percent":20.0}"#,
+        Fire          => r#"{"fireProb":0.1}"#,
+        Gas           => r#"{"gas":100,"humidity":30.0,"pressure":1013.0,"altitude":100.0}"#,
+        Gps           => r#"{"latitude":34.1,"longitude":-118.1,"altitude":null,"quality":null,"numberOfSatellites":null,"hdop":1.2}"#,
+        Gyroscope     => r#"{"gx":0.0,"gy":0.1,"gz":0.2}"#,
+        Image         => r#"{"filename":"img-1.webp","isInfrared":false,"orientationRecord":null}"#,
+        Magnetometer  => r#"{"mx":0.0,"my":0.1,"mz":0.2}"#,
+        Orientation   => r#"{"w":1.0,"qx":0.0,"qy":0.0,"qz":0.0}"#,
+        Person        => r#"{"personProb":0.0}"#,
+        Power         => r#"{"batteryVoltage":12.5,"batteryCurrent":0.5,"solarVoltage":18.0,"solarCurrent":1.0,"loadVoltage":12.0,"loadCurrent":0.3,"soc":80.0,"batteryTemp":295.0,"controllerTemp":300.0,"batteryStatus":"normal","chargingVolatageStatus":"normal","chargingStatus":"charging","loadVolatageStatus":"normal","loadStatus":"on"}"#,
+        Smoke         => r#"{"smokeProb":0.0}"#,
+        Thermometer   => r#"{"temperature":293.15}"#,
+        Valve         => r#"{"valveOpen":false,"externalLightOn":false,"internalLightOn":false}"#,
+        Voc           => r#"{"tvoc":100,"e_co2":400}"#,
+    }
+}
+
+/// the JSON of a record with the given evidence ids and payload (the JSON object of the capability property)
+pub fn evidence_record_json (capability: SensorCapability, id: &str, device_id: &str, sensor_no: usize, time: &str,
+                             evidences: &[&str], payload: &str)->String {
+    let prop = serde_json::to_value( capability).unwrap();
+    let evidences: Vec<String> = evidences.iter().map( |id| format!(r#"{{"id":"{id}"}}"#)).collect();
+    format!( r#"{{"id":"{id}","timeRecorded":"{time}","sensorNo":{sensor_no},"deviceId":"{device_id}","evidences":[{}],"claims":[],{prop}:{payload}}}"#,
+             evidences.join(","))
+}
+
+/// the JSON of a record without evidences
+pub fn record_json (capability: SensorCapability, id: &str, device_id: &str, sensor_no: usize, time: &str, payload: &str)->String {
+    evidence_record_json( capability, id, device_id, sensor_no, time, &[], payload)
+}
+
+/// the JSON of a GPS record with id "gps-<device_id>" without altitude and fix quality
+pub fn gps_json (device_id: &str, time: &str, lat: f64, lon: f64)->String {
+    record_json( SensorCapability::Gps, &format!("gps-{device_id}"), device_id, 9, time,
+                 &format!(r#"{{"latitude":{lat},"longitude":{lon},"altitude":null,"quality":null,"numberOfSatellites":null,"HDOP":null}}"#))
+}
+
+/// a record of the given capability for device "roo7gd1dldn3" with id "rec-<idx>" that was recorded <idx> minutes after 20:00
+pub fn sample_record (capability: SensorCapability, idx: usize)->String {
+    record_json( capability, &format!("rec-{idx}"), "roo7gd1dldn3", idx, &format!("2024-01-23T20:{idx:02}:00.000Z"), sample_payload( capability))
+}
+
+/// a SentinelStorage that just remembers the records it got. Clones share the same record list so that tests
+/// can keep a handle after passing a boxed storage to a SentinelStore or connector
+#[derive(Debug,Clone,Default)]
+pub struct StubStorage {
+    pub records: Arc<Mutex<Vec<(SensorCapability,String,String)>>>, // (capability, record id, record json)
+}
+
+impl StubStorage {
+    pub fn record_ids (&self)->Vec<String> {
+        self.records.lock().unwrap().iter().map( |(_,id,_)| id.clone()).collect()
+    }
+
+    /// the (last) stored JSON of the record with the given id
+    pub fn record_json (&self, record_id: &str)->Option<String> {
+        self.records.lock().unwrap().iter().rev().find( |(_,id,_)| id == record_id).map( |(_,_,json)| json.clone())
+    }
+}
+
+impl SentinelStorage for StubStorage {
+    fn store_record (&mut self, capability: SensorCapability, record_id: &str, _device_id: &str, _sensor_no: u32,
+                     _time_recorded: DateTime<Utc>, json: &str)->Result<()> {
+        self.records.lock().unwrap().push( (capability, record_id.to_string(), json.to_string()));
+        Ok(())
+    }
+
+    fn query_records (&self, _capability: SensorCapability, _device_id: &str, _sensor_no: Option<u32>,
+                      _start: DateTime<Utc>, _end: DateTime<Utc>)->Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    fn remove_older_than (&mut self, _cutoff: DateTime<Utc>)->Result<usize> {
+        Ok(0)
+    }
+}
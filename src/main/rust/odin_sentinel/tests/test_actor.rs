@@ -0,0 +1,50 @@
+use std::time::Duration;
+use serde_json::Value;
+use strum::IntoEnumIterator;
+use odin_actor::prelude::*;
+use odin_actor::tokio_kanal::ActorSystem;
+use odin_sentinel::{CheckpointConfig,MultiSentinelConfig,Sentinel,SentinelStore,SensorCapability,SentinelUpdate};
+use odin_sentinel::actor::SentinelConnector;
+
+mod common;
+use common::{StubStorage,sample_record};
+
+/// run one record of each capability through the message interface of a connector that was warm started
+/// from a checkpoint (without any server source) and check that they all reach the storage
+#[tokio::test]
+async fn test_connector_updates()->anyhow::Result<()> {
+    let path = std::env::temp_dir().join( format!("odin_sentinel_test_checkpoint_{}.json", std::process::id()));
+    let mut checkpoint = SentinelStore::new();
+    checkpoint.insert( "roo7gd1dldn3".to_string(), Sentinel::new( "roo7gd1dldn3".to_string(), "roo7gd1dldn3".to_string()));
+    checkpoint.save( &path)?;
+
+    let storage = StubStorage::default();
+    let config = MultiSentinelConfig { sources: Vec::new(), checkpoint: Some( CheckpointConfig { path: path.clone(), interval: None }) };
+    let connector = SentinelConnector::new_multi( config).with_storage( Box::new( storage.clone()));
+
+    let mut actor_system = ActorSystem::new("test");
+    let hconn = spawn_actor!( actor_system, "connector", connector)?;
+    actor_system.start_all( millis(20)).await?;
+
+    let mut expected = Vec::new();
+    for (idx,capability) in SensorCapability::iter().enumerate() {
+        let value: Value = serde_json::from_str( &sample_record( capability, idx))?;
+        hconn.send_msg( SentinelUpdate::from_json( capability, value)?).await?;
+        expected.push( format!("rec-{idx}"));
+    }
+
+    // a fire record of the camera that reported the cloud cover is stored with its effective probability
+    let camera = SensorCapability::iter().position( |c| c == SensorCapability::Cloudcover).unwrap(); // sample sensorNo is the index
+    let mut fire: Value = serde_json::from_str( &sample_record( SensorCapability::Fire, 50))?;
+    fire["id"] = Value::from( "rec-fire");
+    fire["sensorNo"] = Value::from( camera);
+    hconn.send_msg( SentinelUpdate::from_json( SensorCapability::Fire, fire)?).await?;
+    expected.push( "rec-fire".to_string());
+
+    tokio::time::sleep( Duration::from_millis(200)).await;
+    std::fs::remove_file( &path).ok();
+
+    assert_eq!( storage.record_ids(), expected);
+    assert!( storage.record_json( "rec-fire").unwrap().contains( "effectiveProb"));
+    Ok(())
+}
@@ -0,0 +1,70 @@
+use std::time::Duration;
+use serde_json::Value;
+use odin_sentinel::{Result,SentinelStore,SensorCapability};
+use odin_sentinel::aggregate::aggregate;
+use odin_sentinel::units::{Units,TemperatureUnit};
+
+mod common;
+use common::record_json;
+
+fn thermo (id: &str, time: &str, kelvin: f64)->String {
+    record_json( SensorCapability::Thermometer, id, "dev", 4, time, &format!(r#"{{"temperature":{kelvin}}}"#))
+}
+
+#[test]
+fn test_temperature_series()->Result<()> {
+    let mut store = SentinelStore::new();
+    store.add_json_record( &thermo( "t1", "2023-01-29T19:05:00.000Z", 293.15))?;
+    store.add_json_record( &thermo( "t2", "2023-01-29T19:35:00.000Z", 295.15))?;
+    store.add_json_record( &thermo( "t3", "2023-01-29T19:55:00.000Z", 294.15))?;
+    store.add_json_record( &thermo( "t4", "2023-01-29T21:10:00.000Z", 283.15))?;
+
+    let units = Units { temperature: TemperatureUnit::Celsius, ..Units::default() };
+    let series = store.aggregate( SensorCapability::Thermometer, "temperature", Duration::from_secs(3600), &units)?;
+
+    assert_eq!( series.len(), 1);
+    let s = &series[0];
+    assert_eq!( s.unit.as_deref(), Some("°C"));
+    assert_eq!( s.buckets.len(), 2); // no empty bucket for 20:00
+    let b = &s.buckets[0];
+    assert_eq!( b.start.to_rfc3339(), "2023-01-29T19:00:00+00:00");
+    assert_eq!( b.count, 3);
+    assert!( (b.min - 20.0).abs() < 1e-9 && (b.max - 22.0).abs() < 1e-9 && (b.mean - 21.0).abs() < 1e-9);
+    assert!( (b.last - 21.0).abs() < 1e-9);
+    assert!( (s.buckets[1].mean - 10.0).abs() < 1e-9);
+    Ok(())
+}
+
+#[test]
+fn test_fire_prob_series()->Result<()> {
+    let records: Vec<Value> = [
+        (0, 3, 0.2), (0, 7, 0.6), (0, 12, 0.4), (1, 8, 0.9)
+    ].iter().map( |(dev,min,p)| serde_json::from_str( &record_json( SensorCapability::Fire, &format!("f{dev}{min}"), &format!("dev{dev}"), 7,
+        &format!("2023-01-29T19:{min:02}:00.000Z"), &format!(r#"{{"fireProb":{p}}}"#)
+    )).unwrap()).collect();
+
+    let series = aggregate( SensorCapability::Fire, &records, "fireProb", Duration::from_secs(600), &Units::default())?;
+    assert_eq!( series.len(), 2);
+    assert_eq!( series[0].device_id, "dev0");
+    assert_eq!( series[0].unit, None);
+    assert_eq!( series[0].buckets.iter().map( |b| b.max).collect::<Vec<f64>>(), vec![0.6, 0.4]);
+    assert_eq!( series[1].buckets[0].max, 0.9);
+
+    assert!( aggregate( SensorCapability::Fire, &records, "fireProb", Duration::ZERO, &Units::default()).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_wind_direction_series()->Result<()> {
+    let records: Vec<Value> = [ (1, 350.0), (2, 10.0), (3, 20.0) ].iter().map( |(min,angle)| serde_json::from_str( &record_json(
+        SensorCapability::Anemometer, &format!("a{min}"), "dev", 2, &format!("2023-01-29T19:{min:02}:00.000Z"), &format!(r#"{{"angle":{angle},"speed":3.0}}"#)
+    )).unwrap()).collect();
+
+    let series = aggregate( SensorCapability::Anemometer, &records, "angle", Duration::from_secs(600), &Units::default())?;
+    let b = &series[0].buckets[0];
+    assert!( (b.mean - 6.7).abs() < 0.1, "mean direction {} does not wrap around north", b.mean);
+
+    let series = aggregate( SensorCapability::Anemometer, &records, "speed", Duration::from_secs(600), &Units::default())?;
+    assert!( (series[0].buckets[0].mean - 3.0).abs() < 1e-9);
+    Ok(())
+}
@@ -0,0 +1,67 @@
+use std::time::Duration;
+use odin_sentinel::{Result,SentinelUpdate,SensorRecord,VocData,SmokeData,SensorCapability};
+use odin_sentinel::anomaly::{BaselineConfig,BaselineTracker,AirSignal};
+
+mod common;
+use common::record_json;
+
+fn voc (i: usize, tvoc: i32, e_co2: i32)->Result<SentinelUpdate> {
+    let rec: SensorRecord<VocData> = serde_json::from_str( &record_json( SensorCapability::Voc, &format!("voc-{i}"), "dev", 5,
+        &format!("2023-01-29T19:{i:02}:00.000Z"), &format!(r#"{{"TVOC":{tvoc},"eCO2":{e_co2}}}"#)))?;
+    Ok( rec.into())
+}
+
+fn smoke (id: &str, minute: usize)->Result<SentinelUpdate> {
+    let rec: SensorRecord<SmokeData> = serde_json::from_str( &record_json( SensorCapability::Smoke, id, "dev", 7,
+        &format!("2023-01-29T19:{minute:02}:00.000Z"), r#"{"smokeProb":0.7}"#))?;
+    Ok( rec.into())
+}
+
+#[test]
+fn test_voc_anomaly()->Result<()> {
+    let mut tracker = BaselineTracker::new( BaselineConfig::default());
+
+    for i in 0..20 {
+        let tvoc = if i % 2 == 0 { 100 } else { 102 };
+        assert!( tracker.anomalies( &voc( i, tvoc, 400)?).is_empty());
+    }
+
+    let scores = tracker.process( &voc( 20, 101, 400)?);
+    assert_eq!( scores.len(), 2); // constant eCO2 still gets a score from its resolution floor
+    assert!( scores.iter().all( |s| s.z_score.abs() < 3.0));
+    assert_eq!( scores.iter().find( |s| s.signal == AirSignal::ECo2).unwrap().z_score, 0.0);
+
+    let events = tracker.anomalies( &voc( 21, 200, 400)?);
+    assert_eq!( events.len(), 1);
+    assert_eq!( events[0].score.signal, AirSignal::Tvoc);
+    assert!( events[0].smoke_indicative);
+    assert!( (events[0].score.expected - 101.0).abs() < 1.0);
+
+    let t = events[0].score.time_recorded;
+    assert!( tracker.smoke_evidence( &"dev".to_string(), t, Duration::from_secs(300)).unwrap() > 3.0);
+    assert!( tracker.smoke_evidence( &"other".to_string(), t, Duration::from_secs(300)).is_none());
+    Ok(())
+}
+
+#[test]
+fn test_detection_confirmation()->Result<()> {
+    let mut tracker = BaselineTracker::new( BaselineConfig::default());
+    for i in 0..20 {
+        tracker.anomalies( &voc( i, 100, 400)?);
+    }
+
+    // a detection before the anomaly is kept until the anomaly confirms it
+    assert!( tracker.confirmations( &smoke( "smoke-1", 20)?).is_empty());
+    let anomaly = voc( 21, 200, 400)?;
+    assert!( tracker.anomalies( &anomaly).iter().any( |e| e.smoke_indicative));
+    let confirmations = tracker.confirmations( &anomaly);
+    assert_eq!( confirmations.len(), 1);
+    assert_eq!( confirmations[0].detection_id, "smoke-1");
+    assert_eq!( confirmations[0].capability, SensorCapability::Smoke);
+    assert!( confirmations[0].smoke_score > 3.0);
+
+    // a detection after the anomaly is confirmed right away, one outside the window is not
+    assert_eq!( tracker.confirmations( &smoke( "smoke-2", 25)?).len(), 1);
+    assert!( tracker.confirmations( &smoke( "smoke-3", 59)?).is_empty());
+    Ok(())
+}
@@ -0,0 +1,56 @@
+use chrono::{DateTime,Utc};
+use odin_sentinel::{Result,SentinelStore,SensorCapability};
+use odin_sentinel::cap::{CapConfig,CapArea,CapMsgType,cap_alert,alert_identifier,alert_reference,severity,certainty};
+use odin_sentinel::timeline::AlertThresholds;
+use odin_sentinel::position::PositionConfig;
+
+mod common;
+use common::{record_json,evidence_record_json,gps_json};
+
+fn test_config ()->CapConfig {
+    CapConfig {
+        sender: "odin@example.org".to_string(),
+        sender_name: "ODIN".to_string(),
+        thresholds: AlertThresholds::default(),
+        extreme_prob: 0.95,
+        area: CapArea::Polygon { radius_km: 5.0, n_points: 8 },
+        image_base_uri: Some("https://example.org/images/".to_string()),
+        outputs: vec![],
+        position: PositionConfig::default(),
+    }
+}
+
+#[test]
+fn test_cap_alert()->Result<()> {
+    let mut store = SentinelStore::new();
+    store.add_json_record( &gps_json( "roo7gd1dldn3", "2023-01-29T19:32:04.000Z", 34.16381345, -118.10208433333334))?;
+    store.add_json_record( &record_json( SensorCapability::Image, "img-1", "roo7gd1dldn3", 0, "2023-01-29T19:33:00.000Z",
+                                         r#"{"filename":"img-1.webp","isInfrared":false,"orientationRecord":null}"#))?;
+    store.add_json_record( &evidence_record_json( SensorCapability::Fire, "fire-1", "roo7gd1dldn3", 7, "2023-01-29T19:33:01.000Z",
+                                                  &["img-1"], r#"{"fireProb":0.92}"#))?;
+    let sentinel = store.get( &"roo7gd1dldn3".to_string()).unwrap();
+
+    let config = test_config();
+    assert_eq!( severity( &config, 0.92), "Severe");
+    assert_eq!( certainty( &config, 0.6), "Possible");
+
+    let sent: DateTime<Utc> = "2023-01-29T19:33:05Z".parse().unwrap();
+    let id = alert_identifier( &config, &sentinel.device_id, SensorCapability::Fire, sent);
+    assert_eq!( id, "odin@example.org-roo7gd1dldn3-fire-1675020785000");
+    assert_eq!( alert_identifier( &config, "Roo7GD1", SensorCapability::Smoke, sent), "odin@example.org-Roo7GD1-smoke-1675020785000");
+    let cap = cap_alert( &config, sentinel, SensorCapability::Fire, &id, sent, CapMsgType::Alert, None).unwrap();
+
+    assert!( cap.contains("<msgType>Alert</msgType>"));
+    assert!( cap.contains("<sent>2023-01-29T19:33:05+00:00</sent>"));
+    assert!( cap.contains("<severity>Severe</severity><certainty>Likely</certainty>"));
+    assert!( cap.contains("<uri>https://example.org/images/img-1.webp</uri>"));
+    assert!( cap.contains("<mimeType>image/webp</mimeType>"));
+    assert!( cap.contains("<polygon>"));
+
+    let references = alert_reference( &config, &id, sent);
+    let update = cap_alert( &config, sentinel, SensorCapability::Fire, "x", sent, CapMsgType::Update, Some(&references)).unwrap();
+    assert!( update.contains( &format!("<references>{}</references>", references)));
+
+    assert!( cap_alert( &config, sentinel, SensorCapability::Smoke, &id, sent, CapMsgType::Alert, None).is_none()); // no smoke record
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use strum::IntoEnumIterator;
+use serde_json::Value;
+use odin_sentinel::{Result,SensorCapability,Sentinel,SentinelStore,SentinelUpdate};
+
+mod common;
+use common::sample_record;
+
+#[test]
+fn test_capability_names()->Result<()> {
+    assert_eq!( SensorCapability::from_names( "fire, smoke")?, vec![SensorCapability::Fire, SensorCapability::Smoke]);
+    assert!( SensorCapability::from_names( "")?.is_empty());
+    assert!( SensorCapability::from_names( "fire,nonsense").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_capabilities_wired()->Result<()> {
+    let mut store = SentinelStore::new();
+
+    for (idx,capability) in SensorCapability::iter().enumerate() {
+        // capability <-> record property name
+        let name = serde_json::to_value( capability)?;
+        assert_eq!( SensorCapability::from_name( name.as_str().unwrap()), Some(capability));
+
+        // JSON record -> SentinelUpdate -> JSON record
+        let json = sample_record( capability, idx);
+        let value: Value = serde_json::from_str( &json)?;
+        assert_eq!( SensorCapability::of_record( &value), Some(capability));
+
+        let update = SentinelUpdate::from_json( capability, value.clone())?;
+        assert_eq!( update.capability(), capability);
+        assert_eq!( update.record_id(), format!("rec-{idx}"));
+        let output = serde_json::to_value( &update)?;
+        assert!( output.get( name.as_str().unwrap()).is_some());
+
+        // JSON record -> Sentinel record list
+        assert_eq!( store.add_json_record( &json)?, ("roo7gd1dldn3".to_string(), capability));
+    }
+
+    // all records survive a snapshot roundtrip and are reported as new updates of an empty sentinel
+    let store = SentinelStore::from_json( &store.to_json( false)?)?;
+    let sentinel = store.into_values().pop().unwrap();
+    for capability in SensorCapability::iter() {
+        assert_eq!( sentinel.json_records( capability)?.len(), 1, "no {:?} records", capability);
+    }
+
+    let updates = Sentinel::new( "roo7gd1dldn3".to_string(), "test".to_string()).new_updates( sentinel);
+    assert_eq!( updates.len(), SensorCapability::iter().count());
+    assert!( updates.windows(2).all( |w| w[0].time_recorded() <= w[1].time_recorded()));
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use odin_sentinel::{Result,SentinelStore,SentinelUpdate,SensorCapability,SensorRecord,FireData};
+use odin_sentinel::confidence::CloudWeighting;
+
+mod common;
+use common::{record_json,evidence_record_json};
+
+fn test_store ()->Result<SentinelStore> {
+    let mut store = SentinelStore::new();
+    store.add_json_record( &record_json( SensorCapability::Cloudcover, "cc-0", "dev", 0, "2023-01-29T19:30:00.000Z", r#"{"percent":80.0}"#))?;
+    store.add_json_record( &record_json( SensorCapability::Cloudcover, "cc-1", "dev", 1, "2023-01-29T19:31:00.000Z", r#"{"percent":10.0}"#))?;
+    store.add_json_record( &record_json( SensorCapability::Image, "img-0", "dev", 0, "2023-01-29T19:32:00.000Z",
+                                         r#"{"filename":"img-0.webp","isInfrared":false,"orientationRecord":null}"#))?;
+    Ok(store)
+}
+
+#[test]
+fn test_weighting_curve() {
+    let w = CloudWeighting::default();
+    assert_eq!( w.factor( -5.0), 1.0);
+    assert_eq!( w.factor( 0.0), 1.0);
+    assert!( (w.factor( 65.0) - 0.75).abs() < 1e-9);
+    assert_eq!( w.factor( 100.0), 0.3);
+
+    let none = CloudWeighting { curve: Vec::new(), ..CloudWeighting::default() };
+    assert_eq!( none.factor( 90.0), 1.0);
+}
+
+#[test]
+fn test_effective_prob()->Result<()> {
+    let store = test_store()?;
+    let sentinel = store.get( &"dev".to_string()).unwrap();
+    let weighting = CloudWeighting::default();
+
+    // the evidence image is from camera 0, which reports 80% cloud cover
+    let rec: SensorRecord<FireData> = serde_json::from_str( &evidence_record_json( SensorCapability::Fire, "fire-1", "dev", 7, "2023-01-29T19:33:00.000Z",
+                                                                                  &["img-0"], r#"{"fireProb":0.9}"#))?;
+    let mut update = SentinelUpdate::from( rec);
+    update.annotate_confidence( sentinel, &weighting);
+    let SentinelUpdate::Fire(rec) = &update else { panic!("not a fire record") };
+    assert!( (rec.data.confidence() - 0.54).abs() < 1e-9);
+    assert_eq!( rec.data.fire_prob, 0.9);
+
+    let json = serde_json::to_string( &update)?;
+    assert!( json.contains(r#""effectiveProb":0.54"#));
+
+    // no cloud cover for the detection sensor itself and no evidences - no adjustment
+    let rec: SensorRecord<FireData> = serde_json::from_str( &record_json( SensorCapability::Fire, "fire-2", "dev", 7, "2023-01-29T19:33:00.000Z", r#"{"fireProb":0.9}"#))?;
+    let mut update = SentinelUpdate::from( rec);
+    update.annotate_confidence( sentinel, &weighting);
+    let SentinelUpdate::Fire(rec) = &update else { panic!("not a fire record") };
+    assert_eq!( rec.data.effective_prob, None);
+    assert_eq!( rec.data.confidence(), 0.9);
+    Ok(())
+}
@@ -0,0 +1,81 @@
+use std::time::Duration;
+use chrono::{DateTime,Utc};
+use tokio::net::UdpSocket;
+use odin_sentinel::{Result,SentinelStore,SensorCapability};
+use odin_sentinel::cot::{CotConfig,CotEndpoint,CotSender,position_event,alarm_event,clear_event,position_uid,alarm_uid};
+use odin_sentinel::timeline::AlertThresholds;
+use odin_sentinel::position::PositionConfig;
+
+mod common;
+use common::{record_json,gps_json};
+
+fn test_store ()->Result<SentinelStore> {
+    let mut store = SentinelStore::new();
+    store.add_json_record( &gps_json( "roo7gd1dldn3", "2023-01-29T19:32:04.000Z", 34.16381345, -118.10208433333334))?;
+    store.add_json_record( &record_json( SensorCapability::Fire, "fire-1", "roo7gd1dldn3", 7, "2023-01-29T19:33:01.000Z", r#"{"fireProb":0.92}"#))?;
+    store.add_json_record( &record_json( SensorCapability::Smoke, "smoke-1", "roo7gd1dldn3", 7, "2023-01-29T19:33:01.000Z", r#"{"smokeProb":0.1}"#))?;
+    Ok(store)
+}
+
+/// the time value of an event attribute such as `stale="..."`
+fn event_time (event: &str, attr: &str)->DateTime<Utc> {
+    let start = event.find( &format!(r#" {attr}=""#)).unwrap() + attr.len() + 3;
+    let len = event[start..].find('"').unwrap();
+    DateTime::parse_from_rfc3339( &event[start..start+len]).unwrap().with_timezone( &Utc)
+}
+
+fn test_config (endpoint: CotEndpoint)->CotConfig {
+    CotConfig {
+        endpoint,
+        multicast_ttl: None,
+        uid_prefix: "sentinel-".to_string(),
+        position_type: "a-f-G-E-S".to_string(),
+        alarm_type: "a-h-G".to_string(),
+        stale: Duration::from_secs(300),
+        thresholds: AlertThresholds::default(),
+        position: PositionConfig::default(),
+    }
+}
+
+#[tokio::test]
+async fn test_cot_udp()->Result<()> {
+    let listener = UdpSocket::bind("127.0.0.1:0").await?;
+    let config = test_config( CotEndpoint::Udp( listener.local_addr()?.to_string()));
+
+    let store = test_store()?;
+    let sentinel = store.get( &"roo7gd1dldn3".to_string()).unwrap();
+
+    let before = Utc::now();
+    let pos = position_event( &config, sentinel).unwrap();
+    assert!( pos.contains(r#"uid="sentinel-roo7gd1dldn3""#));
+    assert!( pos.contains(r#"type="a-f-G-E-S""#));
+    assert!( pos.contains(r#"lat="34.16381345""#));
+    assert!( pos.contains("fix at 2023-01-29T19:32:04.000Z</remarks>")); // record time goes into the remarks
+    let time = event_time( &pos, "time");
+    assert!( time >= before && time <= Utc::now()); // event times are send times
+    assert_eq!( event_time( &pos, "start"), time);
+    assert_eq!( event_time( &pos, "stale"), time + chrono::Duration::seconds(300));
+
+    let alarm = alarm_event( &config, sentinel, SensorCapability::Fire).unwrap();
+    assert!( alarm.contains(r#"uid="sentinel-roo7gd1dldn3-fire""#));
+    assert!( alarm.contains("<remarks>alert fire probability 0.92 at 2023-01-29T19:33:01.000Z</remarks>"));
+    assert!( clear_event( &config, sentinel, SensorCapability::Fire).is_none()); // still alarming
+
+    // alarm UIDs extend position UIDs and keep the device id case
+    assert_eq!( position_uid( &config, "Roo7GD1"), "sentinel-Roo7GD1");
+    assert_eq!( alarm_uid( &config, "Roo7GD1", SensorCapability::Smoke), "sentinel-Roo7GD1-smoke");
+    assert!( alarm_event( &config, sentinel, SensorCapability::Smoke).is_none()); // below threshold
+
+    // clearing events use the alarm UID and are stale when sent
+    let clear = clear_event( &config, sentinel, SensorCapability::Smoke).unwrap();
+    assert!( clear.contains(r#"uid="sentinel-roo7gd1dldn3-smoke""#));
+    assert_eq!( event_time( &clear, "stale"), event_time( &clear, "time"));
+
+    let mut sender = CotSender::connect( &config.endpoint, None).await?;
+    sender.send( &pos).await?;
+
+    let mut buf = vec![0u8; 4096];
+    let (len,_) = listener.recv_from( &mut buf).await?;
+    assert_eq!( std::str::from_utf8( &buf[..len]).unwrap(), pos.as_str());
+    Ok(())
+}
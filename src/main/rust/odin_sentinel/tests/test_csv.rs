@@ -0,0 +1,47 @@
+use serde_json::Value;
+use odin_sentinel::SensorCapability;
+use odin_sentinel::csv::csv_table;
+use odin_sentinel::units::{Units,TemperatureUnit,SpeedUnit};
+
+mod common;
+use common::record_json;
+
+fn records (json: &[String])->Vec<Value> {
+    json.iter().map( |s| serde_json::from_str(s).unwrap()).collect()
+}
+
+#[test]
+fn test_thermometer_units() {
+    let recs = records( &[
+        record_json( SensorCapability::Thermometer, "t2", "roo7gd1dldn3", 3, "2024-01-23T20:33:01.000Z", r#"{"temperature":373.15}"#),
+        record_json( SensorCapability::Thermometer, "t1", "roo7gd1dldn3", 3, "2024-01-23T20:32:01.000Z", r#"{"temperature":273.15}"#),
+    ]);
+
+    let csv = csv_table( SensorCapability::Thermometer, &recs, &Units::default());
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!( lines[0], "id,device_id,sensor_no,time_recorded,temperature [°C]");
+    assert_eq!( lines[1], "t1,roo7gd1dldn3,3,2024-01-23T20:32:01.000Z,0.000"); // sorted by time
+
+    let units = Units { temperature: TemperatureUnit::Fahrenheit, ..Units::default() };
+    let csv = csv_table( SensorCapability::Thermometer, &recs, &units);
+    assert!( csv.lines().nth(2).unwrap().ends_with(",212.000"));
+}
+
+#[test]
+fn test_anemometer_units() {
+    let recs = records( &[
+        record_json( SensorCapability::Anemometer, "a1", "roo7gd1dldn3", 5, "2024-01-23T20:32:01.000Z", r#"{"angle":90.0,"speed":10.0}"#),
+    ]);
+    let units = Units { speed: SpeedUnit::KilometersPerHour, ..Units::default() };
+    let csv = csv_table( SensorCapability::Anemometer, &recs, &units);
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!( lines[0], "id,device_id,sensor_no,time_recorded,angle,speed [km/h]");
+    assert!( lines[1].ends_with(",90.0,36.000"));
+}
+
+#[test]
+fn test_unit_parsing() {
+    assert_eq!( "F".parse::<TemperatureUnit>().unwrap(), TemperatureUnit::Fahrenheit);
+    assert_eq!( "mph".parse::<SpeedUnit>().unwrap(), SpeedUnit::MilesPerHour);
+    assert!( "furlong".parse::<SpeedUnit>().is_err());
+}
@@ -0,0 +1,90 @@
+use std::time::Duration;
+use odin_sentinel::{Result,SentinelStore,Sentinel,SensorCapability,SensorRecord,GpsData,FireData,ImageData,sort_in_record};
+use odin_sentinel::geojson::{GeoJsonOpts,feature_collection};
+use odin_sentinel::position::PositionConfig;
+use odin_sentinel::{timeline::{TimeWindow,AlertState,AlertThresholds,alert_intervals},czml::{CzmlOpts,czml_document},kml::{KmlOpts,kml_document}};
+
+mod common;
+use common::{record_json,evidence_record_json,gps_json};
+
+fn test_store ()->Result<SentinelStore> {
+    let gps: SensorRecord<GpsData> = serde_json::from_str( &gps_json( "roo7gd1dldn3", "2023-01-29T19:32:04.000Z", 34.16381345, -118.10208433333334))?;
+    let image: SensorRecord<ImageData> = serde_json::from_str( &record_json( SensorCapability::Image, "img-1", "roo7gd1dldn3", 0, "2023-01-29T19:33:00.000Z",
+                                                                            r#"{"filename":"img-1.webp","isInfrared":false,"orientationRecord":null}"#))?;
+    let fire: SensorRecord<FireData> = serde_json::from_str( &evidence_record_json( SensorCapability::Fire, "fire-1", "roo7gd1dldn3", 7, "2023-01-29T19:33:01.000Z",
+                                                                                   &["img-1"], r#"{"fireProb":0.92}"#))?;
+
+    let mut sentinel = Sentinel::new( "roo7gd1dldn3".to_string(), "test-1".to_string());
+    sort_in_record( &mut sentinel.gps, gps);
+    sort_in_record( &mut sentinel.image, image);
+    sort_in_record( &mut sentinel.fire, fire);
+
+    let mut store = SentinelStore::new();
+    store.insert( sentinel.device_id.clone(), sentinel);
+    Ok(store)
+}
+
+#[test]
+fn test_geojson()->Result<()> {
+    let store = test_store()?;
+    let opts = GeoJsonOpts { include_detections: true, max_age: Duration::from_secs(3600), ray_length: None, position: PositionConfig::default() };
+    let fc = feature_collection( &store, &opts);
+
+    let features = fc["features"].as_array().unwrap();
+    assert_eq!( features.len(), 2);
+    assert_eq!( features[0]["properties"]["deviceName"], "test-1");
+    assert_eq!( features[0]["properties"]["fireProb"], 0.92);
+    assert_eq!( features[0]["properties"]["health"], "stale");
+    assert_eq!( features[0]["geometry"]["coordinates"][1], 34.16381345);
+    assert_eq!( features[1]["properties"]["featureType"], "fire");
+    assert_eq!( features[1]["geometry"]["coordinates"][1], 34.16381345);
+    assert_eq!( features[1]["properties"]["images"][0]["filename"], "img-1.webp");
+    Ok(())
+}
+
+#[test]
+fn test_czml_kml()->Result<()> {
+    let store = test_store()?;
+    let window = TimeWindow::covering( &store).unwrap();
+    assert_eq!( window.start.to_rfc3339().as_str(), "2023-01-29T19:32:04+00:00");
+    assert_eq!( window.end.to_rfc3339().as_str(), "2023-01-29T19:33:01+00:00");
+
+    let sentinel = store.get( &"roo7gd1dldn3".to_string()).unwrap();
+    let alerts = alert_intervals( sentinel, &window, &AlertThresholds::default());
+    assert_eq!( alerts.len(), 2); // the fire record at the window end starts a zero length interval
+    assert_eq!( alerts[0].state, AlertState::Normal);
+    assert_eq!( alerts[1].state, AlertState::Alert);
+    assert_eq!( alerts[1].start, window.end);
+
+    let czml = czml_document( &store, &window, &CzmlOpts::default());
+    assert_eq!( czml[0]["id"], "document");
+    assert_eq!( czml[1]["id"], "roo7gd1dldn3");
+    assert_eq!( czml[1]["position"]["cartographicDegrees"][0], 0.0);
+
+    let kml = kml_document( &store, &window, &KmlOpts::default());
+    assert!( kml.contains("<name>test-1</name>"));
+    assert!( kml.contains("<TimeSpan><begin>2023-01-29T19:32:04Z</begin><end>2023-01-29T19:33:01Z</end></TimeSpan>"));
+    Ok(())
+}
+
+#[test]
+fn test_record_archive()->Result<()> {
+    let path = std::env::temp_dir().join( format!("odin_sentinel_test_archive_{}.jsonl", std::process::id()));
+    let lines = [
+        record_json( SensorCapability::Fire, "fire-1", "roo7gd1dldn3", 7, "2023-01-29T19:33:01.000Z", r#"{"fireProb":0.92}"#),
+        String::new(), // blank lines are skipped
+        record_json( SensorCapability::Fire, "fire-2", "roo7gd1dldn3", 7, "2023-01-29T19:34:01.000Z", r#"{"fireProb":0.95}"#),
+        gps_json( "roo7gd1dldn3", "2023-01-29T19:32:04.000Z", 34.16381345, -118.10208433333334),
+    ];
+    std::fs::write( &path, lines.join("\n"))?;
+
+    let store = SentinelStore::load_record_archive( &path);
+    std::fs::remove_file( &path)?;
+    let store = store?;
+
+    let sentinel = store.get( &"roo7gd1dldn3".to_string()).unwrap();
+    assert_eq!( sentinel.fire.len(), 2);
+    assert_eq!( sentinel.fire[0].id.as_str(), "fire-2"); // newest first
+    assert_eq!( sentinel.gps.len(), 1);
+    Ok(())
+}
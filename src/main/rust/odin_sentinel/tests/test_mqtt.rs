@@ -0,0 +1,83 @@
+#![cfg(feature="mqtt")]
+
+use std::time::Duration;
+use rumqttc::{AsyncClient,MqttOptions,QoS,Event,Packet};
+use odin_sentinel::{Result,SentinelStore,SensorCapability};
+use odin_sentinel::mqtt::{MqttConfig,record_topic,qos,snapshot_messages,publish_messages};
+
+mod common;
+use common::record_json;
+
+fn fire_record (id: &str, time: &str)->String {
+    record_json( SensorCapability::Fire, id, "roo7gd1dldn3", 7, time, r#"{"fireProb":0.92}"#)
+}
+
+#[test]
+fn test_record_topic() {
+    assert_eq!( record_topic( "sentinel", &fire_record( "fire-1", "2023-01-29T19:33:01.000Z")), Some("sentinel/roo7gd1dldn3/fire/7".to_string()));
+    assert_eq!( record_topic( "sentinel", r#"{"deviceId":"roo7gd1dldn3"}"#), None);
+    assert_eq!( qos(1), QoS::AtLeastOnce);
+}
+
+#[test]
+fn test_snapshot_messages()->Result<()> {
+    let mut store = SentinelStore::new();
+    store.add_json_record( &fire_record( "fire-1", "2023-01-29T19:33:01.000Z"))?;
+    store.add_json_record( &fire_record( "fire-0", "2023-01-29T19:32:01.000Z"))?; // older
+    store.add_json_record( &record_json( SensorCapability::Smoke, "smoke-1", "roo7gd1dldn3", 7, "2023-01-29T19:33:01.000Z", r#"{"smokeProb":0.1}"#))?;
+
+    let mut msgs = snapshot_messages( "sentinel", &store)?;
+    msgs.sort();
+    assert_eq!( msgs.len(), 2); // only the newest record per topic
+    assert_eq!( msgs[0].0, "sentinel/roo7gd1dldn3/fire/7");
+    assert!( msgs[0].1.contains(r#""id":"fire-1""#));
+    assert_eq!( msgs[1].0, "sentinel/roo7gd1dldn3/smoke/7");
+    Ok(())
+}
+
+fn test_config (client_id: &str)->MqttConfig {
+    MqttConfig {
+        host: "localhost".to_string(),
+        port: 1883,
+        client_id: client_id.to_string(),
+        credentials: None,
+        keep_alive: Duration::from_secs(30),
+        topic_prefix: "odin-test".to_string(),
+        qos: 1,
+        retain: true,
+        cmd_topic: None,
+    }
+}
+
+/// needs a MQTT broker (e.g. mosquitto) running on localhost:1883 - run with `cargo test --features mqtt -- --ignored`
+#[tokio::test]
+#[ignore]
+async fn test_mqtt_retained()->Result<()> {
+    let config = test_config( "odin-test-pub");
+    let mut store = SentinelStore::new();
+    let fire = fire_record( "fire-1", "2023-01-29T19:33:01.000Z");
+    store.add_json_record( &fire)?;
+    let topic = record_topic( &config.topic_prefix, &fire).unwrap();
+
+    // this is what the MqttBridge does when it connects
+    let (publisher, mut pub_loop) = AsyncClient::new( MqttOptions::new( &config.client_id, &config.host, config.port), 10);
+    publish_messages( &publisher, &config, snapshot_messages( &config.topic_prefix, &store)?).await?;
+    tokio::spawn( async move { while pub_loop.poll().await.is_ok() {} });
+    tokio::time::sleep( Duration::from_millis(500)).await;
+
+    // the subscriber connects after the record was published, i.e. it has to get the retained message
+    let (subscriber, mut sub_loop) = AsyncClient::new( MqttOptions::new( "odin-test-sub", "localhost", 1883), 10);
+    subscriber.subscribe( "odin-test/#", QoS::AtLeastOnce).await?;
+
+    let payload = tokio::time::timeout( Duration::from_secs(5), async move {
+        loop {
+            if let Ok(Event::Incoming(Packet::Publish(p))) = sub_loop.poll().await {
+                if p.topic == topic { return p.payload }
+            }
+        }
+    }).await.expect("no retained message received");
+
+    let payload: serde_json::Value = serde_json::from_slice( &payload)?;
+    assert_eq!( payload.get("id").and_then( |v| v.as_str()), Some("fire-1"));
+    Ok(())
+}
@@ -0,0 +1,49 @@
+#![cfg(feature="parquet")]
+
+use std::fs::File;
+use arrow::datatypes::DataType;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use serde_json::Value;
+use odin_sentinel::{Result,SensorCapability};
+use odin_sentinel::parquet::{record_batch,write_partitioned};
+
+mod common;
+use common::{record_json,evidence_record_json};
+
+fn thermo_records ()->Vec<Value> {
+    use SensorCapability::Thermometer;
+    [ record_json( Thermometer, "t1", "roo7gd1dldn3", 3, "2024-01-22T20:32:01.000Z", r#"{"temperature":291.15}"#),
+      evidence_record_json( Thermometer, "t2", "roo7gd1dldn3", 3, "2024-01-23T20:32:01.000Z", &["i1"], r#"{"temperature":293.15}"#),
+      record_json( Thermometer, "t3", "roo7gd1dldn3", 3, "2024-01-23T20:33:01.000Z", r#"{"temperature":294.15}"#),
+    ].iter().map( |s| serde_json::from_str(s).unwrap()).collect()
+}
+
+#[test]
+fn test_record_batch()->Result<()> {
+    let batch = record_batch( SensorCapability::Thermometer, &thermo_records())?;
+    let schema = batch.schema();
+
+    assert_eq!( batch.num_rows(), 3);
+    assert_eq!( schema.field(0).name(), "id");
+    assert_eq!( schema.field(4).name(), "evidences");
+    assert_eq!( schema.field(6).name(), "temperature");
+    assert_eq!( schema.field(6).data_type(), &DataType::Float64); // SI (Kelvin)
+    Ok(())
+}
+
+#[test]
+fn test_partitioned_parquet()->Result<()> {
+    let dir = std::env::temp_dir().join( "odin_sentinel_test_parquet");
+    std::fs::remove_dir_all( &dir).ok();
+
+    let paths = write_partitioned( &dir, SensorCapability::Thermometer, &thermo_records())?;
+    assert_eq!( paths.len(), 2); // one file per day
+    assert!( paths[1].ends_with( "thermometer/device_id=roo7gd1dldn3/date=2024-01-23/records.parquet"));
+
+    let reader = ParquetRecordBatchReaderBuilder::try_new( File::open( &paths[1])?)?.build()?;
+    let n_rows: usize = reader.map( |b| b.unwrap().num_rows()).sum();
+    assert_eq!( n_rows, 2);
+
+    std::fs::remove_dir_all( &dir).ok();
+    Ok(())
+}
@@ -0,0 +1,73 @@
+use odin_sentinel::{Result,Sentinel,SensorCapability,SensorRecord,GpsData,FireData,ImageData,OrientationData,sort_in_record};
+use odin_sentinel::pointing::{euler_angles,camera_pointing,destination};
+use odin_sentinel::position::PositionConfig;
+
+mod common;
+use common::{record_json,evidence_record_json,gps_json};
+
+fn quat (yaw: f64, pitch: f64)->OrientationData {
+    // yaw about z followed by pitch about y'
+    let (cy, sy) = ((yaw.to_radians()/2.0).cos(), (yaw.to_radians()/2.0).sin());
+    let (cp, sp) = ((pitch.to_radians()/2.0).cos(), (pitch.to_radians()/2.0).sin());
+    OrientationData { w: cy*cp, qx: -sy*sp, qy: cy*sp, qz: sy*cp }
+}
+
+fn assert_close (a: f64, b: f64) {
+    assert!( (a - b).abs() < 1e-6, "{} != {}", a, b);
+}
+
+#[test]
+fn test_camera_pointing() {
+    let o = quat( 90.0, 30.0);
+    let p = camera_pointing( &o);
+    assert_close( p.azimuth, 90.0);
+    assert_close( p.elevation, 30.0);
+
+    let e = euler_angles( &o);
+    assert_close( e.yaw, 90.0);
+    assert_close( e.pitch, 30.0);
+    assert_close( e.roll, 0.0);
+
+    let p = camera_pointing( &quat( -45.0, -10.0));
+    assert_close( p.azimuth, 315.0);
+    assert_close( p.elevation, -10.0);
+}
+
+#[test]
+fn test_destination() {
+    let (lat, lon) = destination( 34.0, -118.0, 0.0, 111_194.9);
+    assert!( (lat - 35.0).abs() < 1e-3);
+    assert_close( lon, -118.0);
+
+    let (lat, lon) = destination( 0.0, 0.0, 90.0, 111_194.9);
+    assert_close( lat, 0.0);
+    assert!( (lon - 1.0).abs() < 1e-3);
+}
+
+#[test]
+fn test_detection_rays()->Result<()> {
+    let gps: SensorRecord<GpsData> = serde_json::from_str( &gps_json( "roo7gd1dldn3", "2023-01-29T19:32:04.000Z", 34.0, -118.0))?;
+    let orientation: SensorRecord<OrientationData> = serde_json::from_str( &record_json( SensorCapability::Orientation, "ori-1", "roo7gd1dldn3", 1,
+        "2023-01-29T19:32:59.000Z", r#"{"w":0.7071067811865476,"qx":0.0,"qy":0.0,"qz":0.7071067811865476}"#))?;
+    let image: SensorRecord<ImageData> = serde_json::from_str( &record_json( SensorCapability::Image, "img-1", "roo7gd1dldn3", 0,
+        "2023-01-29T19:33:00.000Z", r#"{"filename":"img-1.webp","isInfrared":false,"orientationRecord":{"id":"ori-1"}}"#))?;
+    let fire: SensorRecord<FireData> = serde_json::from_str( &evidence_record_json( SensorCapability::Fire, "fire-1", "roo7gd1dldn3", 7,
+        "2023-01-29T19:33:01.000Z", &["img-1"], r#"{"fireProb":0.92}"#))?;
+
+    let mut sentinel = Sentinel::new( "roo7gd1dldn3".to_string(), "test-1".to_string());
+    sort_in_record( &mut sentinel.gps, gps);
+    sort_in_record( &mut sentinel.orientation, orientation);
+    sort_in_record( &mut sentinel.image, image);
+    sort_in_record( &mut sentinel.fire, fire);
+
+    let rays = sentinel.detection_rays( &PositionConfig::default(), 1000.0);
+    assert_eq!( rays.len(), 1);
+    let ray = &rays[0];
+    assert_eq!( ray.record_id, "fire-1");
+    assert_eq!( ray.image_id, "img-1");
+    assert_close( ray.pointing.azimuth, 90.0);
+    assert_eq!( ray.origin, (34.0, -118.0));
+    assert!( (ray.end.0 - 34.0).abs() < 1e-4);
+    assert!( ray.end.1 > -118.0);
+    Ok(())
+}
@@ -0,0 +1,72 @@
+use odin_sentinel::{Result,Sentinel,SensorCapability,SensorRecord,GpsData};
+use odin_sentinel::position::{PositionConfig,estimate_position,detect_relocation};
+use odin_sentinel::timeline::{TimeWindow,positions};
+
+mod common;
+use common::record_json;
+
+fn add_fix (sentinel: &mut Sentinel, i: usize, lat: f64, lon: f64, hdop: f64, sats: i32)->Result<()> {
+    let rec: SensorRecord<GpsData> = serde_json::from_str( &record_json( SensorCapability::Gps, &format!("gps-{i}"), "dev", 9,
+        &format!("2023-01-29T19:{i:02}:00.000Z"),
+        &format!(r#"{{"latitude":{lat},"longitude":{lon},"altitude":null,"quality":null,"numberOfSatellites":{sats},"HDOP":{hdop}}}"#)))?;
+    sentinel.add_record( rec);
+    Ok(())
+}
+
+#[test]
+fn test_position_estimate()->Result<()> {
+    let mut sentinel = Sentinel::new( "dev".to_string(), "dev".to_string());
+    let jitter = [0.00002, -0.00001, 0.00001, -0.00002, 0.0];
+    for (i,d) in jitter.iter().enumerate() {
+        add_fix( &mut sentinel, i, 34.0 + d, -118.0 - d, 1.0, 8)?;
+    }
+    add_fix( &mut sentinel, 5, 34.0005, -118.0, 1.0, 8)?; // ~55m outlier
+    add_fix( &mut sentinel, 6, 34.001, -118.001, 5.0, 2)?; // not enough satellites
+
+    let config = PositionConfig::default();
+    let pos = estimate_position( &sentinel, &config).unwrap();
+    assert_eq!( pos.fixes, 5);
+    assert!( (pos.latitude - 34.0).abs() < 1e-6);
+    assert!( (pos.longitude + 118.0).abs() < 1e-6);
+    assert!( pos.accuracy < 5.0);
+    assert_eq!( pos.time_recorded.to_rfc3339(), "2023-01-29T19:04:00+00:00");
+    assert!( detect_relocation( &sentinel, &config).is_none());
+
+    // time-dynamic exports use the estimates, not the raw fixes
+    let window = TimeWindow::new( "2023-01-29T19:02:30Z".parse().unwrap(), "2023-01-29T19:10:00Z".parse().unwrap());
+    let list = positions( &sentinel, &config, &window);
+    assert_eq!( list.len(), 5); // window start and the 4 fixes within the window
+    assert_eq!( list[0].0, window.start);
+    assert_eq!( list.last().unwrap().1, pos);
+    assert!( (list[3].1.latitude - 34.0).abs() < 1e-6); // the outlier fix
+    Ok(())
+}
+
+#[test]
+fn test_relocation()->Result<()> {
+    let mut sentinel = Sentinel::new( "dev".to_string(), "dev".to_string());
+    let config = PositionConfig::default();
+
+    for i in 0..5 {
+        add_fix( &mut sentinel, i, 34.0, -118.0, 1.0, 8)?;
+    }
+    add_fix( &mut sentinel, 5, 34.01, -118.0, 1.0, 8)?;
+    assert!( detect_relocation( &sentinel, &config).is_none()); // not yet confirmed
+    add_fix( &mut sentinel, 6, 34.01, -118.0, 1.0, 8)?;
+    assert!( detect_relocation( &sentinel, &config).is_none());
+
+    add_fix( &mut sentinel, 7, 34.01, -118.0, 1.0, 8)?;
+    let relocation = detect_relocation( &sentinel, &config).unwrap();
+    assert!( (relocation.distance - 1112.0).abs() < 5.0);
+    assert!( (relocation.from.latitude - 34.0).abs() < 1e-9);
+    assert!( (relocation.to.latitude - 34.01).abs() < 1e-9);
+
+    add_fix( &mut sentinel, 8, 34.01, -118.0, 1.0, 8)?;
+    assert!( detect_relocation( &sentinel, &config).is_none()); // only reported once
+    assert!( (sentinel.position( &config).unwrap().latitude - 34.01).abs() < 1e-9);
+
+    // detections before the relocation are located at the old position
+    let before = "2023-01-29T19:04:30Z".parse().unwrap();
+    assert!( (sentinel.position_at( &config, before).unwrap().latitude - 34.0).abs() < 1e-9);
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use chrono::{NaiveDate,NaiveTime};
+use odin_sentinel::{Result,Sentinel,SensorCapability,SensorRecord,PowerData,GpsData};
+use odin_sentinel::power::{PowerConfig,daily_energy,charging_window,runtime_forecast};
+
+mod common;
+use common::{record_json,gps_json};
+
+fn add_power (sentinel: &mut Sentinel, day: u32, hour: u32, solar: f64, load: f64, soc: f64)->Result<()> {
+    let rec: SensorRecord<PowerData> = serde_json::from_str( &record_json( SensorCapability::Power, &format!("pwr-{day}-{hour}"), "dev", 3,
+        &format!("2023-06-{day:02}T{hour:02}:00:00.000Z"), &format!(r#"{{"batteryVoltage":12.8,"batteryCurrent":0.0,"solarVoltage":20.0,"solarCurrent":{},"loadVoltage":12.0,"loadCurrent":{},"soc":{soc},
+        "batteryTemp":293.15,"controllerTemp":298.15,"batteryStatus":"normal","chargingVolatageStatus":"normal","chargingStatus":"normal",
+        "loadVolatageStatus":"normal","loadStatus":"normal"}}"#, solar / 20.0, load / 12.0)))?;
+    sentinel.add_record( rec);
+    Ok(())
+}
+
+fn test_sentinel (last_soc: f64)->Result<Sentinel> {
+    let mut sentinel = Sentinel::new( "dev".to_string(), "dev".to_string());
+    for hour in 0..24 {
+        let solar = if (14..=22).contains( &hour) { 100.0 } else { 0.0 };
+        add_power( &mut sentinel, 1, hour, solar, 20.0, 50.0)?;
+    }
+    for hour in 0..3 {
+        add_power( &mut sentinel, 2, hour, 0.0, 20.0, 40.0)?;
+    }
+    add_power( &mut sentinel, 2, 3, 0.0, 20.0, last_soc)?;
+    Ok(sentinel)
+}
+
+#[test]
+fn test_daily_energy()->Result<()> {
+    let sentinel = test_sentinel( 30.0)?;
+    let days = daily_energy( &sentinel, &PowerConfig::default());
+
+    assert_eq!( days.len(), 2);
+    assert_eq!( days[0].date, NaiveDate::from_ymd_opt( 2023, 6, 1).unwrap());
+    assert!( (days[0].energy_in - 900.0).abs() < 1e-6);
+    assert!( (days[0].energy_out - 480.0).abs() < 1e-6);
+    assert!( (days[0].balance - 420.0).abs() < 1e-6);
+    assert!( (days[1].energy_out - 60.0).abs() < 1e-6);
+
+    let (start, end) = charging_window( &sentinel, &PowerConfig::default()).unwrap();
+    assert_eq!( start, NaiveTime::from_hms_opt( 14, 0, 0).unwrap());
+    assert_eq!( end, NaiveTime::from_hms_opt( 22, 0, 0).unwrap());
+    Ok(())
+}
+
+#[test]
+fn test_local_days()->Result<()> {
+    // solar charging from 07:00 to 15:00 local time (UTC-7) must not be split across two days
+    let mut sentinel = test_sentinel( 30.0)?;
+    let config = PowerConfig { utc_offset: Some(-7 * 3600), ..PowerConfig::default() };
+    let days = daily_energy( &sentinel, &config);
+
+    assert_eq!( days.len(), 2);
+    assert_eq!( days[0].date, NaiveDate::from_ymd_opt( 2023, 5, 31).unwrap());
+    assert!( (days[0].energy_in).abs() < 1e-6 && (days[0].energy_out - 140.0).abs() < 1e-6);
+    assert!( (days[1].energy_in - 900.0).abs() < 1e-6 && (days[1].energy_out - 400.0).abs() < 1e-6);
+
+    // without a configured offset we use the solar time of the sentinel longitude
+    let gps: SensorRecord<GpsData> = serde_json::from_str( &gps_json( "dev", "2023-06-01T00:00:00.000Z", 37.0, -105.0))?;
+    sentinel.add_record( gps);
+    assert_eq!( daily_energy( &sentinel, &PowerConfig::default()), days);
+    Ok(())
+}
+
+#[test]
+fn test_overnight_forecast()->Result<()> {
+    let config = PowerConfig::default(); // 1200Wh, shutdown at 10%
+
+    // 240Wh left, 11h until charging resumes at 20W
+    let forecast = runtime_forecast( &test_sentinel( 30.0)?, &config).unwrap();
+    assert!( (forecast.remaining_energy - 240.0).abs() < 1e-6);
+    assert!( (forecast.load_power - 20.0).abs() < 1e-6);
+    assert!( (forecast.time_to_empty.unwrap().as_secs_f64() - 12.0 * 3600.0).abs() < 1.0);
+    assert_eq!( forecast.charging_resumes.unwrap().to_rfc3339(), "2023-06-02T14:00:00+00:00");
+    assert!( !forecast.overnight_shutdown);
+
+    // 180Wh only last 9h
+    let forecast = runtime_forecast( &test_sentinel( 25.0)?, &config).unwrap();
+    assert!( forecast.overnight_shutdown);
+    Ok(())
+}
@@ -0,0 +1,87 @@
+use odin_sentinel::{Result,Sentinel,SentinelStore,SentinelUpdate,SensorCapability,SensorRecord,ImageData,PersonData};
+use odin_sentinel::privacy::{PrivacyFilter,PrivacyPolicy,PersonPolicy,REDACTED_FILENAME};
+
+mod common;
+use common::{record_json,evidence_record_json};
+
+fn image (id: &str, time: &str)->Result<SensorRecord<ImageData>> {
+    Ok( serde_json::from_str( &record_json( SensorCapability::Image, id, "dev", 0, time,
+                                            &format!(r#"{{"filename":"{id}.webp","isInfrared":false,"orientationRecord":null}}"#)))?)
+}
+
+fn person (id: &str, prob: f64, evidence: &str)->Result<SensorRecord<PersonData>> {
+    Ok( serde_json::from_str( &evidence_record_json( SensorCapability::Person, id, "dev", 7, "2023-01-29T19:33:01.000Z", &[evidence],
+                                                     &format!(r#"{{"personProb":{prob}}}"#)))?)
+}
+
+#[test]
+fn test_redact()->Result<()> {
+    let mut filter = PrivacyFilter::new( PrivacyPolicy { persons: PersonPolicy::Redact, min_person_prob: 0.5 });
+    let mut sentinel = Sentinel::new( "dev".to_string(), "dev".to_string());
+    sentinel.add_record( image( "img-1", "2023-01-29T19:33:00.000Z")?);
+    sentinel.add_record( image( "img-2", "2023-01-29T19:33:00.000Z")?);
+
+    // low probability person records are not filtered
+    let updates = filter.filter_update( person( "p-0", 0.2, "img-2")?.into(), &sentinel);
+    assert_eq!( updates.len(), 1);
+    for update in updates { update.sort_into( &mut sentinel) }
+    assert_eq!( sentinel.image.iter().filter( |r| r.data.filename == REDACTED_FILENAME).count(), 0);
+
+    // the already published image is replaced by a redacted version
+    let updates = filter.filter_update( person( "p-1", 0.9, "img-1")?.into(), &sentinel);
+    assert_eq!( updates.len(), 2);
+    assert!( matches!( &updates[0], SentinelUpdate::Image(img) if img.id == "img-1" && img.data.filename == REDACTED_FILENAME));
+    assert!( matches!( updates[1], SentinelUpdate::Person(_)));
+    for update in updates { update.sort_into( &mut sentinel) }
+
+    assert_eq!( sentinel.image.len(), 2);
+    let img = sentinel.image.iter().find( |r| r.id == "img-1").unwrap();
+    assert_eq!( img.data.filename, REDACTED_FILENAME);
+    assert_eq!( sentinel.image.iter().find( |r| r.id == "img-2").unwrap().data.filename, "img-2.webp");
+    assert_eq!( sentinel.person.len(), 2);
+    Ok(())
+}
+
+#[test]
+fn test_suppress()->Result<()> {
+    let policy = PrivacyPolicy { persons: PersonPolicy::Suppress, min_person_prob: 0.5 };
+    let mut filter = PrivacyFilter::new( policy.clone());
+    let mut sentinel = Sentinel::new( "dev".to_string(), "dev".to_string());
+    sentinel.add_record( image( "img-1", "2023-01-29T19:33:00.000Z")?);
+
+    // we can't retract the already published image, but we can redact it
+    let updates = filter.filter_update( person( "p-1", 0.9, "img-1")?.into(), &sentinel);
+    assert_eq!( updates.len(), 1);
+    for update in updates { update.sort_into( &mut sentinel) }
+    assert!( sentinel.person.is_empty());
+    assert_eq!( sentinel.image.iter().map( |r| r.data.filename.as_str()).collect::<Vec<_>>(), vec![REDACTED_FILENAME]);
+
+    // images that show up late are suppressed
+    assert!( filter.filter_update( image( "img-1", "2023-01-29T19:33:00.000Z")?.into(), &sentinel).is_empty());
+    assert_eq!( filter.filter_update( image( "img-3", "2023-01-29T19:34:00.000Z")?.into(), &sentinel).len(), 1);
+
+    // initial data
+    let mut store = SentinelStore::new();
+    store.add_json_record( &serde_json::to_string( &image( "img-1", "2023-01-29T19:33:00.000Z")?)?)?;
+    store.add_json_record( &serde_json::to_string( &person( "p-1", 0.9, "img-1")?)?)?;
+    PrivacyFilter::new( policy).filter_store( &mut store);
+    let sentinel = store.get( &"dev".to_string()).unwrap();
+    assert!( sentinel.person.is_empty());
+    assert!( sentinel.image.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_json_records()->Result<()> {
+    let mut records = vec![
+        (SensorCapability::Image, vec![ serde_json::to_value( image( "img-1", "2023-01-29T19:33:00.000Z")?)?,
+                                        serde_json::to_value( image( "img-2", "2023-01-29T19:33:00.000Z")?)?]),
+        (SensorCapability::Person, vec![ serde_json::to_value( person( "p-1", 0.9, "img-1")?)?]),
+    ];
+    PrivacyFilter::new( PrivacyPolicy::redact_all()).filter_json_records( &mut records);
+
+    assert_eq!( records[0].1[0]["image"]["filename"], REDACTED_FILENAME);
+    assert_eq!( records[0].1[1]["image"]["filename"], "img-2.webp");
+    assert_eq!( records[1].1.len(), 1);
+    Ok(())
+}
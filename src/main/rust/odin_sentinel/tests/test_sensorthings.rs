@@ -0,0 +1,69 @@
+use serde_json::Value;
+use odin_sentinel::{Result,SentinelStore,SensorCapability};
+use odin_sentinel::sensorthings::{Query,SensorThingsOpts,resolve};
+
+mod common;
+use common::{record_json,gps_json};
+
+fn test_store ()->Result<SentinelStore> {
+    let mut store = SentinelStore::new();
+    store.add_json_record( &gps_json( "roo7gd1dldn3", "2023-01-29T19:32:04.000Z", 34.16381345, -118.10208433333334))?;
+    for (id,time,prob) in [("fire-1","2023-01-29T19:33:01.000Z",0.12), ("fire-2","2023-01-29T19:34:01.000Z",0.52), ("fire-3","2023-01-29T19:35:01.000Z",0.92)] {
+        store.add_json_record( &record_json( SensorCapability::Fire, id, "roo7gd1dldn3", 7, time, &format!(r#"{{"fireProb":{prob}}}"#)))?;
+    }
+    Ok(store)
+}
+
+fn get (store: &SentinelStore, path: &str, query: &str)->Result<Option<Value>> {
+    resolve( store, &SensorThingsOpts::default(), path, &Query::parse( query)?)
+}
+
+fn ids (v: &Value)->Vec<&str> {
+    v["value"].as_array().unwrap().iter().map( |e| e["@iot.id"].as_str().unwrap()).collect()
+}
+
+#[test]
+fn test_entities()->Result<()> {
+    let store = test_store()?;
+
+    let things = get( &store, "Things", "")?.unwrap();
+    assert_eq!( ids( &things), vec!["roo7gd1dldn3"]);
+
+    let loc = get( &store, "Things('roo7gd1dldn3')/Locations", "")?.unwrap();
+    assert_eq!( loc["value"][0]["location"]["coordinates"][1].as_f64(), Some(34.16381345));
+
+    let ds = get( &store, "Things('roo7gd1dldn3')/Datastreams", "")?.unwrap();
+    assert_eq!( ids( &ds), vec!["roo7gd1dldn3.7.fire", "roo7gd1dldn3.9.gps"]);
+
+    let sensor = get( &store, "Datastreams('roo7gd1dldn3.7.fire')/Sensor", "")?.unwrap();
+    assert_eq!( sensor["@iot.id"], "roo7gd1dldn3.7");
+
+    assert!( get( &store, "Things('unknown')", "")?.is_none());
+    assert!( get( &store, "Datastreams('roo7gd1dldn3.7.smoke')", "")?.is_none());
+    Ok(())
+}
+
+#[test]
+fn test_observation_queries()->Result<()> {
+    let store = test_store()?;
+    let path = "Datastreams('roo7gd1dldn3.7.fire')/Observations";
+
+    let obs = get( &store, path, "")?.unwrap();
+    assert_eq!( ids( &obs), vec!["fire-3", "fire-2", "fire-1"]);
+    assert_eq!( obs["value"][0]["result"]["fireProb"].as_f64(), Some(0.92));
+
+    let obs = get( &store, path, "$orderby=phenomenonTime%20asc&$top=2&$count=true")?.unwrap();
+    assert_eq!( ids( &obs), vec!["fire-1", "fire-2"]);
+    assert_eq!( obs["@iot.count"], 3);
+    assert!( obs["@iot.nextLink"].as_str().unwrap().contains("%24skip=2"));
+
+    let obs = get( &store, path, "$filter=phenomenonTime%20gt%202023-01-29T19:33:30Z%20and%20phenomenonTime%20lt%202023-01-29T19:35:00Z")?.unwrap();
+    assert_eq!( ids( &obs), vec!["fire-2"]);
+
+    let obs = get( &store, "Observations('fire-2')/Datastream", "")?.unwrap();
+    assert_eq!( obs["@iot.id"], "roo7gd1dldn3.7.fire");
+
+    assert!( get( &store, "Things", "$orderby=phenomenonTime").is_err());
+    assert!( Query::parse( "$filter=name%20eq%20'x'").is_err());
+    Ok(())
+}
@@ -0,0 +1,105 @@
+#![cfg(feature="sqlite")]
+
+use std::time::Duration;
+use chrono::{DateTime,Utc};
+use odin_sentinel::{Result,SensorCapability,SensorRecord,VocData,ImageData,ValveData};
+use odin_sentinel::storage::{SentinelStorage,SqliteStorage,SqliteStorageConfig};
+
+mod common;
+use common::{record_json,sample_record};
+
+fn voc_record (id: &str, time: &str, tvoc: i32)->Result<SensorRecord<VocData>> {
+    let json = record_json( SensorCapability::Voc, id, "roo7gd1dldn3", 39, time, &format!(r#"{{"tvoc":{tvoc},"e_co2":489}}"#));
+    Ok(serde_json::from_str(&json)?)
+}
+
+#[test]
+fn test_sqlite_roundtrip()->Result<()> {
+    let config = SqliteStorageConfig { path: "".into(), retention: None, purge_interval: Duration::from_secs(3600) };
+    let mut storage: Box<dyn SentinelStorage> = Box::new( SqliteStorage::open_in_memory( config)?);
+
+    storage.store( &voc_record( "r2", "2024-01-23T20:33:01.000Z", 140)?)?;
+    storage.store( &voc_record( "r1", "2024-01-23T20:32:01.000Z", 138)?)?;
+    storage.store( &voc_record( "r1", "2024-01-23T20:32:01.000Z", 138)?)?; // duplicates are ignored
+    storage.store( &voc_record( "r0", "2024-01-22T20:32:01.000Z", 130)?)?;
+
+    let start: DateTime<Utc> = "2024-01-23T00:00:00Z".parse().unwrap();
+    let end: DateTime<Utc> = "2024-01-24T00:00:00Z".parse().unwrap();
+    let recs = storage.query::<VocData>( "roo7gd1dldn3", Some(39), start, end)?;
+    assert_eq!( recs.len(), 2);
+    assert_eq!( recs[0].id.as_str(), "r1"); // ascending time order
+    assert_eq!( recs[1].data.tvoc, 140);
+
+    assert!( storage.query::<VocData>( "roo7gd1dldn3", Some(1), start, end)?.is_empty());
+
+    assert_eq!( storage.remove_older_than( start)?, 1);
+    Ok(())
+}
+
+#[test]
+fn test_sqlite_columns()->Result<()> {
+    let path = std::env::temp_dir().join( format!("odin_sentinel_test_{}.db", std::process::id()));
+    let config = SqliteStorageConfig { path: path.clone(), retention: None, purge_interval: Duration::from_secs(3600) };
+    {
+        let mut storage: Box<dyn SentinelStorage> = Box::new( SqliteStorage::open( config)?);
+        storage.store( &voc_record( "r1", "2024-01-23T20:32:01.000Z", 138)?)?;
+        storage.store( &voc_record( "r2", "2024-01-23T20:33:01.000Z", 140)?)?;
+    } // dropping the storage waits for the writer thread
+
+    // the archive can be queried without going through SentinelStorage
+    let conn = rusqlite::Connection::open( &path)?;
+    let n: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM voc WHERE device_id = ?1 AND sensor_no = ?2 AND time_recorded > ?3 AND tvoc >= 140",
+        rusqlite::params![ "roo7gd1dldn3", 39, "2024-01-23T20:32:30.000Z".parse::<DateTime<Utc>>().unwrap().timestamp_millis()],
+        |row| row.get(0)
+    )?;
+    assert_eq!( n, 1);
+
+    drop( conn);
+    std::fs::remove_file( &path).ok();
+    Ok(())
+}
+
+#[test]
+fn test_sqlite_payload_types()->Result<()> {
+    let config = SqliteStorageConfig { path: "".into(), retention: None, purge_interval: Duration::from_secs(3600) };
+    let mut storage: Box<dyn SentinelStorage> = Box::new( SqliteStorage::open_in_memory( config)?);
+
+    let valve: SensorRecord<ValveData> = serde_json::from_str( &sample_record( SensorCapability::Valve, 1))?;
+    let image: SensorRecord<ImageData> = serde_json::from_str( &sample_record( SensorCapability::Image, 2))?; // no orientation record
+    let oriented: SensorRecord<ImageData> = serde_json::from_str( &record_json( SensorCapability::Image, "rec-3", "roo7gd1dldn3", 2,
+        "2024-01-23T20:03:00.000Z", r#"{"filename":"img-3.webp","isInfrared":true,"orientationRecord":{"id":"ori-1"}}"#))?;
+    storage.store( &valve)?;
+    storage.store( &image)?;
+    storage.store( &oriented)?;
+
+    let start: DateTime<Utc> = "2024-01-23T00:00:00Z".parse().unwrap();
+    let end: DateTime<Utc> = "2024-01-24T00:00:00Z".parse().unwrap();
+    let valves = storage.query::<ValveData>( "roo7gd1dldn3", None, start, end)?;
+    assert_eq!( serde_json::to_string( &valves[0])?, serde_json::to_string( &valve)?);
+
+    let images = storage.query::<ImageData>( "roo7gd1dldn3", Some(2), start, end)?;
+    assert_eq!( images.len(), 2);
+    assert_eq!( serde_json::to_string( &images[0])?, serde_json::to_string( &image)?);
+    assert_eq!( serde_json::to_string( &images[1])?, serde_json::to_string( &oriented)?);
+    Ok(())
+}
+
+#[test]
+fn test_sqlite_idle_purge()->Result<()> {
+    let path = std::env::temp_dir().join( format!("odin_sentinel_test_purge_{}.db", std::process::id()));
+    let config = SqliteStorageConfig { path: path.clone(), retention: Some( Duration::from_secs(3600)), purge_interval: Duration::from_secs(1) };
+    let mut storage: Box<dyn SentinelStorage> = Box::new( SqliteStorage::open( config)?);
+    storage.store( &voc_record( "r1", "2024-01-23T20:32:01.000Z", 138)?)?;
+
+    // nothing else is stored, the writer still has to expire the record
+    std::thread::sleep( Duration::from_millis(2500));
+    let conn = rusqlite::Connection::open( &path)?;
+    let n: i64 = conn.query_row( "SELECT COUNT(*) FROM voc", [], |row| row.get(0))?;
+    assert_eq!( n, 0);
+
+    drop( conn);
+    drop( storage);
+    std::fs::remove_file( &path).ok();
+    Ok(())
+}
@@ -0,0 +1,186 @@
+use odin_sentinel::{Result,SentinelStore,Sentinel,SensorRecord,VocData,FireData,sort_in_record,new_records,DeviceFilter,SensorCapability,SentinelConfig,Device,namespaced_device_id,split_device_id,matches_pattern};
+use odin_sentinel::{DeviceEvent,SentinelUpdate};
+use odin_sentinel::ws::WsCmd;
+
+mod common;
+use common::{StubStorage,record_json};
+
+#[test]
+fn test_namespaced_device_ids()->Result<()> {
+    let id = namespaced_device_id( "calfire", "roo7gd1dldn3");
+    assert_eq!( id.as_str(), "calfire:roo7gd1dldn3");
+    assert_eq!( split_device_id( &id), ("calfire", "roo7gd1dldn3"));
+
+    // the empty source id leaves device ids alone
+    assert_eq!( namespaced_device_id( "", "roo7gd1dldn3").as_str(), "roo7gd1dldn3");
+    assert_eq!( split_device_id( "roo7gd1dldn3"), ("", "roo7gd1dldn3"));
+
+    let mut store = SentinelStore::new();
+    store.insert( "roo7gd1dldn3".to_string(), Sentinel::new( "roo7gd1dldn3".to_string(), "test-1".to_string()));
+    let store = store.into_namespaced( "calfire");
+    assert!( store.get( &"calfire:roo7gd1dldn3".to_string()).is_some());
+    assert_eq!( store.get_source_device_ids( "calfire"), vec!["roo7gd1dldn3".to_string()]);
+    assert!( store.get_source_device_ids( "").is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_cmd_device_ids()->Result<()> {
+    let cmd = WsCmd::SwitchValve { device_ids: vec!["a:1".to_string(), "b:2".to_string()], state: "on".to_string(), message_id: "42".to_string() };
+    let cmd1 = cmd.with_device_ids( vec!["1".to_string()]);
+    assert_eq!( cmd1.device_ids(), Some(&vec!["1".to_string()]));
+    assert!( WsCmd::new_ping("ping").device_ids().is_none());
+    Ok(())
+}
+
+#[test]
+fn test_device_filter()->Result<()> {
+    assert!( matches_pattern( "roo7*", "roo7gd1dldn3"));
+    assert!( matches_pattern( "*dn3", "roo7gd1dldn3"));
+    assert!( matches_pattern( "r*gd*3", "roo7gd1dldn3"));
+    assert!( matches_pattern( "roo7gd1dldn3", "roo7gd1dldn3"));
+    assert!( !matches_pattern( "roo7", "roo7gd1dldn3"));
+    assert!( !matches_pattern( "a*a", "a"));
+
+    let filter = DeviceFilter { 
+        include: vec!["roo*".to_string()], 
+        exclude: vec!["*test*".to_string()], 
+        capabilities: vec![SensorCapability::Fire, SensorCapability::Smoke] 
+    };
+    assert!( filter.accepts_device( "roo7gd1dldn3"));
+    assert!( !filter.accepts_device( "roo-test-1"));
+    assert!( !filter.accepts_device( "xyz"));
+    assert!( filter.accepts_capability( SensorCapability::Smoke));
+    assert!( !filter.accepts_capability( SensorCapability::Gps));
+
+    let filter = DeviceFilter::default();
+    assert!( filter.accepts_device( "xyz"));
+    assert!( filter.accepts_capability( SensorCapability::Gps));
+    Ok(())
+}
+
+#[test]
+fn test_device_names()->Result<()> {
+    let config: SentinelConfig = ron::from_str( r#"(
+        base_uri: "https://delphire.io/api", ws_uri: "wss://delphire.io/websocket", access_token: "x",
+        max_history_len: 10, max_age: (secs: 3600, nanos: 0), ping_interval: None,
+        device_names: { "roo7gd1dldn3": "test-1" }
+    )"#)?;
+
+    let device = |id: &str, info: Option<&str>| Device { id: id.to_string(), info: info.map( |s| s.to_string()) };
+    assert_eq!( config.device_name( &device( "roo7gd1dldn3", Some("info"))), "test-1");
+    assert_eq!( config.device_name( &device( "xyz", Some("info"))), "info");
+    assert_eq!( config.device_name( &device( "xyz", None)), "xyz"); // fall back to device id
+    Ok(())
+}
+
+#[test]
+fn test_store_roundtrip()->Result<()> {
+    let input = r#"{"id":"eYdrMhE4b55MO87oJF9r","timeRecorded":"2024-01-23T20:32:01.004Z","sensorNo":39,"deviceId":"roo7gd1dldn3","evidences":[],"claims":[],"voc":{"tvoc":138,"e_co2":489}}"#;
+    let rec: SensorRecord<VocData> = serde_json::from_str(input)?;
+
+    let mut sentinel = Sentinel::new( "roo7gd1dldn3".to_string(), "test-1".to_string());
+    sort_in_record( &mut sentinel.voc, rec);
+    let mut store = SentinelStore::new();
+    store.insert( sentinel.device_id.clone(), sentinel);
+
+    let json = store.to_json(false)?;
+    let store1 = SentinelStore::from_json( &json)?;
+    assert_eq!( store1.to_json(false)?, json);
+
+    let ron = store.to_ron(false)?;
+    let store2 = SentinelStore::from_ron( &ron)?;
+    assert_eq!( store2.to_json(false)?, json);
+
+    let sentinel = store2.get( &"roo7gd1dldn3".to_string()).unwrap();
+    assert_eq!( sentinel.voc.len(), 1);
+    assert_eq!( sentinel.voc[0].data.tvoc, 138);
+    Ok(())
+}
+
+#[test]
+fn test_generic_record_access()->Result<()> {
+    let mut sentinel = Sentinel::new( "roo7gd1dldn3".to_string(), "test".to_string());
+    for (id,time,prob) in [("f1","2024-01-23T20:31:00Z",0.1), ("f3","2024-01-23T20:33:00Z",0.9), ("f2","2024-01-23T20:32:00Z",0.5)] {
+        let json = record_json( SensorCapability::Fire, id, "roo7gd1dldn3", 7, time, &format!(r#"{{"fireProb":{prob}}}"#));
+        let rec: SensorRecord<FireData> = serde_json::from_str( &json)?;
+        sentinel.add_record( rec);
+    }
+    let voc: SensorRecord<VocData> = serde_json::from_str( &record_json( SensorCapability::Voc, "v1", "roo7gd1dldn3", 39, "2024-01-23T20:31:30Z",
+                                                                        r#"{"TVOC":138,"eCO2":489}"#))?;
+    sentinel.add_record( voc);
+
+    assert_eq!( sentinel.records::<FireData>().len(), 3);
+    assert_eq!( sentinel.latest::<FireData>().map( |r| r.id.as_str()), Some("f3"));
+    assert!( sentinel.latest::<VocData>().is_some());
+
+    let start = "2024-01-23T20:31:30Z".parse().unwrap();
+    let end = "2024-01-23T20:32:30Z".parse().unwrap();
+    let recs = sentinel.records_in::<FireData>( start..end);
+    assert_eq!( recs.len(), 1);
+    assert_eq!( recs[0].id.as_str(), "f2");
+
+    let ids: Vec<String> = sentinel.iter_all().map( |u| u.record_id().to_string()).collect();
+    assert_eq!( ids, vec!["f1", "v1", "f2", "f3"]);
+    Ok(())
+}
+
+#[test]
+fn test_device_changes()->Result<()> {
+    let storage = StubStorage::default();
+    let mut store = SentinelStore::new();
+    store.set_storage( Box::new( storage.clone()));
+    store.insert( "calfire:dev-1".to_string(), Sentinel::new( "calfire:dev-1".to_string(), "dev-1".to_string()));
+
+    let event = store.add_device( Sentinel::new( "calfire:dev-2".to_string(), "dev-2".to_string()));
+    assert!( matches!( event, DeviceEvent::Added(ref id) if id == "calfire:dev-2"));
+    let mut ids = store.get_source_device_ids( "calfire");
+    ids.sort();
+    assert_eq!( ids, vec!["dev-1".to_string(), "dev-2".to_string()]);
+
+    // records of new devices go through to the storage
+    let voc: SensorRecord<VocData> = serde_json::from_str( &record_json( SensorCapability::Voc, "v1", "calfire:dev-2", 39, "2024-01-23T20:31:30Z",
+                                                                        r#"{"TVOC":138,"eCO2":489}"#))?;
+    store.write_through( &SentinelUpdate::from( voc))?;
+    assert_eq!( storage.record_ids(), vec!["v1".to_string()]);
+
+    let now = chrono::Utc::now();
+    let event = store.retire_device( &"calfire:dev-1".to_string(), now);
+    assert!( matches!( event, Some(DeviceEvent::Removed(ref id)) if id == "calfire:dev-1"));
+    assert!( store.retire_device( &"calfire:dev-1".to_string(), now).is_none()); // only reported once
+    assert!( store.retire_device( &"calfire:dev-3".to_string(), now).is_none());
+    assert_eq!( store.get_source_device_ids( "calfire"), vec!["dev-2".to_string()]);
+    assert_eq!( store.get( &"calfire:dev-1".to_string()).unwrap().retired, Some(now));
+
+    // a retired device that is reported again gets replaced
+    store.add_device( Sentinel::new( "calfire:dev-1".to_string(), "dev-1".to_string()));
+    assert_eq!( store.get_source_device_ids( "calfire").len(), 2);
+    Ok(())
+}
+
+#[test]
+fn test_resume_points()->Result<()> {
+    let voc = |id: &str, time: &str|->Result<SensorRecord<VocData>> {
+        Ok( serde_json::from_str( &record_json( SensorCapability::Voc, id, "calfire:dev-1", 39, time, r#"{"TVOC":138,"eCO2":489}"#))?)
+    };
+
+    let mut sentinel = Sentinel::new( "calfire:dev-1".to_string(), "dev-1".to_string());
+    sentinel.add_record( voc( "v1", "2024-01-23T20:31:00Z")?);
+    sentinel.add_record( voc( "v2", "2024-01-23T20:32:00Z")?);
+    let mut store = SentinelStore::new();
+    store.insert( sentinel.device_id.clone(), sentinel);
+    store.insert( "calfire:dev-2".to_string(), Sentinel::new( "calfire:dev-2".to_string(), "dev-2".to_string())); // no records yet
+
+    let resume = store.resume_points( "calfire");
+    assert_eq!( resume.len(), 1);
+    assert_eq!( resume.get( "dev-1"), Some(&"2024-01-23T20:32:00Z".parse().unwrap()));
+    assert!( store.resume_points( "").is_empty());
+
+    // records we get from a resumed query might overlap with the ones we have
+    let sentinel = store.get( &"calfire:dev-1".to_string()).unwrap();
+    let recs = [ voc( "v3", "2024-01-23T20:33:00Z")?, voc( "v2", "2024-01-23T20:32:00Z")? ].into_iter().collect();
+    let new_recs = new_records( &sentinel.voc, recs);
+    assert_eq!( new_recs.len(), 1);
+    assert_eq!( new_recs[0].id.as_str(), "v3");
+    Ok(())
+}
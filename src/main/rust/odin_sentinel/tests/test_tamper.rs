@@ -0,0 +1,64 @@
+use odin_sentinel::{Result,SentinelUpdate,SensorCapability,SensorRecord,AccelerometerData,GyroscopeData,MagnetometerData};
+use odin_sentinel::tamper::{TamperConfig,TamperDetector,TamperKind};
+
+mod common;
+use common::record_json;
+
+fn accel (i: usize, ax: f32, ay: f32, az: f32)->Result<SentinelUpdate> {
+    let rec: SensorRecord<AccelerometerData> = serde_json::from_str( &record_json( SensorCapability::Accelerometer, &format!("acc-{i}"), "dev", 6,
+        &format!("2023-01-29T19:{i:02}:00.000Z"), &format!(r#"{{"ax":{ax},"ay":{ay},"az":{az}}}"#)))?;
+    Ok( rec.into())
+}
+
+fn mag (i: usize, mx: f64, my: f64, mz: f64)->Result<SentinelUpdate> {
+    let rec: SensorRecord<MagnetometerData> = serde_json::from_str( &record_json( SensorCapability::Magnetometer, &format!("mag-{i}"), "dev", 6,
+        &format!("2023-01-29T19:{i:02}:30.000Z"), &format!(r#"{{"mx":{mx},"my":{my},"mz":{mz}}}"#)))?;
+    Ok( rec.into())
+}
+
+fn gyro (i: usize, gz: f64)->Result<SentinelUpdate> {
+    let rec: SensorRecord<GyroscopeData> = serde_json::from_str( &record_json( SensorCapability::Gyroscope, &format!("gyr-{i}"), "dev", 6,
+        &format!("2023-01-29T19:{i:02}:45.000Z"), &format!(r#"{{"gx":0.0,"gy":0.0,"gz":{gz}}}"#)))?;
+    Ok( rec.into())
+}
+
+#[test]
+fn test_tilt_and_shock()->Result<()> {
+    let mut detector = TamperDetector::new( TamperConfig::default());
+
+    for i in 0..6 {
+        assert!( detector.process( &accel( i, 0.0, 0.05, 9.81)?).is_empty());
+    }
+    assert!( detector.process( &gyro( 6, 5.0)?).is_empty());
+
+    let events = detector.process( &accel( 7, 0.0, 6.94, 6.94)?); // tipped 45 deg
+    assert_eq!( events.len(), 1);
+    assert!( matches!( events[0].kind, TamperKind::Tilt{angle} if (angle - 45.0).abs() < 1.0));
+    assert_eq!( events[0].record_id, "acc-7");
+    assert!( detector.process( &accel( 8, 0.0, 6.94, 6.94)?).is_empty()); // only reported once
+
+    let events = detector.process( &accel( 9, 0.0, 0.05, 9.81)?);
+    assert!( matches!( events[0].kind, TamperKind::Upright{..}));
+
+    let events = detector.process( &accel( 10, 0.0, 0.0, 25.0)?);
+    assert!( matches!( events[0].kind, TamperKind::Shock{magnitude} if magnitude > 1.0));
+
+    let events = detector.process( &gyro( 11, 180.0)?);
+    assert!( matches!( events[0].kind, TamperKind::Rotation{rate} if rate == 180.0));
+    Ok(())
+}
+
+#[test]
+fn test_heading_change()->Result<()> {
+    let mut detector = TamperDetector::new( TamperConfig::default());
+    for i in 0..6 {
+        detector.process( &accel( i, 0.0, 0.0, 9.81)?);
+        assert!( detector.process( &mag( i, 30.0, 0.0, -40.0)?).is_empty());
+    }
+
+    let events = detector.process( &mag( 6, 0.0, 30.0, -40.0)?); // rotated 90 deg around the vertical axis
+    assert_eq!( events.len(), 1);
+    assert!( matches!( events[0].kind, TamperKind::HeadingChange{delta} if (delta.abs() - 90.0).abs() < 1e-6));
+    assert!( detector.process( &mag( 7, 0.0, 30.0, -40.0)?).is_empty());
+    Ok(())
+}
@@ -0,0 +1,65 @@
+use odin_sentinel::{Result,SentinelStore,SensorCapability};
+use odin_sentinel::triangulation::{TriangulationConfig,triangulate};
+
+mod common;
+use common::{record_json,evidence_record_json,gps_json};
+
+fn add_sentinel (store: &mut SentinelStore, device_id: &str, lon: f64, qz: f64, time: &str)->Result<()> {
+    let img_id = format!("img-{device_id}");
+    store.add_json_record( &gps_json( device_id, "2023-01-29T19:30:00.000Z", 34.0, lon))?;
+    store.add_json_record( &record_json( SensorCapability::Orientation, &format!("ori-{device_id}"), device_id, 1, time,
+                                         &format!(r#"{{"w":0.9238795325112867,"qx":0.0,"qy":0.0,"qz":{qz}}}"#)))?;
+    store.add_json_record( &record_json( SensorCapability::Image, &img_id, device_id, 0, time,
+                                         &format!(r#"{{"filename":"{img_id}.webp","isInfrared":false,"orientationRecord":{{"id":"ori-{device_id}"}}}}"#)))?;
+    store.add_json_record( &evidence_record_json( SensorCapability::Fire, &format!("fire-{device_id}"), device_id, 7, time, &[&img_id],
+                                                  r#"{"fireProb":0.9}"#))?;
+    Ok(())
+}
+
+#[test]
+fn test_triangulation()->Result<()> {
+    let mut store = SentinelStore::new();
+    add_sentinel( &mut store, "west", -118.0, 0.3826834323650898, "2023-01-29T19:33:00.000Z")?;  // pointing NE
+    add_sentinel( &mut store, "east", -117.9, -0.3826834323650898, "2023-01-29T19:34:00.000Z")?; // pointing NW
+
+    let config = TriangulationConfig::default();
+    let loc = triangulate( &config, &store, &"east".to_string(), "fire-east").unwrap();
+
+    assert!( (loc.longitude - -117.95).abs() < 1e-3);
+    assert!( (loc.latitude - 34.0415).abs() < 1e-3);
+    assert_eq!( loc.device_ids.len(), 2);
+    assert!( loc.detections.contains( &"fire-west".to_string()));
+    assert_eq!( loc.time_recorded.to_rfc3339(), "2023-01-29T19:34:00+00:00");
+
+    // symmetric geometry with perpendicular bearings gives a circular error region
+    let ellipse = loc.error_ellipse;
+    assert!( ellipse.semi_major > 0.0 && (ellipse.semi_major - ellipse.semi_minor).abs() / ellipse.semi_major < 0.01);
+
+    // detections outside of the time window are not combined
+    let config = TriangulationConfig { time_window: std::time::Duration::from_secs(30), ..TriangulationConfig::default() };
+    assert!( triangulate( &config, &store, &"east".to_string(), "fire-east").is_none());
+    Ok(())
+}
+
+#[test]
+fn test_missing_evidence()->Result<()> {
+    let mut store = SentinelStore::new();
+    add_sentinel( &mut store, "west", -118.0, 0.3826834323650898, "2023-01-29T19:33:00.000Z")?;
+
+    // the fire record of "east" arrives before its evidence image
+    store.add_json_record( &gps_json( "east", "2023-01-29T19:30:00.000Z", 34.0, -117.9))?;
+    store.add_json_record( &record_json( SensorCapability::Orientation, "ori-east", "east", 1, "2023-01-29T19:34:00.000Z",
+                                         r#"{"w":0.9238795325112867,"qx":0.0,"qy":0.0,"qz":-0.3826834323650898}"#))?;
+    store.add_json_record( &evidence_record_json( SensorCapability::Fire, "fire-east", "east", 7, "2023-01-29T19:34:30.000Z", &["img-east"],
+                                                  r#"{"fireProb":0.9}"#))?;
+
+    let config = TriangulationConfig::default();
+    assert!( triangulate( &config, &store, &"east".to_string(), "fire-east").is_none());
+
+    store.add_json_record( &record_json( SensorCapability::Image, "img-east", "east", 0, "2023-01-29T19:34:00.000Z",
+                                         r#"{"filename":"img-east.webp","isInfrared":false,"orientationRecord":{"id":"ori-east"}}"#))?;
+    let loc = triangulate( &config, &store, &"east".to_string(), "fire-east").unwrap();
+    assert_eq!( loc.device_ids.len(), 2);
+    assert_eq!( loc.time_recorded.to_rfc3339(), "2023-01-29T19:34:30+00:00"); // the fire record time, not the image time
+    Ok(())
+}
@@ -0,0 +1,87 @@
+use std::time::Duration;
+use odin_sentinel::{Result,SentinelStore,SensorCapability};
+use odin_sentinel::wind::{WindConfig,smoke_source};
+
+mod common;
+use common::{record_json,gps_json};
+
+fn anemometer (id: &str, device_id: &str, time: &str, angle: f64, speed: f64)->String {
+    record_json( SensorCapability::Anemometer, id, device_id, 8, time, &format!(r#"{{"angle":{angle},"speed":{speed}}}"#))
+}
+
+fn gps (device_id: &str, lat: f64, lon: f64)->String {
+    gps_json( device_id, "2023-01-29T19:00:00.000Z", lat, lon)
+}
+
+fn smoke (id: &str, device_id: &str, time: &str, prob: f64)->String {
+    record_json( SensorCapability::Smoke, id, device_id, 7, time, &format!(r#"{{"smokeProb":{prob}}}"#))
+}
+
+#[test]
+fn test_wind_stats()->Result<()> {
+    let mut store = SentinelStore::new();
+    store.add_json_record( &anemometer( "a1", "dev", "2023-01-29T19:30:00.000Z", 350.0, 2.0))?;
+    store.add_json_record( &anemometer( "a2", "dev", "2023-01-29T19:31:00.000Z", 10.0, 4.0))?;
+    store.add_json_record( &anemometer( "a3", "dev", "2023-01-29T19:32:00.000Z", 0.0, 6.0))?;
+    store.add_json_record( &anemometer( "a0", "dev", "2023-01-29T18:00:00.000Z", 180.0, 20.0))?; // outside of window
+
+    let stats = store.get( &"dev".to_string()).unwrap().wind_stats( Duration::from_secs(600)).unwrap();
+    assert_eq!( stats.samples, 3);
+    assert!( stats.direction < 5.0 || stats.direction > 355.0); // vector averaging wraps around north
+    assert!( (stats.mean_speed - 4.0).abs() < 1e-9);
+    assert!( (stats.gust_speed - 6.0).abs() < 1e-9);
+    assert!( stats.vector_mean_speed < stats.mean_speed);
+    assert!( stats.direction_std > 5.0 && stats.direction_std < 15.0);
+    Ok(())
+}
+
+#[test]
+fn test_smoke_source()->Result<()> {
+    let mut store = SentinelStore::new();
+    store.add_json_record( &gps( "detector", 34.0, -118.0))?;
+    store.add_json_record( &gps( "station", 34.0, -117.98))?; // ~1.8km east of the detector
+    store.add_json_record( &anemometer( "a1", "station", "2023-01-29T19:30:00.000Z", 270.0, 5.0))?;
+    store.add_json_record( &anemometer( "a2", "station", "2023-01-29T19:31:00.000Z", 280.0, 5.0))?;
+    store.add_json_record( &smoke( "smoke-1", "detector", "2023-01-29T19:32:00.000Z", 0.8))?;
+
+    let config = WindConfig::default();
+    let source = smoke_source( &config, &store, &"detector".to_string(), "smoke-1").unwrap();
+    assert_eq!( source.wind.device_id, "station");
+    assert!( (source.sector.center - 275.0).abs() < 0.1); // source is upwind, i.e. west
+    assert!( source.sector.contains( 265.0));
+    assert!( !source.sector.contains( 90.0));
+    assert_eq!( source.origin, Some((34.0, -118.0)));
+    assert_eq!( source.bearings.len(), 1);
+    assert!( source.location.is_none()); // nobody else saw smoke
+
+    // no wind within reach
+    let config = WindConfig { max_wind_distance: 1000.0, ..WindConfig::default() };
+    assert!( smoke_source( &config, &store, &"detector".to_string(), "smoke-1").is_none());
+    Ok(())
+}
+
+#[test]
+fn test_smoke_source_location()->Result<()> {
+    let mut store = SentinelStore::new();
+    store.add_json_record( &gps( "detector", 34.0, -118.0))?;
+    store.add_json_record( &gps( "station", 34.0, -117.98))?;
+    store.add_json_record( &gps( "neighbor", 34.02, -118.0))?; // ~2.2km north of the detector, with its own anemometer
+    store.add_json_record( &anemometer( "a1", "station", "2023-01-29T19:30:00.000Z", 275.0, 5.0))?;
+    store.add_json_record( &anemometer( "a2", "neighbor", "2023-01-29T19:31:00.000Z", 225.0, 5.0))?;
+    store.add_json_record( &smoke( "smoke-1", "detector", "2023-01-29T19:32:00.000Z", 0.8))?;
+    store.add_json_record( &smoke( "smoke-2", "neighbor", "2023-01-29T19:31:30.000Z", 0.6))?;
+    store.add_json_record( &smoke( "smoke-3", "station", "2023-01-29T19:31:00.000Z", 0.2))?; // below threshold
+
+    let config = WindConfig::default();
+    let source = smoke_source( &config, &store, &"detector".to_string(), "smoke-1").unwrap();
+    let mut ids: Vec<&str> = source.bearings.iter().map( |b| b.device_id.as_str()).collect();
+    ids.sort();
+    assert_eq!( ids, vec!["detector", "neighbor"]);
+
+    // west bearing from the detector and south-west bearing from the neighbor intersect ~2km west of the detector
+    let (lat, lon) = source.location.unwrap();
+    assert!( lat > 34.0 && lat < 34.005);
+    assert!( lon > -118.03 && lon < -118.015);
+    assert!( source.error_ellipse.unwrap().semi_major > 0.0);
+    Ok(())
+}